@@ -0,0 +1,12 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+void main() {
+    // Keep the background dim so the white vector art stays readable.
+    f_color = vec4(texture(tex, uv).rgb * 0.25, 1.0);
+}
@@ -4,9 +4,17 @@ layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
 
 layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
 
+layout(push_constant) uniform PushConstants {
+    float time;
+} pc;
+
 void main() {
-    vec2 norm_coords = (gl_GlobalInvocationID.xy + vec2(0.5)) / vec2(imageSize(img));
-    vec2 c = (norm_coords - vec2(0.5)) * 2.0 - vec2(1.0, 0.0);
+    vec2 norm_coords = (gl_GlobalInvocationID.xy + vec2(0.5)) /
+        vec2(imageSize(img));
+
+    // Slowly breathe in and out of the set so the background is animated.
+    float zoom = 2.0 + sin(pc.time * 0.25);
+    vec2 c = (norm_coords - vec2(0.5)) * zoom - vec2(1.0, 0.0);
 
     vec2 z = vec2(0.0, 0.0);
     float i;
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D font;
+
+void main() {
+    // Both the vertex color and the font are premultiplied.
+    f_color = v_color * texture(font, v_uv);
+}
@@ -0,0 +1,23 @@
+#version 450
+
+// egui vertices, converted to clip space
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+
+// egui colors are sRGB, but blending happens in linear space.
+vec3 linear_from_srgb(vec3 srgb) {
+    bvec3 cutoff = lessThan(srgb, vec3(0.04045));
+    vec3 lower = srgb / 12.92;
+    vec3 higher = pow((srgb + 0.055) / 1.055, vec3(2.4));
+    return mix(higher, lower, cutoff);
+}
+
+void main() {
+    v_uv = uv;
+    v_color = vec4(linear_from_srgb(color.rgb), color.a);
+    gl_Position = vec4(pos, 0.0, 1.0);
+}
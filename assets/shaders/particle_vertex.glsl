@@ -0,0 +1,46 @@
+#version 450
+
+// Quad corner
+layout(location = 0) in vec2 corner;
+
+// Particle data
+layout(location = 1) in vec2 pos;
+layout(location = 2) in float life;
+layout(location = 3) in float max_life;
+
+layout(location = 0) out float alpha;
+
+// The camera. See `View` in src/renderer/camera.rs.
+layout(push_constant) uniform View {
+    vec2 pos;
+    float zoom;
+    float angle;
+} view;
+
+mat2 rotation(in float angle) {
+    return mat2(
+        cos(angle), -sin(angle),
+        sin(angle),  cos(angle)
+    );
+}
+
+// World space to clip space through the camera. Offsets from an entity's
+// position are aspect corrected before they are added, so undo that while
+// rotating.
+vec2 to_clip(vec2 world) {
+    vec2 stretch = vec2(1.0, 1920.0/1080.0);
+    vec2 rel = rotation(-radians(view.angle)) * ((world - view.pos) / stretch);
+    return rel * view.zoom * stretch;
+}
+
+void main() {
+    vec2 stretch = vec2(1.0, 1920.0/1080.0);
+
+    // Dead particles collapse to a point and are not rasterized.
+    float size = life > 0.0 ? 0.006 : 0.0;
+    alpha = life > 0.0 ? life / max_life : 0.0;
+
+    // The depth of layer -1, see `layer_depth` in src/renderer.rs
+    float depth = 0.5 + 1.0 / 256.0;
+    gl_Position = vec4(to_clip(corner * size * stretch + pos), depth, 1.0);
+}
@@ -0,0 +1,52 @@
+#version 450
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+struct Particle {
+    vec2 pos;
+    vec2 vel;
+    float life;
+    float max_life;
+};
+
+layout(set = 0, binding = 0) buffer Data {
+    Particle data[];
+} buf;
+
+// Keep in sync with `step` in src/renderer/particles.rs, which is the CPU
+// version of this shader.
+const float DRAG = 0.97;
+
+// Half the width of the world, which spans -half_size..half_size.
+layout(push_constant) uniform World {
+    float half_size;
+} world;
+
+void main() {
+    uint idx = gl_GlobalInvocationID.x;
+    Particle p = buf.data[idx];
+
+    if (p.life <= 0.0) {
+        return;
+    }
+
+    p.pos += p.vel;
+    p.vel *= DRAG;
+    p.life -= 1.0;
+
+    // Wrap around the edges like every other entity.
+    float h = world.half_size;
+    if (p.pos.x > h) {
+        p.pos.x -= 2.0 * h;
+    } else if (p.pos.x < -h) {
+        p.pos.x += 2.0 * h;
+    }
+
+    if (p.pos.y > h) {
+        p.pos.y -= 2.0 * h;
+    } else if (p.pos.y < -h) {
+        p.pos.y += 2.0 * h;
+    }
+
+    buf.data[idx] = p;
+}
@@ -0,0 +1,50 @@
+#version 450
+
+// Quad corner
+layout(location = 0) in vec2 corner;
+
+// Instance data
+layout(location = 1) in vec2 pos_offset;
+layout(location = 2) in float angle;
+layout(location = 3) in float scale;
+layout(location = 4) in vec4 uv_rect;
+// From the instance's layer, see `layer_depth` in src/renderer.rs
+layout(location = 5) in float depth;
+layout(location = 6) in vec4 color;
+
+layout(location = 0) out vec2 uv;
+layout(location = 1) out vec4 tint;
+
+// The camera. See `View` in src/renderer/camera.rs.
+layout(push_constant) uniform View {
+    vec2 pos;
+    float zoom;
+    float angle;
+} view;
+
+mat2 rotation(in float angle) {
+    return mat2(
+        cos(angle), -sin(angle),
+        sin(angle),  cos(angle)
+    );
+}
+
+// World space to clip space through the camera. Offsets from an entity's
+// position are aspect corrected before they are added, so undo that while
+// rotating.
+vec2 to_clip(vec2 world) {
+    vec2 stretch = vec2(1.0, 1920.0/1080.0);
+    vec2 rel = rotation(-radians(view.angle)) * ((world - view.pos) / stretch);
+    return rel * view.zoom * stretch;
+}
+
+void main() {
+    // The rect is (min u, min v, max u, max v) in the atlas.
+    uv = mix(uv_rect.xy, uv_rect.zw, corner * 0.5 + 0.5);
+
+    vec2 stretch = vec2(1.0, 1920.0/1080.0);
+    vec2 vertex = rotation(radians(angle)) * corner * scale *
+        stretch + pos_offset;
+    gl_Position = vec4(to_clip(vertex), depth, 1.0);
+    tint = color;
+}
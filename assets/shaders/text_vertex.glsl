@@ -0,0 +1,16 @@
+#version 450
+
+// Position of the string in clip space
+layout(location = 0) in vec2 origin;
+
+// Position of the vertex relative to the origin, before the aspect correction
+layout(location = 1) in vec2 offset;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+void main() {
+    vec2 stretch = vec2(1.0, 1920.0/1080.0);
+    v_color = color;
+    gl_Position = vec4(offset * stretch + origin, 0.0, 1.0);
+}
@@ -0,0 +1,53 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use vulkano_test::broadphase::Broadphase;
+
+// Half the width of the world when the camera follows the ship.
+const HALF: f32 = 3.0;
+const RADIUS: f32 = 0.02;
+
+fn asteroids(count: usize) -> Vec<([f32; 2], f32)> {
+    let mut rng = StdRng::seed_from_u64(count as u64);
+    (0..count)
+        .map(|_| {
+            let pos = [rng.gen_range(-HALF, HALF), rng.gen_range(-HALF, HALF)];
+            (pos, rng.gen_range(RADIUS * 0.5, RADIUS * 1.5))
+        })
+        .collect()
+}
+
+// Every pair, for comparison.
+fn all_pairs(circles: &[([f32; 2], f32)], pairs: &mut Vec<(usize, usize)>) {
+    pairs.clear();
+    for i in 0..circles.len() {
+        for j in i + 1..circles.len() {
+            pairs.push((i, j));
+        }
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pairs");
+
+    for &count in [100, 1_000, 10_000].iter() {
+        let circles = asteroids(count);
+        let mut pairs = Vec::new();
+
+        let id = BenchmarkId::new("spatial_hash", count);
+        group.bench_with_input(id, &circles, |b, circles| {
+            let mut broadphase = Broadphase::new();
+            b.iter(|| broadphase.pairs(circles, HALF, &mut pairs));
+        });
+
+        let id = BenchmarkId::new("all_pairs", count);
+        group.bench_with_input(id, &circles, |b, circles| {
+            b.iter(|| all_pairs(circles, &mut pairs));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -6,6 +6,8 @@ pkgs.mkShell {
     cargo
     cmake
     python3
+    pkg-config
+    alsa-lib
   ];
 
   APPEND_LIBRARY_PATH = stdenv.lib.makeLibraryPath [
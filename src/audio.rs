@@ -0,0 +1,265 @@
+use rodio::{Decoder, OutputStream, Source};
+
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::Path,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc,
+    },
+};
+
+use crate::renderer::asset_dir;
+
+mod mixer;
+use mixer::{Command, Mixer};
+
+pub mod synth;
+use synth::Preset;
+
+// How long switching music tracks takes, in seconds.
+const MUSIC_FADE: f32 = 2.0;
+
+// Seeds the noise of synthesized effects, so they sound the same every run.
+const SYNTH_SEED: u32 = 1;
+
+/// Mono samples, shared between everything playing them.
+#[derive(Clone)]
+pub struct Sound {
+    samples: Arc<[f32]>,
+    rate: u32,
+}
+
+impl Sound {
+    /// Decodes a WAV file, mixing it down to mono.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| e.to_string())?;
+        let decoder = Decoder::new(BufReader::new(file))
+            .map_err(|e| e.to_string())?;
+
+        let channels = decoder.channels() as usize;
+        let rate = decoder.sample_rate();
+        let interleaved: Vec<f32> = decoder.convert_samples().collect();
+        let samples: Vec<f32> = interleaved
+            .chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        Ok(Sound { samples: samples.into(), rate })
+    }
+
+    /// Reads an sfxr preset from a JSON file and synthesizes it.
+    pub fn synthesize(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let preset = Preset::parse(&json)?;
+
+        Ok(Sound {
+            samples: synth::render(&preset, SYNTH_SEED).into(),
+            rate: synth::RATE,
+        })
+    }
+}
+
+/// The sound effects the game plays, synthesized from the presets in
+/// `assets/sounds`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Sfx {
+    Thrust,
+    Fire,
+    Explosion,
+    Death,
+    ThumpLow,
+    ThumpHigh,
+}
+
+impl Sfx {
+    const ALL: [Sfx; 6] = [
+        Sfx::Thrust,
+        Sfx::Fire,
+        Sfx::Explosion,
+        Sfx::Death,
+        Sfx::ThumpLow,
+        Sfx::ThumpHigh,
+    ];
+
+    fn file(self) -> &'static str {
+        match self {
+            Sfx::Thrust => "thrust.json",
+            Sfx::Fire => "laser.json",
+            Sfx::Explosion => "explosion.json",
+            Sfx::Death => "death.json",
+            Sfx::ThumpLow => "thump_low.json",
+            Sfx::ThumpHigh => "thump_high.json",
+        }
+    }
+}
+
+/// Loudness of each bus, from 0 to 1. The master volume scales both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Volumes {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+}
+
+impl Default for Volumes {
+    fn default() -> Self {
+        Volumes { master: 0.8, sfx: 1.0, music: 0.5 }
+    }
+}
+
+enum Backend {
+    // Plays the mixer until the stream is dropped.
+    Rodio { _stream: OutputStream },
+    // No output device. Nothing receives the commands, so nothing is mixed.
+    Null,
+}
+
+/// Sound effects and music. Without an audio device everything still works
+/// but is silent, so the game runs anywhere.
+pub struct Audio {
+    _backend: Backend,
+    commands: Sender<Command>,
+    // Indexed by `Sfx`, `None` when the file failed to load.
+    sounds: Vec<Option<Sound>>,
+    tracks: Vec<Sound>,
+    track: usize,
+    // Loops that are playing.
+    loops: Vec<Sfx>,
+    pub volumes: Volumes,
+    applied: Volumes,
+}
+
+fn load_sounds() -> Vec<Option<Sound>> {
+    Sfx::ALL
+        .iter()
+        .map(|sfx| {
+            let path = asset_dir().join("sounds").join(sfx.file());
+            Sound::synthesize(&path)
+                .map_err(|e| println!("Failed to load {:?}: {}", path, e))
+                .ok()
+        })
+        .collect()
+}
+
+// Every WAV file in `assets/music`, in name order.
+fn load_tracks() -> Vec<Sound> {
+    let dir = asset_dir().join("music");
+    let mut paths: Vec<_> = match fs::read_dir(&dir) {
+        Ok(entries) => entries.filter_map(|e| Some(e.ok()?.path())).collect(),
+        Err(e) => {
+            println!("Failed to read {:?}: {}", dir, e);
+            return Vec::new();
+        }
+    };
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "wav"));
+    paths.sort();
+
+    paths
+        .iter()
+        .filter_map(|path| {
+            Sound::load(path)
+                .map_err(|e| println!("Failed to load {:?}: {}", path, e))
+                .ok()
+        })
+        .collect()
+}
+
+impl Audio {
+    /// Opens the default output device, falling back to the null backend if
+    /// there is none.
+    pub fn new() -> Self {
+        let volumes = Volumes::default();
+        let (commands, rx) = channel();
+
+        let backend = match OutputStream::try_default() {
+            Ok((stream, handle)) => {
+                match handle.play_raw(Mixer::new(rx, volumes)) {
+                    Ok(()) => Backend::Rodio { _stream: stream },
+                    Err(e) => {
+                        println!("Failed to start audio: {}", e);
+                        Backend::Null
+                    }
+                }
+            }
+            Err(e) => {
+                println!("No audio output, playing silently: {}", e);
+                Backend::Null
+            }
+        };
+
+        Audio {
+            _backend: backend,
+            commands,
+            sounds: load_sounds(),
+            tracks: load_tracks(),
+            track: 0,
+            loops: Vec::new(),
+            volumes,
+            applied: volumes,
+        }
+    }
+
+    // The null backend dropped the receiver, so sending fails and that is
+    // fine.
+    fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    fn sound(&self, sfx: Sfx) -> Option<Sound> {
+        self.sounds[sfx as usize].clone()
+    }
+
+    /// Sends volume changes to the mixer. Call once a frame.
+    pub fn update(&mut self) {
+        if self.volumes != self.applied {
+            self.applied = self.volumes;
+            self.send(Command::Volumes(self.volumes));
+        }
+    }
+
+    /// Plays `sfx` once, panned from -1 (left) to 1 (right).
+    pub fn play(&self, sfx: Sfx, pan: f32) {
+        if let Some(sound) = self.sound(sfx) {
+            self.send(Command::Play { sound, volume: 1.0, pan });
+        }
+    }
+
+    /// Starts or stops looping `sfx`, and moves it to `pan` while it plays.
+    /// Meant to be called every frame with whether it should be heard.
+    pub fn looping(&mut self, sfx: Sfx, playing: bool, pan: f32) {
+        let was_playing = self.loops.contains(&sfx);
+
+        if playing && !was_playing {
+            let sound = match self.sound(sfx) {
+                Some(sound) => sound,
+                None => return,
+            };
+            self.loops.push(sfx);
+            self.send(Command::StartLoop { sfx, sound });
+        } else if !playing && was_playing {
+            self.loops.retain(|&s| s != sfx);
+            self.send(Command::StopLoop(sfx));
+        }
+
+        if playing {
+            self.send(Command::SetLoop { sfx, volume: 1.0, pan });
+        }
+    }
+
+    /// Crossfades to music track `index`, wrapping around.
+    pub fn play_music(&mut self, index: usize) {
+        if self.tracks.is_empty() {
+            return;
+        }
+
+        self.track = index % self.tracks.len();
+        let sound = self.tracks[self.track].clone();
+        self.send(Command::Music { sound, fade: MUSIC_FADE });
+    }
+
+    pub fn next_track(&mut self) {
+        self.play_music(self.track + 1);
+    }
+}
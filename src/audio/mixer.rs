@@ -0,0 +1,361 @@
+use rodio::Source;
+
+use std::{sync::mpsc::Receiver, time::Duration};
+
+// The output is at the rate effects are synthesized at. Sounds at other
+// rates are resampled as they are mixed.
+use super::{synth::RATE, Sfx, Sound, Volumes};
+
+// Frames mixed at a time. Commands take effect between blocks, and gain
+// changes are ramped across one to avoid clicks.
+const BLOCK: usize = 256;
+
+// How long loops take to fade in and out, in seconds.
+const LOOP_FADE: f32 = 0.02;
+
+/// What the game asks of the mixer, sent from the window thread.
+pub enum Command {
+    Play { sound: Sound, volume: f32, pan: f32 },
+    StartLoop { sfx: Sfx, sound: Sound },
+    SetLoop { sfx: Sfx, volume: f32, pan: f32 },
+    StopLoop(Sfx),
+    /// Fades out the music that is playing while `sound` fades in, over
+    /// `fade` seconds.
+    Music { sound: Sound, fade: f32 },
+    Volumes(Volumes),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bus {
+    Sfx,
+    Music,
+}
+
+struct Voice {
+    sound: Sound,
+    bus: Bus,
+    // Set while the voice is a loop the game can still control.
+    looping: Option<Sfx>,
+    repeat: bool,
+    // Position in the sound's samples, fractional when resampling.
+    pos: f64,
+    volume: f32,
+    pan: f32,
+    // The gains reached at the end of the last block.
+    gains: [f32; 2],
+    // Fade level from 0 to 1 and how much it changes per frame. The voice
+    // is dropped once it has faded out.
+    fade: f32,
+    fade_step: f32,
+}
+
+impl Voice {
+    fn new(sound: Sound, bus: Bus, volume: f32, pan: f32) -> Self {
+        Voice {
+            sound,
+            bus,
+            looping: None,
+            repeat: false,
+            pos: 0.0,
+            volume,
+            pan,
+            gains: [0.0, 0.0],
+            fade: 1.0,
+            fade_step: 0.0,
+        }
+    }
+
+    fn fade_in(mut self, seconds: f32) -> Self {
+        self.fade = 0.0;
+        self.fade_step = 1.0 / (seconds * RATE as f32).max(1.0);
+        self
+    }
+
+    fn fade_out(&mut self, seconds: f32) {
+        self.fade_step = -1.0 / (seconds * RATE as f32).max(1.0);
+    }
+
+    // Repeating voices only end by fading out. An empty sound has nothing
+    // to play, even on repeat.
+    fn done(&self) -> bool {
+        let len = self.sound.samples.len() as f64;
+        (self.pos >= len && !self.repeat)
+            || len == 0.0
+            || (self.fade_step < 0.0 && self.fade <= 0.0)
+    }
+}
+
+// Equal power panning, so sounds keep their loudness as they move across.
+fn pan_gains(pan: f32) -> [f32; 2] {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+    [angle.cos(), angle.sin()]
+}
+
+/// Mixes every playing sound into one stereo stream. It does not care where
+/// the stream goes, the rodio backend plays it as a `Source`.
+pub struct Mixer {
+    commands: Receiver<Command>,
+    voices: Vec<Voice>,
+    volumes: Volumes,
+    // Interleaved stereo samples of the current block, and how many of them
+    // were handed out.
+    block: Vec<f32>,
+    cursor: usize,
+}
+
+impl Mixer {
+    pub fn new(commands: Receiver<Command>, volumes: Volumes) -> Self {
+        Mixer {
+            commands,
+            voices: Vec::new(),
+            volumes,
+            block: Vec::new(),
+            cursor: 0,
+        }
+    }
+
+    fn apply(&mut self, command: Command) {
+        match command {
+            Command::Play { sound, volume, pan } => {
+                self.voices.push(Voice::new(sound, Bus::Sfx, volume, pan));
+            }
+            Command::StartLoop { sfx, sound } => {
+                let mut voice = Voice::new(sound, Bus::Sfx, 0.0, 0.0)
+                    .fade_in(LOOP_FADE);
+                voice.looping = Some(sfx);
+                voice.repeat = true;
+                self.voices.push(voice);
+            }
+            Command::SetLoop { sfx, volume, pan } => {
+                for voice in self.voices.iter_mut() {
+                    if voice.looping == Some(sfx) {
+                        voice.volume = volume;
+                        voice.pan = pan;
+                    }
+                }
+            }
+            Command::StopLoop(sfx) => {
+                for voice in self.voices.iter_mut() {
+                    if voice.looping == Some(sfx) {
+                        voice.looping = None;
+                        voice.fade_out(LOOP_FADE);
+                    }
+                }
+            }
+            Command::Music { sound, fade } => {
+                for voice in self.voices.iter_mut() {
+                    if voice.bus == Bus::Music {
+                        voice.fade_out(fade);
+                    }
+                }
+                let mut voice = Voice::new(sound, Bus::Music, 1.0, 0.0)
+                    .fade_in(fade);
+                voice.repeat = true;
+                self.voices.push(voice);
+            }
+            Command::Volumes(volumes) => self.volumes = volumes,
+        }
+    }
+
+    fn mix_block(&mut self) {
+        for command in self.commands.try_iter().collect::<Vec<_>>() {
+            self.apply(command);
+        }
+
+        self.block.clear();
+        self.block.resize(BLOCK * 2, 0.0);
+        self.cursor = 0;
+
+        let volumes = self.volumes;
+        for voice in self.voices.iter_mut() {
+            let bus = match voice.bus {
+                Bus::Sfx => volumes.sfx,
+                Bus::Music => volumes.music,
+            };
+            let [left, right] = pan_gains(voice.pan);
+            let level = voice.volume * bus * volumes.master;
+            let target = [left * level, right * level];
+
+            let samples = &voice.sound.samples;
+            let len = samples.len() as f64;
+            let step = voice.sound.rate as f64 / RATE as f64;
+
+            for (i, frame) in self.block.chunks_exact_mut(2).enumerate() {
+                if voice.pos >= len {
+                    if !voice.repeat || len == 0.0 {
+                        break;
+                    }
+                    // A step can skip past more than one repeat of a short
+                    // sound.
+                    voice.pos %= len;
+                }
+
+                // Linear interpolation between neighbouring samples, wrapping
+                // around for loops.
+                let index = voice.pos as usize;
+                let t = (voice.pos - index as f64) as f32;
+                let next = match samples.get(index + 1) {
+                    Some(&next) => next,
+                    None if voice.repeat => samples[0],
+                    None => 0.0,
+                };
+                let sample = samples[index] * (1.0 - t) + next * t;
+
+                let ramp = (i + 1) as f32 / BLOCK as f32;
+                for c in 0..2 {
+                    let change = target[c] - voice.gains[c];
+                    let gain = voice.gains[c] + change * ramp;
+                    frame[c] += sample * gain * voice.fade;
+                }
+
+                voice.fade = (voice.fade + voice.fade_step).clamp(0.0, 1.0);
+                voice.pos += step;
+            }
+
+            voice.gains = target;
+        }
+
+        self.voices.retain(|voice| !voice.done());
+    }
+}
+
+impl Iterator for Mixer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.cursor == self.block.len() {
+            self.mix_block();
+        }
+
+        let sample = self.block[self.cursor];
+        self.cursor += 1;
+        Some(sample)
+    }
+}
+
+impl Source for Mixer {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::{channel, Sender};
+
+    // Volumes that leave samples as they are, apart from panning.
+    const FULL: Volumes = Volumes { master: 1.0, sfx: 1.0, music: 1.0 };
+
+    fn sound(samples: &[f32]) -> Sound {
+        Sound { samples: samples.into(), rate: RATE }
+    }
+
+    // A mixer that isn't played by anything, with its command sender.
+    fn mixer() -> (Mixer, Sender<Command>) {
+        let (commands, rx) = channel();
+        (Mixer::new(rx, FULL), commands)
+    }
+
+    // The next block's left channel. The first block ramps the gains up
+    // from silence.
+    fn left(mixer: &mut Mixer) -> Vec<f32> {
+        mixer.take(BLOCK * 2).step_by(2).collect()
+    }
+
+    fn play(commands: &Sender<Command>, sound: Sound, volume: f32) {
+        let pan = -1.0;
+        commands.send(Command::Play { sound, volume, pan }).unwrap();
+    }
+
+    #[test]
+    fn mixes_voices_together() {
+        let (mut mixer, commands) = mixer();
+        play(&commands, sound(&[0.25; BLOCK * 4]), 1.0);
+        play(&commands, sound(&[0.5; BLOCK * 4]), 1.0);
+        left(&mut mixer);
+
+        for sample in left(&mut mixer) {
+            assert!((sample - 0.75).abs() < 1e-5, "{}", sample);
+        }
+    }
+
+    #[test]
+    fn scales_by_volume() {
+        let (mut mixer, commands) = mixer();
+        play(&commands, sound(&[1.0; BLOCK * 4]), 0.5);
+        let volumes = Volumes { master: 0.5, ..FULL };
+        commands.send(Command::Volumes(volumes)).unwrap();
+        left(&mut mixer);
+
+        for sample in left(&mut mixer) {
+            assert!((sample - 0.25).abs() < 1e-5, "{}", sample);
+        }
+    }
+
+    #[test]
+    fn repeating_voices_wrap_around() {
+        let (mut mixer, commands) = mixer();
+        let sound = sound(&[0.0, 1.0, 0.0]);
+        let sfx = Sfx::Thrust;
+        commands.send(Command::StartLoop { sfx, sound }).unwrap();
+        let set = Command::SetLoop { sfx, volume: 1.0, pan: -1.0 };
+        commands.send(set).unwrap();
+        let faded_in = (LOOP_FADE * RATE as f32) as usize / BLOCK + 1;
+        for _ in 0..faded_in {
+            left(&mut mixer);
+        }
+
+        // Faded in and at full gain, the loop keeps playing its three
+        // samples.
+        let block = left(&mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+        let [a, b, c] = [block[0], block[1], block[2]];
+        for frame in block.chunks_exact(3) {
+            assert_eq!(frame, &[a, b, c][..frame.len()]);
+        }
+        let mut frame = [a, b, c];
+        frame.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(frame, [0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn one_shots_end_and_loops_stop() {
+        let (mut mixer, commands) = mixer();
+        play(&commands, sound(&[1.0; 10]), 1.0);
+        let sound = sound(&[1.0; 10]);
+        commands.send(Command::StartLoop { sfx: Sfx::Thrust, sound }).unwrap();
+        left(&mut mixer);
+        assert_eq!(mixer.voices.len(), 1);
+
+        commands.send(Command::StopLoop(Sfx::Thrust)).unwrap();
+        left(&mut mixer);
+        left(&mut mixer);
+        assert!(mixer.voices.is_empty());
+        assert!(left(&mut mixer).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn empty_sounds_are_skipped() {
+        let (mut mixer, commands) = mixer();
+        play(&commands, sound(&[]), 1.0);
+        let sound = sound(&[]);
+        commands.send(Command::StartLoop { sfx: Sfx::Thrust, sound }).unwrap();
+
+        assert!(left(&mut mixer).iter().all(|&sample| sample == 0.0));
+        assert!(mixer.voices.is_empty());
+    }
+}
@@ -0,0 +1,497 @@
+use serde::{Deserialize, Serialize};
+
+/// The rate sounds are synthesized at.
+pub const RATE: u32 = 44100;
+
+// Every output sample averages this many oscillator steps.
+const SUPERSAMPLE: u32 = 8;
+
+// sfxr's overall gain, applied before the preset's volume.
+const MASTER_VOLUME: f32 = 0.05;
+
+const PHASER_LEN: usize = 1024;
+const NOISE_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Wave {
+    Square,
+    Sawtooth,
+    Sine,
+    Noise,
+}
+
+/// The parameters of an sfxr sound. Most range from 0 to 1, ramps from -1
+/// to 1, and they map to frequencies and lengths the same way sfxr does, so
+/// presets can be designed in any sfxr clone and copied over. Missing
+/// fields take their default, which is a short square wave beep.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub wave: Wave,
+
+    // Envelope. Punch boosts the volume at the start of the sustain.
+    pub attack: f32,
+    pub sustain: f32,
+    pub punch: f32,
+    pub decay: f32,
+
+    pub base_freq: f32,
+    /// The sound stops once a falling frequency drops below this.
+    pub freq_limit: f32,
+    pub freq_ramp: f32,
+    /// Change of the frequency ramp.
+    pub freq_delta_ramp: f32,
+
+    pub vibrato_strength: f32,
+    pub vibrato_speed: f32,
+
+    /// Jumps the frequency by this much once, after `arp_speed`.
+    pub arp_mod: f32,
+    pub arp_speed: f32,
+
+    /// Square wave duty cycle.
+    pub duty: f32,
+    pub duty_ramp: f32,
+
+    /// Restarts the frequency and arpeggio sweeps this often, 0 never.
+    pub repeat_speed: f32,
+
+    pub phaser_offset: f32,
+    pub phaser_ramp: f32,
+
+    /// Low pass cutoff, 1 disables the filter.
+    pub lpf_freq: f32,
+    pub lpf_ramp: f32,
+    pub lpf_resonance: f32,
+    pub hpf_freq: f32,
+    pub hpf_ramp: f32,
+
+    pub volume: f32,
+}
+
+impl Preset {
+    /// Reads a preset from JSON. The envelope is clamped to sfxr's range,
+    /// as every sample of it is rendered and a huge one never finishes.
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let mut preset: Preset =
+            serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let envelope =
+            [&mut preset.attack, &mut preset.sustain, &mut preset.decay];
+        for stage in envelope {
+            *stage = stage.clamp(0.0, 1.0);
+        }
+        Ok(preset)
+    }
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Preset {
+            wave: Wave::Square,
+            attack: 0.0,
+            sustain: 0.3,
+            punch: 0.0,
+            decay: 0.4,
+            base_freq: 0.3,
+            freq_limit: 0.0,
+            freq_ramp: 0.0,
+            freq_delta_ramp: 0.0,
+            vibrato_strength: 0.0,
+            vibrato_speed: 0.0,
+            arp_mod: 0.0,
+            arp_speed: 0.0,
+            duty: 0.0,
+            duty_ramp: 0.0,
+            repeat_speed: 0.0,
+            phaser_offset: 0.0,
+            phaser_ramp: 0.0,
+            lpf_freq: 1.0,
+            lpf_ramp: 0.0,
+            lpf_resonance: 0.0,
+            hpf_freq: 0.0,
+            hpf_ramp: 0.0,
+            volume: 0.5,
+        }
+    }
+}
+
+// A xorshift generator for the noise wave. Rendering must not depend on
+// global state or a crate's choice of algorithm, so the same preset and
+// seed always give the same samples.
+struct Noise(u32);
+
+impl Noise {
+    // From -1 to 1.
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+// The synthesizer's state while rendering, following sfxr's `SynthSample`.
+struct Synth<'a> {
+    p: &'a Preset,
+    rng: Noise,
+
+    phase: u32,
+    fperiod: f64,
+    fmaxperiod: f64,
+    fslide: f64,
+    fdslide: f64,
+    period: u32,
+
+    square_duty: f32,
+    square_slide: f32,
+
+    env_stage: usize,
+    env_time: u32,
+    env_length: [u32; 3],
+    env_vol: f32,
+
+    fphase: f32,
+    fdphase: f32,
+    iphase: usize,
+    phaser_buffer: [f32; PHASER_LEN],
+    ipp: usize,
+
+    noise_buffer: [f32; NOISE_LEN],
+
+    fltp: f32,
+    fltdp: f32,
+    fltw: f32,
+    fltw_d: f32,
+    fltdmp: f32,
+    fltphp: f32,
+    flthp: f32,
+    flthp_d: f32,
+
+    vib_phase: f32,
+    vib_speed: f32,
+    vib_amp: f32,
+
+    rep_time: u32,
+    rep_limit: u32,
+
+    arp_time: u32,
+    arp_limit: u32,
+    arp_mod: f64,
+
+    playing: bool,
+}
+
+impl<'a> Synth<'a> {
+    fn new(p: &'a Preset, seed: u32) -> Self {
+        let mut synth = Synth {
+            p,
+            // Xorshift never leaves zero.
+            rng: Noise(seed.max(1)),
+            phase: 0,
+            fperiod: 0.0,
+            fmaxperiod: 0.0,
+            fslide: 0.0,
+            fdslide: 0.0,
+            period: 0,
+            square_duty: 0.0,
+            square_slide: 0.0,
+            env_stage: 0,
+            env_time: 0,
+            env_length: [0; 3],
+            env_vol: 0.0,
+            fphase: 0.0,
+            fdphase: 0.0,
+            iphase: 0,
+            phaser_buffer: [0.0; PHASER_LEN],
+            ipp: 0,
+            noise_buffer: [0.0; NOISE_LEN],
+            fltp: 0.0,
+            fltdp: 0.0,
+            fltw: 0.0,
+            fltw_d: 0.0,
+            fltdmp: 0.0,
+            fltphp: 0.0,
+            flthp: 0.0,
+            flthp_d: 0.0,
+            vib_phase: 0.0,
+            vib_speed: 0.0,
+            vib_amp: 0.0,
+            rep_time: 0,
+            rep_limit: 0,
+            arp_time: 0,
+            arp_limit: 0,
+            arp_mod: 0.0,
+            playing: true,
+        };
+        synth.reset(false);
+        synth
+    }
+
+    // Sets up the sweeps, and everything else too unless this is a repeat.
+    fn reset(&mut self, restart: bool) {
+        let p = self.p;
+
+        let base_freq = p.base_freq as f64;
+        let freq_limit = p.freq_limit as f64;
+        self.fperiod = 100.0 / (base_freq * base_freq + 0.001);
+        self.period = self.fperiod as u32;
+        self.fmaxperiod = 100.0 / (freq_limit * freq_limit + 0.001);
+        self.fslide = 1.0 - (p.freq_ramp as f64).powi(3) * 0.01;
+        self.fdslide = -(p.freq_delta_ramp as f64).powi(3) * 0.000001;
+        self.square_duty = 0.5 - p.duty * 0.5;
+        self.square_slide = -p.duty_ramp * 0.00005;
+
+        let arp_mod = p.arp_mod as f64;
+        self.arp_mod = if arp_mod >= 0.0 {
+            1.0 - arp_mod * arp_mod * 0.9
+        } else {
+            1.0 + arp_mod * arp_mod * 10.0
+        };
+        self.arp_time = 0;
+        self.arp_limit = if p.arp_speed == 1.0 {
+            0
+        } else {
+            ((1.0 - p.arp_speed).powi(2) * 20000.0 + 32.0) as u32
+        };
+
+        if restart {
+            return;
+        }
+
+        self.phase = 0;
+
+        self.fltp = 0.0;
+        self.fltdp = 0.0;
+        self.fltw = p.lpf_freq.powi(3) * 0.1;
+        self.fltw_d = 1.0 + p.lpf_ramp * 0.0001;
+        self.fltdmp = (5.0 / (1.0 + p.lpf_resonance.powi(2) * 20.0)
+            * (0.01 + self.fltw)).min(0.8);
+        self.fltphp = 0.0;
+        self.flthp = p.hpf_freq.powi(2) * 0.1;
+        self.flthp_d = 1.0 + p.hpf_ramp * 0.0003;
+
+        self.vib_phase = 0.0;
+        self.vib_speed = p.vibrato_speed.powi(2) * 0.01;
+        self.vib_amp = p.vibrato_strength * 0.5;
+
+        self.env_vol = 0.0;
+        self.env_stage = 0;
+        self.env_time = 0;
+        self.env_length = [
+            (p.attack.powi(2) * 100000.0) as u32,
+            (p.sustain.powi(2) * 100000.0) as u32,
+            (p.decay.powi(2) * 100000.0) as u32,
+        ];
+
+        self.fphase =
+            p.phaser_offset.powi(2) * 1020.0 * p.phaser_offset.signum();
+        self.fdphase = p.phaser_ramp.powi(2) * p.phaser_ramp.signum();
+        self.iphase = self.fphase.abs() as usize;
+        self.phaser_buffer = [0.0; PHASER_LEN];
+        self.ipp = 0;
+
+        for noise in self.noise_buffer.iter_mut() {
+            *noise = self.rng.next();
+        }
+
+        self.rep_time = 0;
+        self.rep_limit = if p.repeat_speed == 0.0 {
+            0
+        } else {
+            ((1.0 - p.repeat_speed).powi(2) * 20000.0 + 32.0) as u32
+        };
+    }
+
+    fn sample(&mut self) -> f32 {
+        let p = self.p;
+
+        self.rep_time += 1;
+        if self.rep_limit != 0 && self.rep_time >= self.rep_limit {
+            self.rep_time = 0;
+            self.reset(true);
+        }
+
+        self.arp_time += 1;
+        if self.arp_limit != 0 && self.arp_time >= self.arp_limit {
+            self.arp_limit = 0;
+            self.fperiod *= self.arp_mod;
+        }
+
+        self.fslide += self.fdslide;
+        self.fperiod *= self.fslide;
+        if self.fperiod > self.fmaxperiod {
+            self.fperiod = self.fmaxperiod;
+            if p.freq_limit > 0.0 {
+                self.playing = false;
+            }
+        }
+
+        let mut rfperiod = self.fperiod;
+        if self.vib_amp > 0.0 {
+            self.vib_phase += self.vib_speed;
+            let vib = self.vib_phase.sin() * self.vib_amp;
+            rfperiod = self.fperiod * (1.0 + vib as f64);
+        }
+        self.period = (rfperiod as u32).max(8);
+
+        self.square_duty =
+            (self.square_duty + self.square_slide).clamp(0.0, 0.5);
+
+        self.env_time += 1;
+        if self.env_time > self.env_length[self.env_stage] {
+            self.env_time = 0;
+            self.env_stage += 1;
+            if self.env_stage == 3 {
+                self.playing = false;
+                return 0.0;
+            }
+        }
+        let length = self.env_length[self.env_stage].max(1);
+        let progress = self.env_time as f32 / length as f32;
+        self.env_vol = match self.env_stage {
+            0 => progress,
+            1 => 1.0 + (1.0 - progress) * 2.0 * p.punch,
+            _ => 1.0 - progress,
+        };
+
+        self.fphase += self.fdphase;
+        self.iphase = (self.fphase.abs() as usize).min(PHASER_LEN - 1);
+
+        if self.flthp_d != 0.0 {
+            self.flthp = (self.flthp * self.flthp_d).clamp(0.00001, 0.1);
+        }
+
+        let mut total = 0.0;
+        for _ in 0..SUPERSAMPLE {
+            self.phase += 1;
+            if self.phase >= self.period {
+                self.phase %= self.period;
+                if p.wave == Wave::Noise {
+                    for noise in self.noise_buffer.iter_mut() {
+                        *noise = self.rng.next();
+                    }
+                }
+            }
+
+            let fp = self.phase as f32 / self.period as f32;
+            let mut sample = match p.wave {
+                Wave::Square => if fp < self.square_duty { 0.5 } else { -0.5 },
+                Wave::Sawtooth => 1.0 - fp * 2.0,
+                Wave::Sine => (fp * 2.0 * std::f32::consts::PI).sin(),
+                Wave::Noise => {
+                    let index =
+                        self.phase as usize * NOISE_LEN / self.period as usize;
+                    self.noise_buffer[index]
+                }
+            };
+
+            // Resonant low pass, then high pass.
+            let pp = self.fltp;
+            self.fltw = (self.fltw * self.fltw_d).clamp(0.0, 0.1);
+            if p.lpf_freq != 1.0 {
+                self.fltdp += (sample - self.fltp) * self.fltw;
+                self.fltdp -= self.fltdp * self.fltdmp;
+            } else {
+                self.fltp = sample;
+                self.fltdp = 0.0;
+            }
+            self.fltp += self.fltdp;
+
+            self.fltphp += self.fltp - pp;
+            self.fltphp -= self.fltphp * self.flthp;
+            sample = self.fltphp;
+
+            // Phaser, mixing in a delayed copy.
+            self.phaser_buffer[self.ipp % PHASER_LEN] = sample;
+            let delayed = (self.ipp + PHASER_LEN - self.iphase) % PHASER_LEN;
+            sample += self.phaser_buffer[delayed];
+            self.ipp = (self.ipp + 1) % PHASER_LEN;
+
+            total += sample * self.env_vol;
+        }
+
+        let sample =
+            total / SUPERSAMPLE as f32 * MASTER_VOLUME * 2.0 * p.volume;
+        sample.clamp(-1.0, 1.0)
+    }
+}
+
+/// Renders `preset` to mono samples at `RATE`, until its envelope ends or
+/// its frequency drops below the limit. The same preset and seed always
+/// render the same samples.
+pub fn render(preset: &Preset, seed: u32) -> Vec<f32> {
+    let mut synth = Synth::new(preset, seed);
+    let mut samples = Vec::new();
+
+    while synth.playing {
+        let sample = synth.sample();
+        if synth.playing {
+            samples.push(sample);
+        }
+    }
+
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::renderer::asset_dir;
+
+    use std::fs;
+
+    // The samples an envelope stage lasts, as sfxr counts them.
+    fn stage(length: f32) -> usize {
+        (length.powi(2) * 100000.0) as usize
+    }
+
+    #[test]
+    fn same_preset_and_seed_render_the_same() {
+        let preset = Preset { wave: Wave::Noise, ..Preset::default() };
+        assert_eq!(render(&preset, 7), render(&preset, 7));
+        assert_ne!(render(&preset, 7), render(&preset, 8));
+    }
+
+    #[test]
+    fn renders_for_as_long_as_the_envelope() {
+        let preset = Preset {
+            attack: 0.1,
+            sustain: 0.2,
+            decay: 0.3,
+            ..Preset::default()
+        };
+        let len = render(&preset, 1).len();
+
+        // The sustain and decay each start with the sample that ended the
+        // stage before.
+        assert_eq!(len, stage(0.1) + stage(0.2) + stage(0.3) + 2);
+    }
+
+    #[test]
+    fn clamps_the_envelope() {
+        let json = r#"{ "attack": 1e30, "sustain": -2, "decay": 5 }"#;
+        let preset = Preset::parse(json).unwrap();
+        let envelope = (preset.attack, preset.sustain, preset.decay);
+        assert_eq!(envelope, (1.0, 0.0, 1.0));
+        assert!(Preset::parse("{ \"attack\": \"long\" }").is_err());
+    }
+
+    #[test]
+    fn every_preset_parses() {
+        let dir = asset_dir().join("sounds");
+        let mut presets = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let json = fs::read_to_string(&path).unwrap();
+                let preset = Preset::parse(&json)
+                    .unwrap_or_else(|e| panic!("{:?}: {}", path, e));
+                assert!(!render(&preset, 1).is_empty(), "{:?} is silent", path);
+                presets += 1;
+            }
+        }
+        assert!(presets > 0);
+    }
+}
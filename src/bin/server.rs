@@ -0,0 +1,51 @@
+// Runs a game for clients to connect to with `--connect`, without a
+// window: `server PORT [PLAYERS] [versus]`.
+
+use vulkano_test::{
+    game::{self, Mode, Setup},
+    net::Server,
+};
+
+use std::{net::UdpSocket, time::Instant};
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let (port, players, mode) = match args.as_slice() {
+        [_, port, rest @ ..] => {
+            let players =
+                rest.iter().find_map(|arg| arg.parse().ok()).unwrap_or(2);
+            let versus = rest.iter().any(|arg| arg == "versus");
+            let mode = if versus { Mode::Versus } else { Mode::Coop };
+            (port, players, mode)
+        }
+        _ => {
+            println!("Usage: server PORT [PLAYERS] [versus]");
+            return;
+        }
+    };
+    let port: u16 = match port.parse() {
+        Ok(port) => port,
+        Err(e) => {
+            println!("Invalid port {}: {}", port, e);
+            return;
+        }
+    };
+
+    let socket = match UdpSocket::bind(("0.0.0.0", port)) {
+        Ok(socket) => socket,
+        Err(e) => {
+            println!("Failed to serve on port {}: {}", port, e);
+            return;
+        }
+    };
+    let st = game::new_state(None, Setup { players, mode });
+    let players = st.players.len();
+    let mut server = Server::new(st, socket, players).unwrap();
+    println!("Serving {} players on {}", players, server.local_addr().unwrap());
+
+    let mut next = Instant::now();
+    loop {
+        server.tick();
+        game::wait(&mut next);
+    }
+}
@@ -0,0 +1,191 @@
+
+// Cells per side at most, however small the circles are.
+const MAX_CELLS_PER_SIDE: usize = 1024;
+
+/// Finds pairs of circles that may overlap in a square world that wraps
+/// around at `-half..half`, so that only those need an exact test. Circles
+/// are bucketed by centre into a uniform grid with cells at least as wide
+/// as the largest circle, which means overlapping circles are always in the
+/// same or neighbouring cells, counting neighbours across the wrap.
+pub struct Broadphase {
+    // Circles sorted by cell, and where each cell's run of them starts.
+    // Cell `c` holds `entries[starts[c]..starts[c + 1]]`.
+    starts: Vec<u32>,
+    entries: Vec<u32>,
+    cells: Vec<u32>,
+}
+
+impl Broadphase {
+    pub fn new() -> Self {
+        Broadphase {
+            starts: Vec::new(),
+            entries: Vec::new(),
+            cells: Vec::new(),
+        }
+    }
+
+    /// Replaces `pairs` with candidate pairs `(i, j)`, `i < j`, of indices
+    /// into `circles`, each a centre and a radius. Every overlapping pair is
+    /// a candidate, but not every candidate overlaps.
+    pub fn pairs(
+        &mut self,
+        circles: &[([f32; 2], f32)],
+        half: f32,
+        pairs: &mut Vec<(usize, usize)>,
+    ) {
+        pairs.clear();
+        if circles.len() < 2 {
+            return;
+        }
+
+        let size = 2.0 * half;
+        let max_radius = circles.iter().map(|&(_, r)| r).fold(0.0, f32::max);
+
+        // Cells can be wider than needed, so there is no point in having
+        // many more of them than circles.
+        let by_radius = (size / (2.0 * max_radius)).floor();
+        let by_count = (circles.len() as f32).sqrt().ceil();
+        let n = (by_radius.min(by_count) as usize).clamp(1, MAX_CELLS_PER_SIDE);
+        let cell_size = size / n as f32;
+
+        let coord = |v: f32| {
+            let v = (v + half).rem_euclid(size);
+            ((v / cell_size) as usize).min(n - 1)
+        };
+
+        self.cells.clear();
+        self.cells.extend(circles.iter().map(|&([x, y], _)| {
+            (coord(y) * n + coord(x)) as u32
+        }));
+
+        // Counting sort by cell.
+        self.starts.clear();
+        self.starts.resize(n * n + 1, 0);
+        for &cell in self.cells.iter() {
+            self.starts[cell as usize + 1] += 1;
+        }
+        for c in 0..n * n {
+            self.starts[c + 1] += self.starts[c];
+        }
+        let mut next = self.starts.clone();
+        self.entries.clear();
+        self.entries.resize(circles.len(), 0);
+        for (i, &cell) in self.cells.iter().enumerate() {
+            let slot = &mut next[cell as usize];
+            self.entries[*slot as usize] = i as u32;
+            *slot += 1;
+        }
+
+        // With fewer than three cells per side, some neighbours are the same
+        // cell and must only be visited once.
+        let mut offsets: Vec<usize> = Vec::with_capacity(3);
+        for d in [n - 1, 0, 1] {
+            let d = d % n;
+            if !offsets.contains(&d) {
+                offsets.push(d);
+            }
+        }
+
+        let mut neighbours = Vec::with_capacity(9);
+        for (i, &cell) in self.cells.iter().enumerate() {
+            let (cx, cy) = (cell as usize % n, cell as usize / n);
+
+            neighbours.clear();
+            for &dy in offsets.iter() {
+                for &dx in offsets.iter() {
+                    neighbours.push((cy + dy) % n * n + (cx + dx) % n);
+                }
+            }
+
+            for &c in neighbours.iter() {
+                let run = self.starts[c] as usize..self.starts[c + 1] as usize;
+                for &j in self.entries[run].iter() {
+                    let j = j as usize;
+                    if j > i {
+                        pairs.push((i, j));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for Broadphase {
+    fn default() -> Self {
+        Broadphase::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(d: f32, half: f32) -> f32 {
+        (d + half).rem_euclid(2.0 * half) - half
+    }
+
+    fn overlapping(
+        circles: &[([f32; 2], f32)],
+        half: f32,
+    ) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for i in 0..circles.len() {
+            for j in i + 1..circles.len() {
+                let ([ax, ay], ar) = circles[i];
+                let ([bx, by], br) = circles[j];
+                let dx = wrapped(bx - ax, half);
+                let dy = wrapped(by - ay, half);
+                if dx * dx + dy * dy < (ar + br) * (ar + br) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    fn check(circles: &[([f32; 2], f32)], half: f32) {
+        let mut pairs = Vec::new();
+        Broadphase::new().pairs(circles, half, &mut pairs);
+
+        for pair in overlapping(circles, half) {
+            assert!(pairs.contains(&pair), "missed {:?}", pair);
+        }
+
+        let mut unique = pairs.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), pairs.len(), "duplicate pairs");
+    }
+
+    #[test]
+    fn finds_every_overlap() {
+        let circles: Vec<_> = (0..400)
+            .map(|i| {
+                let t = i as f32;
+                let pos = [(t * 0.61).sin() * 2.9, (t * 1.37).cos() * 2.9];
+                (pos, 0.02 + (i % 5) as f32 * 0.02)
+            })
+            .collect();
+        check(&circles, 3.0);
+    }
+
+    #[test]
+    fn finds_overlaps_across_the_wrap() {
+        let circles = [
+            ([0.98, 0.0], 0.05),
+            ([-0.98, 0.0], 0.05),
+            ([0.0, -0.99], 0.05),
+            ([0.0, 0.99], 0.05),
+            ([0.99, 0.99], 0.05),
+            ([-0.99, -0.99], 0.05),
+        ];
+        check(&circles, 1.0);
+    }
+
+    #[test]
+    fn handles_circles_as_big_as_the_world() {
+        let circles =
+            [([0.0, 0.0], 1.0), ([0.5, 0.5], 0.1), ([-0.5, 0.9], 0.1)];
+        check(&circles, 1.0);
+    }
+}
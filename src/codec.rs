@@ -0,0 +1,185 @@
+/// A compact binary encoding for sending things over the network. Numbers
+/// are little endian and have a fixed size, so a value that did not change
+/// encodes to the same bytes, which is what snapshot deltas rely on.
+pub trait Encode: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    /// The value at the start of `r`, or `None` if there isn't a valid one.
+    fn decode(r: &mut Reader) -> Option<Self>;
+}
+
+/// 64 bit FNV-1a. Unlike the standard library's hasher it is the same in
+/// every build and on every machine, so hashes can be saved or compared
+/// over the network.
+pub fn fnv(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+/// Reads encoded values from the front of a byte slice.
+pub struct Reader<'a>(pub &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    /// Everything left.
+    pub fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn read<T: Encode>(&mut self) -> Option<T> {
+        T::decode(self)
+    }
+}
+
+macro_rules! encode_numbers {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend(&self.to_le_bytes());
+                }
+
+                fn decode(r: &mut Reader) -> Option<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    bytes.copy_from_slice(r.take(std::mem::size_of::<$t>())?);
+                    Some(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+encode_numbers!(u8, u16, u32, u64, i32, f32);
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        match r.read::<u8>()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+// Sizes and indices are never anywhere near 4 billion.
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(r.read::<u32>()? as usize)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.is_some().encode(out);
+        if let Some(value) = self {
+            value.encode(out);
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        if r.read::<bool>()? {
+            Some(Some(r.read()?))
+        } else {
+            Some(None)
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.len().encode(out);
+        for value in self.iter() {
+            value.encode(out);
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        let len: usize = r.read()?;
+        // Everything sent takes at least a byte, so a corrupt length fails
+        // here rather than allocating.
+        if len > r.0.len() {
+            return None;
+        }
+        (0..len).map(|_| r.read()).collect()
+    }
+}
+
+impl<T: Encode + Copy + Default, const N: usize> Encode for [T; N] {
+    fn encode(&self, out: &mut Vec<u8>) {
+        for value in self.iter() {
+            value.encode(out);
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        let mut values = [T::default(); N];
+        for value in values.iter_mut() {
+            *value = r.read()?;
+        }
+        Some(values)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some((r.read()?, r.read()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Encode + PartialEq + std::fmt::Debug>(value: T) {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        let mut r = Reader(&out);
+        assert_eq!(r.read::<T>(), Some(value));
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn values_survive_a_round_trip() {
+        round_trip(0xbeef_u16);
+        round_trip(-12_i32);
+        round_trip(f32::MIN_POSITIVE);
+        round_trip(true);
+        round_trip(Some([1.5_f32, -2.0]));
+        round_trip(None::<u64>);
+        round_trip(vec![(3_u32, Some(7_u8)), (4, None)]);
+    }
+
+    #[test]
+    fn truncated_values_are_invalid() {
+        let mut out = Vec::new();
+        vec![1_u32, 2, 3].encode(&mut out);
+        out.pop();
+        assert_eq!(Reader(&out).read::<Vec<u32>>(), None);
+        assert_eq!(Reader(&[2]).read::<bool>(), None);
+    }
+}
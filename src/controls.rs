@@ -0,0 +1,172 @@
+use gilrs::{Axis, Button, EventType, Gilrs};
+use vulkano_test::{game::Action, scene::Nav};
+use winit::event::VirtualKeyCode as Key;
+
+// Bindings for the players sharing the keyboard, by player.
+const KEYS: [[(Key, Action); 4]; 2] = [
+    [
+        (Key::A, Action::Left),
+        (Key::F, Action::Right),
+        (Key::D, Action::Thrust),
+        (Key::S, Action::Fire),
+    ],
+    [
+        (Key::Left, Action::Left),
+        (Key::Right, Action::Right),
+        (Key::Up, Action::Thrust),
+        (Key::Down, Action::Fire),
+    ],
+];
+
+// Keys for getting around menus, which take them over from the ships while
+// a menu is showing.
+const NAV_KEYS: [(Key, Nav); 11] = [
+    (Key::Up, Nav::Up),
+    (Key::W, Nav::Up),
+    (Key::Down, Nav::Down),
+    (Key::S, Nav::Down),
+    (Key::Left, Nav::Left),
+    (Key::A, Nav::Left),
+    (Key::Right, Nav::Right),
+    (Key::D, Nav::Right),
+    (Key::Return, Nav::Select),
+    (Key::Space, Nav::Select),
+    (Key::Escape, Nav::Back),
+];
+
+// How far the stick has to be pushed to the side to turn.
+const STICK_THRESHOLD: f32 = 0.5;
+
+fn button_action(button: Button) -> Option<Action> {
+    match button {
+        Button::DPadLeft => Some(Action::Left),
+        Button::DPadRight => Some(Action::Right),
+        Button::South | Button::RightTrigger2 => Some(Action::Thrust),
+        Button::West | Button::RightTrigger => Some(Action::Fire),
+        _ => None,
+    }
+}
+
+// Buttons for getting around menus, as with `NAV_KEYS`.
+fn button_nav(button: Button) -> Option<Nav> {
+    match button {
+        Button::DPadUp => Some(Nav::Up),
+        Button::DPadDown => Some(Nav::Down),
+        Button::DPadLeft => Some(Nav::Left),
+        Button::DPadRight => Some(Nav::Right),
+        Button::South | Button::Start => Some(Nav::Select),
+        Button::East => Some(Nav::Back),
+        _ => None,
+    }
+}
+
+// Which way the stick is pushed past the threshold: -1, 0 or 1.
+fn stick_side(x: f32) -> i8 {
+    if x < -STICK_THRESHOLD {
+        -1
+    } else if x > STICK_THRESHOLD {
+        1
+    } else {
+        0
+    }
+}
+
+/// Something done on a gamepad.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pad {
+    /// A player's action started or stopped.
+    Action { player: usize, action: Action, held: bool },
+    Nav(Nav),
+}
+
+/// Maps keys and gamepads to players. The first two players share the
+/// keyboard, and gamepad `n` steers player `n` as well as their keys do.
+pub struct Controls {
+    // Gamepads are optional, the keyboard always works.
+    gilrs: Option<Gilrs>,
+    // The side each gamepad's stick was last pushed to, by player.
+    sticks: Vec<i8>,
+}
+
+impl Controls {
+    pub fn new() -> Self {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                println!("Gamepads are not available: {}", e);
+                None
+            }
+        };
+
+        Controls { gilrs, sticks: Vec::new() }
+    }
+
+    /// The player and action bound to `key`, if any.
+    pub fn key(&self, key: Key) -> Option<(usize, Action)> {
+        KEYS.iter().enumerate().find_map(|(player, keys)| {
+            keys.iter()
+                .find(|&&(bound, _)| bound == key)
+                .map(|&(_, action)| (player, action))
+        })
+    }
+
+    /// The menu navigation bound to `key`, if any.
+    pub fn nav(&self, key: Key) -> Option<Nav> {
+        NAV_KEYS.iter().find(|&&(bound, _)| bound == key).map(|&(_, nav)| nav)
+    }
+
+    /// What was done on gamepads since the last poll. As with keys, buttons
+    /// pressed while `in_menu` navigate it instead of steering, and start
+    /// pauses a game.
+    pub fn poll_pads(&mut self, in_menu: bool) -> Vec<Pad> {
+        let mut pads = Vec::new();
+        let gilrs = match &mut self.gilrs {
+            Some(gilrs) => gilrs,
+            None => return pads,
+        };
+
+        while let Some(event) = gilrs.next_event() {
+            let player: usize = event.id.into();
+            let action = |action, held| Pad::Action { player, action, held };
+            match event.event {
+                EventType::ButtonPressed(Button::Start, _) if !in_menu => {
+                    pads.push(Pad::Nav(Nav::Back));
+                }
+                EventType::ButtonPressed(button, _) => {
+                    let nav = button_nav(button).filter(|_| in_menu);
+                    match (nav, button_action(button)) {
+                        (Some(nav), _) => pads.push(Pad::Nav(nav)),
+                        (None, Some(bound)) => pads.push(action(bound, true)),
+                        (None, None) => (),
+                    }
+                }
+                // Releases always reach the ships, so nothing stays held
+                // after a menu.
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(bound) = button_action(button) {
+                        pads.push(action(bound, false));
+                    }
+                }
+                // Only crossing the threshold changes anything, so a stick
+                // at rest leaves the D-pad alone.
+                EventType::AxisChanged(Axis::LeftStickX, x, _) => {
+                    if self.sticks.len() <= player {
+                        self.sticks.resize(player + 1, 0);
+                    }
+                    let side = stick_side(x);
+                    let last =
+                        std::mem::replace(&mut self.sticks[player], side);
+                    if (last < 0) != (side < 0) {
+                        pads.push(action(Action::Left, side < 0));
+                    }
+                    if (last > 0) != (side > 0) {
+                        pads.push(action(Action::Right, side > 0));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        pads
+    }
+}
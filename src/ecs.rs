@@ -0,0 +1,472 @@
+use crate::{
+    codec::{Encode, Reader},
+    physics::Body,
+    renderer::MeshId,
+};
+
+/// A handle to an entity. Handles to despawned entities are never mistaken
+/// for whatever reuses their slot, thanks to the generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+/// One component type for every entity, indexed by entity slot.
+#[derive(Clone)]
+pub struct Storage<T> {
+    slots: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Storage<T> {
+    fn new() -> Self {
+        Storage { slots: Vec::new() }
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        match self.slots.get(entity.index as usize)? {
+            Some((generation, c)) if *generation == entity.generation => {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        match self.slots.get_mut(entity.index as usize)? {
+            Some((generation, c)) if *generation == entity.generation => {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn insert(&mut self, entity: Entity, component: T) {
+        let index = entity.index as usize;
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+        self.slots[index] = Some((entity.generation, component));
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if self.get(entity).is_some() {
+            self.slots[entity.index as usize] = None;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let (generation, c) = slot.as_ref()?;
+            Some((Entity { index: index as u32, generation: *generation }, c))
+        })
+    }
+
+    // Whether every component belongs to a living entity.
+    fn all_alive(&self, world: &World) -> bool {
+        self.iter().all(|(entity, _)| world.is_alive(entity))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.slots.iter_mut().enumerate().filter_map(|(index, slot)| {
+            let (generation, c) = slot.as_mut()?;
+            Some((Entity { index: index as u32, generation: *generation }, c))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    pub pos: [f32; 2],
+    /// Degrees, using the same convention as instance angles.
+    pub angle: f32,
+    /// Radius, in world units.
+    pub scale: f32,
+}
+
+/// Units per frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Velocity(pub [f32; 2]);
+
+/// Degrees per frame.
+#[derive(Debug, Clone, Copy)]
+pub struct AngularVelocity(pub f32);
+
+/// Wraps around the edges of the world instead of leaving it.
+#[derive(Debug, Clone, Copy)]
+pub struct Wrap;
+
+/// Drawn as an instance of the mesh, in its instance group.
+#[derive(Debug, Clone, Copy)]
+pub struct Renderable(pub MeshId);
+
+/// Colour the entity is drawn in.
+#[derive(Debug, Clone, Copy)]
+pub struct Tint(pub [f32; 4]);
+
+/// Frames until the entity is despawned.
+#[derive(Debug, Clone, Copy)]
+pub struct Lifetime(pub u32);
+
+/// Every entity and its components. Components live in one storage per
+/// type, and systems are functions over the storages they need.
+#[derive(Clone)]
+pub struct World {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+    pub transforms: Storage<Transform>,
+    pub velocities: Storage<Velocity>,
+    pub angular_velocities: Storage<AngularVelocity>,
+    pub wraps: Storage<Wrap>,
+    pub renderables: Storage<Renderable>,
+    pub tints: Storage<Tint>,
+    pub lifetimes: Storage<Lifetime>,
+    pub bodies: Storage<Body>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            generations: Vec::new(),
+            alive: Vec::new(),
+            free: Vec::new(),
+            transforms: Storage::new(),
+            velocities: Storage::new(),
+            angular_velocities: Storage::new(),
+            wraps: Storage::new(),
+            renderables: Storage::new(),
+            tints: Storage::new(),
+            lifetimes: Storage::new(),
+            bodies: Storage::new(),
+        }
+    }
+
+    /// A new entity without components.
+    pub fn spawn(&mut self) -> Entity {
+        let index = match self.free.pop() {
+            Some(index) => index,
+            None => {
+                self.generations.push(0);
+                self.alive.push(false);
+                self.generations.len() as u32 - 1
+            }
+        };
+        self.alive[index as usize] = true;
+
+        Entity { index, generation: self.generations[index as usize] }
+    }
+
+    /// Whether the entity hasn't been despawned. Handles from another world,
+    /// e.g. an older snapshot, may point past the end and are not alive.
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        let index = entity.index as usize;
+        self.alive.get(index) == Some(&true)
+            && self.generations.get(index) == Some(&entity.generation)
+    }
+
+    /// Removes the entity and all of its components.
+    pub fn despawn(&mut self, entity: Entity) {
+        if !self.is_alive(entity) {
+            return;
+        }
+
+        self.transforms.remove(entity);
+        self.velocities.remove(entity);
+        self.angular_velocities.remove(entity);
+        self.wraps.remove(entity);
+        self.renderables.remove(entity);
+        self.tints.remove(entity);
+        self.lifetimes.remove(entity);
+        self.bodies.remove(entity);
+
+        let index = entity.index as usize;
+        self.alive[index] = false;
+        self.generations[index] = self.generations[index].wrapping_add(1);
+        self.free.push(entity.index);
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
+}
+
+impl Encode for Entity {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.index.encode(out);
+        self.generation.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Entity { index: r.read()?, generation: r.read()? })
+    }
+}
+
+impl<T: Encode> Encode for Storage<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.slots.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Storage { slots: r.read()? })
+    }
+}
+
+impl Encode for Transform {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.pos.encode(out);
+        self.angle.encode(out);
+        self.scale.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Transform { pos: r.read()?, angle: r.read()?, scale: r.read()? })
+    }
+}
+
+// Single field components encode as their field.
+macro_rules! encode_newtypes {
+    ($($t:ident),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    self.0.encode(out);
+                }
+
+                fn decode(r: &mut Reader) -> Option<Self> {
+                    Some($t(r.read()?))
+                }
+            }
+        )*
+    };
+}
+
+encode_newtypes!(Velocity, AngularVelocity, Renderable, Tint, Lifetime);
+
+impl Encode for Wrap {
+    fn encode(&self, _out: &mut Vec<u8>) {}
+
+    fn decode(_r: &mut Reader) -> Option<Self> {
+        Some(Wrap)
+    }
+}
+
+// The bookkeeping goes last. It changes length as entities come and go, and
+// would shift everything after it, making deltas larger.
+impl Encode for World {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.transforms.encode(out);
+        self.velocities.encode(out);
+        self.angular_velocities.encode(out);
+        self.wraps.encode(out);
+        self.renderables.encode(out);
+        self.tints.encode(out);
+        self.lifetimes.encode(out);
+        self.bodies.encode(out);
+        self.generations.encode(out);
+        self.alive.encode(out);
+        self.free.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        let world = World {
+            transforms: r.read()?,
+            velocities: r.read()?,
+            angular_velocities: r.read()?,
+            wraps: r.read()?,
+            renderables: r.read()?,
+            tints: r.read()?,
+            lifetimes: r.read()?,
+            bodies: r.read()?,
+            generations: r.read()?,
+            alive: r.read()?,
+            free: r.read()?,
+        };
+
+        // Anything else would panic later, when entities are looked up.
+        let slots = world.generations.len();
+        if world.alive.len() != slots
+            || world.free.iter().any(|&index| index as usize >= slots)
+        {
+            return None;
+        }
+
+        // A free slot that is in use, or listed twice, would be handed out
+        // to two entities. Components of dead entities would come back to
+        // life with whatever reuses their slot.
+        let mut freed = vec![false; slots];
+        for &index in world.free.iter() {
+            let index = index as usize;
+            if world.alive[index] || freed[index] {
+                return None;
+            }
+            freed[index] = true;
+        }
+        let owned = world.transforms.all_alive(&world)
+            && world.velocities.all_alive(&world)
+            && world.angular_velocities.all_alive(&world)
+            && world.wraps.all_alive(&world)
+            && world.renderables.all_alive(&world)
+            && world.tints.all_alive(&world)
+            && world.lifetimes.all_alive(&world)
+            && world.bodies.all_alive(&world);
+        if !owned {
+            return None;
+        }
+        Some(world)
+    }
+}
+
+/// Moves everything with a velocity by `dt` ticks.
+pub fn integrate(world: &mut World, dt: f32) {
+    for (entity, Velocity(vel)) in world.velocities.iter() {
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            transform.pos[0] += vel[0] * dt;
+            transform.pos[1] += vel[1] * dt;
+        }
+    }
+
+    for (entity, AngularVelocity(spin)) in world.angular_velocities.iter() {
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            transform.angle += spin * dt;
+        }
+    }
+}
+
+/// Brings wrapping entities that left the world back in on the other side.
+/// The world spans `-half..half` on both axes.
+pub fn wrap(world: &mut World, half: f32) {
+    for (entity, Wrap) in world.wraps.iter() {
+        if let Some(transform) = world.transforms.get_mut(entity) {
+            for v in transform.pos.iter_mut() {
+                *v = crate::wrap(*v, half);
+            }
+        }
+    }
+}
+
+/// Counts lifetimes down and despawns entities whose time is up.
+pub fn expire(world: &mut World) {
+    let mut expired = Vec::new();
+
+    for (entity, Lifetime(frames)) in world.lifetimes.iter_mut() {
+        *frames = frames.saturating_sub(1);
+        if *frames == 0 {
+            expired.push(entity);
+        }
+    }
+
+    for entity in expired {
+        world.despawn(entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(pos: [f32; 2]) -> Transform {
+        Transform { pos, angle: 0.0, scale: 1.0 }
+    }
+
+    fn encoded(world: &World) -> Vec<u8> {
+        let mut out = Vec::new();
+        world.encode(&mut out);
+        out
+    }
+
+    #[test]
+    fn despawned_entities_lose_their_components() {
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.velocities.insert(entity, Velocity([1.0, 0.0]));
+        assert!(world.is_alive(entity));
+
+        world.despawn(entity);
+        assert!(!world.is_alive(entity));
+        assert!(world.velocities.get(entity).is_none());
+    }
+
+    #[test]
+    fn reused_slots_get_a_new_generation() {
+        let mut world = World::new();
+        let old = world.spawn();
+        world.transforms.insert(old, at([0.0, 0.0]));
+        world.despawn(old);
+
+        let new = world.spawn();
+        assert_eq!(new.index, old.index);
+        assert_ne!(new.generation, old.generation);
+        assert!(world.is_alive(new));
+        assert!(!world.is_alive(old));
+        assert!(world.transforms.get(old).is_none());
+
+        // Despawning the stale handle leaves the new entity alone.
+        world.transforms.insert(new, at([1.0, 1.0]));
+        world.despawn(old);
+        assert!(world.is_alive(new));
+        assert!(world.transforms.get(new).is_some());
+    }
+
+    #[test]
+    fn handles_from_bigger_worlds_are_not_alive() {
+        let mut bigger = World::new();
+        bigger.spawn();
+        let entity = bigger.spawn();
+
+        let mut world = World::new();
+        world.spawn();
+        assert!(!world.is_alive(entity));
+        world.despawn(entity);
+        assert!(world.transforms.get(entity).is_none());
+    }
+
+    #[test]
+    fn worlds_survive_a_round_trip() {
+        let mut world = World::new();
+        let gone = world.spawn();
+        let entity = world.spawn();
+        world.transforms.insert(entity, at([0.5, -0.5]));
+        world.velocities.insert(entity, Velocity([0.1, 0.2]));
+        world.wraps.insert(entity, Wrap);
+        world.lifetimes.insert(entity, Lifetime(3));
+        world.despawn(gone);
+
+        let out = encoded(&world);
+        let decoded: World = Reader(&out).read().unwrap();
+        assert_eq!(encoded(&decoded), out);
+        assert!(decoded.is_alive(entity));
+        assert!(!decoded.is_alive(gone));
+        assert_eq!(decoded.lifetimes.get(entity).map(|l| l.0), Some(3));
+
+        assert!(Reader(&out[..out.len() - 1]).read::<World>().is_none());
+    }
+
+    #[test]
+    fn inconsistent_worlds_are_rejected() {
+        let decodes = |world: &World| {
+            Reader(&encoded(world)).read::<World>().is_some()
+        };
+
+        let mut world = World::new();
+        let entity = world.spawn();
+        world.transforms.insert(entity, at([0.0, 0.0]));
+        assert!(decodes(&world));
+
+        let mut dead = world.clone();
+        dead.alive[0] = false;
+        assert!(!decodes(&dead));
+
+        let mut freed = world.clone();
+        freed.free.push(0);
+        assert!(!decodes(&freed));
+
+        let mut twice = world;
+        twice.alive[0] = false;
+        twice.transforms = Storage::new();
+        twice.free = vec![0, 0];
+        assert!(!decodes(&twice));
+    }
+}
@@ -0,0 +1,51 @@
+// Settings and tables kept on disk between runs.
+
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Replaces the file at `path` with `contents`, creating its directory if
+/// need be. The contents are written and synced to a file next to it, which
+/// is then renamed over it, so a crash leaves either the old file or the
+/// new one, never half of one.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = temp_path(path);
+    let mut out = fs::File::create(&temp)?;
+    out.write_all(contents)?;
+    out.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+// Where `path` is written before it's renamed into place.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = OsString::from(path.as_os_str());
+    temp.push(".tmp");
+    temp.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_are_replaced_whole() {
+        let dir = format!("files-test-{}", std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("nested").join("settings.json");
+
+        write_atomically(&path, b"first").unwrap();
+        write_atomically(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(temp_path(&path), dir.join("nested/settings.json.tmp"));
+        assert!(!temp_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1191 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    broadphase::Broadphase,
+    codec::{fnv, Encode, Reader},
+    ecs::{
+        self,
+        AngularVelocity,
+        Entity,
+        Lifetime,
+        Renderable,
+        Tint,
+        Transform,
+        Velocity,
+        World,
+        Wrap,
+    },
+    net,
+    physics::{self, Body},
+    renderer::{self, Camera, Emitter, MeshId, Particle, Sprite},
+    wrap,
+};
+
+/// Something a player does with their ship for as long as it is held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Left,
+    Right,
+    Thrust,
+    Fire,
+}
+
+impl Action {
+    /// The action's bit in a set of held buttons.
+    pub fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// Gameplay constants that can be tweaked from the GUI while playing.
+#[derive(Clone, PartialEq)]
+pub struct Tuning {
+    /// Degrees per frame.
+    pub rotation_speed: f32,
+    /// Acceleration per frame while thrusting.
+    pub thrust: f32,
+    /// Fraction of the ship's velocity lost per frame.
+    pub damping: f32,
+    /// The ship's top speed, per frame.
+    pub max_speed: f32,
+    pub asteroid_scale: f32,
+    /// Whether asteroids bounce off each other.
+    pub asteroid_bounce: bool,
+    /// Whether co-op players can shoot each other.
+    pub friendly_fire: bool,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            rotation_speed: 5.0,
+            thrust: 0.0005,
+            damping: 0.005,
+            max_speed: 0.03,
+            asteroid_scale: 0.1,
+            asteroid_bounce: true,
+            friendly_fire: false,
+        }
+    }
+}
+
+// Half the width of the world when the camera follows the ship. Otherwise
+// the world is exactly the screen.
+const FOLLOW_WORLD: f32 = 3.0;
+
+const SHIP_RADIUS: f32 = 0.05;
+
+/// One colour per player, and so at most this many players.
+pub const PLAYER_COLORS: [[f32; 4]; 4] = [
+    [0.4, 0.9, 1.0, 1.0],
+    [1.0, 0.6, 0.2, 1.0],
+    [0.5, 1.0, 0.4, 1.0],
+    [1.0, 0.4, 0.8, 1.0],
+];
+const LIVES: u32 = 3;
+// Frames a player waits after losing a ship, and then can't be hit for.
+const RESPAWN_DELAY: u32 = 90;
+const INVULNERABLE: u32 = 120;
+
+// Bullets leave the nose at this speed per frame on top of the ship's.
+const BULLET_SPEED: f32 = 0.02;
+const BULLET_RADIUS: f32 = 0.008;
+const BULLET_LIFETIME: u32 = 45;
+// Frames between shots while fire is held.
+const RELOAD: u32 = 10;
+
+const ASTEROID_SCORE: u32 = 10;
+// For shooting another player in versus.
+const SHIP_SCORE: u32 = 50;
+
+// Asteroids in the first wave, each wave has one more, up to the last wave.
+const ASTEROID_COUNT: usize = 6;
+const MAX_WAVE: usize = 100;
+// Asteroids start at least this far from the ships.
+const ASTEROID_CLEARANCE: f32 = 0.4;
+// Fastest starting speed per frame and spin in degrees per frame.
+const ASTEROID_SPEED: f32 = 0.004;
+const ASTEROID_SPIN: f32 = 2.0;
+// Debris thrown out when an asteroid is shot.
+const EXPLOSION_PARTICLES: usize = 40;
+// Asteroids are placed the same way every time, so snapshots match.
+const ASTEROID_SEED: u64 = 1979;
+// Effects are too, so a game looks the same every time it is played.
+const FX_SEED: u64 = 1980;
+
+// Physics steps once per tick, and game units are per tick.
+const DT: f32 = 1.0;
+// Trauma added to the camera when a ship is destroyed.
+const HIT_TRAUMA: f32 = 0.6;
+
+// Frames between beats of the background thump. Each beat comes a little
+// sooner than the last, until a ship is hit.
+const BEAT_START: u32 = 60;
+const BEAT_MIN: u32 = 20;
+
+// Indices into `MESHES`.
+pub const SHIP_MESH: MeshId = 0;
+pub const ASTEROID_MESH: MeshId = 1;
+pub const BULLET_MESH: MeshId = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// Players fight the asteroids together.
+    Coop,
+    /// Players also score by shooting each other.
+    Versus,
+}
+
+/// How a new game is played.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setup {
+    pub players: usize,
+    pub mode: Mode,
+}
+
+impl Default for Setup {
+    fn default() -> Self {
+        Setup { players: 2, mode: Mode::Coop }
+    }
+}
+
+#[derive(Clone)]
+pub struct Player {
+    // `None` while waiting to respawn, or when out of lives.
+    pub ship: Option<Entity>,
+    pub color: [f32; 4],
+    pub score: u32,
+    // Ships left, counting the one flying.
+    pub lives: u32,
+    // Actions held.
+    pub left: bool,
+    pub right: bool,
+    pub thrust: bool,
+    pub fire: bool,
+    // Frames until the next shot, the next ship and the end of the ship's
+    // invulnerability.
+    pub reload: u32,
+    pub respawn: u32,
+    pub invulnerable: u32,
+}
+
+impl Player {
+    // No ship and none to come.
+    pub fn out(&self) -> bool {
+        self.ship.is_none() && self.lives == 0
+    }
+
+    // Holds the actions whose bits are set in `buttons`.
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.left = buttons & Action::Left.bit() != 0;
+        self.right = buttons & Action::Right.bit() != 0;
+        self.thrust = buttons & Action::Thrust.bit() != 0;
+        self.fire = buttons & Action::Fire.bit() != 0;
+    }
+
+    // The bits of the actions held.
+    pub fn buttons(&self) -> u8 {
+        let held = [
+            (self.left, Action::Left),
+            (self.right, Action::Right),
+            (self.thrust, Action::Thrust),
+            (self.fire, Action::Fire),
+        ];
+        let mut buttons = 0;
+        for &(held, action) in held.iter() {
+            if held {
+                buttons |= action.bit();
+            }
+        }
+        buttons
+    }
+}
+
+#[derive(Clone)]
+pub struct Bullet {
+    pub entity: Entity,
+    // Index of the player who fired it.
+    pub owner: usize,
+}
+
+/// Everything in a game. Snapshots are published by the simulation thread
+/// for the renderer, so it is cloned every tick.
+#[derive(Clone)]
+pub struct State {
+    pub tuning: Tuning,
+    pub camera: Camera,
+    // Whether the camera follows the first ship around a larger world.
+    pub follow: bool,
+    // Half the width of the world, entities wrap at -world..world.
+    pub world: f32,
+    // Frames between beats, frames until the next one, and whether it is
+    // the high one. Beats alternate between two notes.
+    pub beat_interval: u32,
+    pub beat_timer: u32,
+    pub beat_high: bool,
+    pub mode: Mode,
+    pub entities: World,
+    pub players: Vec<Player>,
+    pub bullets: Vec<Bullet>,
+    pub asteroids: Vec<Entity>,
+    // Waves cleared, and where the next wave's asteroids come from.
+    pub wave: usize,
+    pub rng: StdRng,
+    // Where particle effects' randomness comes from, kept apart from `rng`
+    // so the effects don't change the waves.
+    pub fx_rng: StdRng,
+    // Area of each mesh, which masses are derived from.
+    pub mesh_areas: Vec<f32>,
+    pub exhaust: Emitter,
+    pub explosion: Emitter,
+    // Alternative textured look for the ships, toggled at runtime.
+    pub skin: Option<Sprite>,
+    pub textured: bool,
+    // Particles emitted since the last tick, handed to the renderer.
+    pub particles: Vec<Particle>,
+    // Events raised since the last tick, handed to the window thread.
+    pub events: Vec<GameEvent>,
+}
+
+impl net::Game for State {
+    fn advance(&mut self, buttons: &[u8], replay: bool) {
+        // Only the latest frame's are kept, for whoever runs the game to
+        // hand on.
+        self.events.clear();
+        self.particles.clear();
+
+        for (player, &buttons) in self.players.iter_mut().zip(buttons.iter()) {
+            player.set_buttons(buttons);
+        }
+        update(self);
+
+        // These were heard and seen the first time around.
+        if replay {
+            self.events.clear();
+            self.particles.clear();
+        }
+    }
+
+    // Covers what the simulation depends on, leaving out the camera and
+    // particles, which only affect how things look. Peers compare it, so
+    // it is hashed from the encoding, which is the same on every machine.
+    fn checksum(&self) -> u64 {
+        let mut out = Vec::new();
+
+        for player in self.players.iter() {
+            player.ship.encode(&mut out);
+            player.score.encode(&mut out);
+            player.lives.encode(&mut out);
+            player.reload.encode(&mut out);
+            player.respawn.encode(&mut out);
+            player.invulnerable.encode(&mut out);
+        }
+        for (entity, transform) in self.entities.transforms.iter() {
+            entity.encode(&mut out);
+            transform.encode(&mut out);
+        }
+        for (entity, velocity) in self.entities.velocities.iter() {
+            entity.encode(&mut out);
+            velocity.encode(&mut out);
+        }
+        self.wave.encode(&mut out);
+        self.beat_timer.encode(&mut out);
+        self.beat_interval.encode(&mut out);
+
+        fnv(&out)
+    }
+}
+
+// What a server sends its clients. The camera, particles and looks are the
+// client's own, and the random numbers only matter for spawning the next
+// wave, which the server does.
+impl net::Replicated for State {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.tuning.encode(out);
+        self.mode.encode(out);
+        self.world.encode(out);
+        self.beat_interval.encode(out);
+        self.beat_timer.encode(out);
+        self.beat_high.encode(out);
+        self.wave.encode(out);
+        self.players.encode(out);
+        self.entities.encode(out);
+        self.bullets.encode(out);
+        self.asteroids.encode(out);
+        self.events.encode(out);
+    }
+
+    fn decode(&mut self, encoded: &[u8]) -> bool {
+        let mut r = Reader(encoded);
+        let mut read = || {
+            self.tuning = r.read()?;
+            self.mode = r.read()?;
+            self.world = r.read()?;
+            self.beat_interval = r.read()?;
+            self.beat_timer = r.read()?;
+            self.beat_high = r.read()?;
+            self.wave = r.read()?;
+            self.players = r.read()?;
+            self.entities = r.read()?;
+            self.bullets = r.read()?;
+            self.asteroids = r.read()?;
+            self.events = r.read()?;
+            Some(())
+        };
+        read().is_some() && r.is_empty() && consistent(self)
+    }
+
+    fn buttons(&self) -> Vec<u8> {
+        self.players.iter().map(Player::buttons).collect()
+    }
+}
+
+// Whether a decoded game is one `update` can run without panicking. It
+// comes from the network, and the game indexes and unwraps on things it
+// otherwise keeps consistent itself.
+fn consistent(st: &State) -> bool {
+    let world = &st.entities;
+    let moving = |entity| {
+        world.is_alive(entity)
+            && world.transforms.get(entity).is_some()
+            && world.velocities.get(entity).is_some()
+            && world.bodies.get(entity).is_some()
+    };
+
+    // A smaller world has no room to spawn asteroids away from the ships.
+    st.world.is_finite()
+        && st.world >= 1.0
+        && st.wave <= MAX_WAVE
+        && st.players.len() <= PLAYER_COLORS.len()
+        && st.beat_interval >= BEAT_MIN
+        && st.beat_timer <= st.beat_interval
+        && st.tuning.sane()
+        && st.players.iter().all(|player| player.ship.into_iter().all(moving))
+        && st.asteroids.iter().all(|&asteroid| moving(asteroid))
+        && st.bullets.iter().all(|bullet| bullet.owner < st.players.len())
+        && world.renderables
+            .iter()
+            .all(|(_, &Renderable(mesh))| mesh < renderer::MESHES.len())
+}
+
+impl Tuning {
+    // Whether every amount is one the game can run with.
+    fn sane(&self) -> bool {
+        [
+            self.rotation_speed,
+            self.thrust,
+            self.damping,
+            self.max_speed,
+            self.asteroid_scale,
+        ]
+        .iter()
+        .all(|&amount| amount.is_finite() && amount >= 0.0)
+    }
+}
+
+impl Encode for Tuning {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.rotation_speed.encode(out);
+        self.thrust.encode(out);
+        self.damping.encode(out);
+        self.max_speed.encode(out);
+        self.asteroid_scale.encode(out);
+        self.asteroid_bounce.encode(out);
+        self.friendly_fire.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Tuning {
+            rotation_speed: r.read()?,
+            thrust: r.read()?,
+            damping: r.read()?,
+            max_speed: r.read()?,
+            asteroid_scale: r.read()?,
+            asteroid_bounce: r.read()?,
+            friendly_fire: r.read()?,
+        })
+    }
+}
+
+impl Encode for Mode {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u8).encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        match r.read::<u8>()? {
+            0 => Some(Mode::Coop),
+            1 => Some(Mode::Versus),
+            _ => None,
+        }
+    }
+}
+
+impl Encode for Player {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.ship.encode(out);
+        self.color.encode(out);
+        self.score.encode(out);
+        self.lives.encode(out);
+        self.buttons().encode(out);
+        self.reload.encode(out);
+        self.respawn.encode(out);
+        self.invulnerable.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        let mut player = Player {
+            ship: r.read()?,
+            color: r.read()?,
+            score: r.read()?,
+            lives: r.read()?,
+            left: false,
+            right: false,
+            thrust: false,
+            fire: false,
+            reload: 0,
+            respawn: 0,
+            invulnerable: 0,
+        };
+        player.set_buttons(r.read()?);
+        player.reload = r.read()?;
+        player.respawn = r.read()?;
+        player.invulnerable = r.read()?;
+        Some(player)
+    }
+}
+
+impl Encode for Bullet {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.entity.encode(out);
+        self.owner.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Bullet { entity: r.read()?, owner: r.read()? })
+    }
+}
+
+/// Something that happened in the game that the player should hear about.
+#[derive(Clone)]
+pub enum GameEvent {
+    /// A ship was destroyed at `x`.
+    Hit { x: f32 },
+    /// A bullet was fired at `x`.
+    Fire { x: f32 },
+    /// A bullet destroyed an asteroid at `x`.
+    Explosion { x: f32 },
+    /// A beat of the background thump.
+    Beat { high: bool },
+}
+
+impl Encode for GameEvent {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            GameEvent::Hit { x } => (0_u8, x).encode(out),
+            GameEvent::Fire { x } => (1_u8, x).encode(out),
+            GameEvent::Explosion { x } => (2_u8, x).encode(out),
+            GameEvent::Beat { high } => (3_u8, high).encode(out),
+        }
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        match r.read::<u8>()? {
+            0 => Some(GameEvent::Hit { x: r.read()? }),
+            1 => Some(GameEvent::Fire { x: r.read()? }),
+            2 => Some(GameEvent::Explosion { x: r.read()? }),
+            3 => Some(GameEvent::Beat { high: r.read()? }),
+            _ => None,
+        }
+    }
+}
+
+/// What to announce when the game is over, if it is.
+pub fn game_over(st: &State) -> Option<String> {
+    let left: Vec<usize> = (0..st.players.len())
+        .filter(|&i| !st.players[i].out())
+        .collect();
+
+    match left.as_slice() {
+        [] => Some("GAME OVER".to_string()),
+        &[winner] if st.mode == Mode::Versus && st.players.len() > 1 => {
+            Some(format!("P{} WINS", winner + 1))
+        }
+        _ => None,
+    }
+}
+
+// The first ship still flying, which the camera follows.
+fn first_ship(st: &State) -> Option<Transform> {
+    let ship = st.players.iter().find_map(|player| player.ship)?;
+    st.entities.transforms.get(ship).copied()
+}
+
+// Switches between a fixed camera on a one screen world and a camera that
+// follows the ship around a larger one.
+pub fn toggle_follow(st: &mut State) {
+    st.follow = !st.follow;
+    st.world = if st.follow { FOLLOW_WORLD } else { 1.0 };
+    st.camera.pos = [0.0, 0.0];
+}
+
+// Whether two entities overlap, as circles of radius `scale`, the shorter
+// way around the world.
+fn touching(st: &State, a: Entity, b: Entity) -> bool {
+    let transforms = &st.entities.transforms;
+    let (a, b) = match (transforms.get(a), transforms.get(b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return false,
+    };
+    let dx = wrap(b.pos[0] - a.pos[0], st.world);
+    let dy = wrap(b.pos[1] - a.pos[1], st.world);
+    let reach = a.scale + b.scale;
+    dx * dx + dy * dy < reach * reach
+}
+
+// For each of `entities`, the asteroids it may be touching, in the order of
+// `st.asteroids`. Only these need testing with `touching`.
+fn near_asteroids(st: &State, entities: &[Entity]) -> Vec<Vec<Entity>> {
+    // Asteroids go first, so they are the first of any mixed pair. The
+    // others are tagged with their index in `entities`.
+    let tagged = entities
+        .iter()
+        .enumerate()
+        .map(|(i, &entity)| (entity, Some(i)));
+    let all = st.asteroids
+        .iter()
+        .map(|&asteroid| (asteroid, None))
+        .chain(tagged);
+    let mut circles = Vec::new();
+    let mut tags = Vec::new();
+    for (entity, tag) in all {
+        if let Some(transform) = st.entities.transforms.get(entity) {
+            circles.push((transform.pos, transform.scale));
+            tags.push((entity, tag));
+        }
+    }
+
+    let mut pairs = Vec::new();
+    Broadphase::new().pairs(&circles, st.world, &mut pairs);
+
+    let mut near = vec![Vec::new(); entities.len()];
+    for (i, j) in pairs {
+        if let ((asteroid, None), (_, Some(k))) = (tags[i], tags[j]) {
+            near[k].push((i, asteroid));
+        }
+    }
+    near.into_iter()
+        .map(|mut asteroids| {
+            asteroids.sort_unstable_by_key(|&(i, _)| i);
+            asteroids.into_iter().map(|(_, asteroid)| asteroid).collect()
+        })
+        .collect()
+}
+
+// Turns, thrusts and fires every ship as its player asks.
+fn steer(st: &mut State) {
+    for (i, player) in st.players.iter_mut().enumerate() {
+        player.invulnerable = player.invulnerable.saturating_sub(1);
+        player.reload = player.reload.saturating_sub(1);
+
+        let ship = match player.ship {
+            Some(ship) => ship,
+            None => continue,
+        };
+
+        let spin = match (player.left, player.right) {
+            (true, false) => st.tuning.rotation_speed,
+            (false, true) => -st.tuning.rotation_speed,
+            _ => 0.0,
+        };
+        st.entities.angular_velocities.insert(ship, AngularVelocity(spin));
+
+        let transform = *st.entities.transforms.get(ship).unwrap();
+        let angle = transform.angle.to_radians();
+        // Forward is up at angle 0, and y points down.
+        let forward = [-angle.sin(), -angle.cos()];
+
+        if player.thrust {
+            let body = st.entities.bodies.get_mut(ship).unwrap();
+            let thrust = st.tuning.thrust;
+            body.accel = [forward[0] * thrust, forward[1] * thrust];
+        }
+
+        if player.fire && player.reload == 0 {
+            let [x, y] = transform.pos;
+            let nose = transform.scale;
+            let Velocity(vel) = *st.entities.velocities.get(ship).unwrap();
+            let bullet = st.entities.spawn();
+            st.entities.transforms.insert(bullet, Transform {
+                pos: [x + forward[0] * nose, y + forward[1] * nose],
+                angle: transform.angle,
+                scale: BULLET_RADIUS,
+            });
+            st.entities.velocities.insert(bullet, Velocity([
+                vel[0] + forward[0] * BULLET_SPEED,
+                vel[1] + forward[1] * BULLET_SPEED,
+            ]));
+            st.entities.wraps.insert(bullet, Wrap);
+            st.entities.renderables.insert(bullet, Renderable(BULLET_MESH));
+            st.entities.tints.insert(bullet, Tint(player.color));
+            st.entities.lifetimes.insert(bullet, Lifetime(BULLET_LIFETIME));
+            st.bullets.push(Bullet { entity: bullet, owner: i });
+            st.events.push(GameEvent::Fire { x });
+            player.reload = RELOAD;
+        }
+    }
+}
+
+// Destroys a player's ship, crediting whoever shot it in versus.
+fn kill(st: &mut State, victim: usize, by: Option<usize>) {
+    let ship = match st.players[victim].ship.take() {
+        Some(ship) => ship,
+        None => return,
+    };
+    let x = st.entities.transforms.get(ship).unwrap().pos[0];
+    st.entities.despawn(ship);
+
+    let player = &mut st.players[victim];
+    player.lives = player.lives.saturating_sub(1);
+    player.respawn = RESPAWN_DELAY;
+    if let Some(by) = by {
+        st.players[by].score += SHIP_SCORE;
+    }
+
+    st.camera.add_trauma(HIT_TRAUMA);
+    st.events.push(GameEvent::Hit { x });
+    st.beat_interval = BEAT_START;
+}
+
+// Bullets destroy the first asteroid they touch, or ships they may hit.
+fn shoot(st: &mut State) {
+    let bullets = std::mem::take(&mut st.bullets);
+    let entities: Vec<Entity> = bullets
+        .iter()
+        .map(|bullet| bullet.entity)
+        .collect();
+    let near = near_asteroids(st, &entities);
+
+    for (bullet, near) in bullets.into_iter().zip(near) {
+        if !st.entities.is_alive(bullet.entity) {
+            continue;
+        }
+
+        // Asteroids shot by an earlier bullet are no longer touching.
+        let asteroid = near
+            .into_iter()
+            .find(|&asteroid| touching(st, bullet.entity, asteroid));
+        if let Some(asteroid) = asteroid {
+            let pos = st.entities.transforms.get(asteroid).unwrap().pos;
+            let Velocity(vel) = *st.entities.velocities.get(asteroid).unwrap();
+            st.explosion.burst(
+                &mut st.fx_rng,
+                EXPLOSION_PARTICLES,
+                pos,
+                0.0,
+                vel,
+                &mut st.particles,
+            );
+            let x = pos[0];
+            st.entities.despawn(asteroid);
+            st.entities.despawn(bullet.entity);
+            st.asteroids.retain(|&other| other != asteroid);
+            st.players[bullet.owner].score += ASTEROID_SCORE;
+            st.events.push(GameEvent::Explosion { x });
+            continue;
+        }
+
+        let hits_players = st.mode == Mode::Versus || st.tuning.friendly_fire;
+        let victim = (0..st.players.len()).find(|&i| {
+            let player = &st.players[i];
+            hits_players && i != bullet.owner && player.invulnerable == 0
+                && player.ship.is_some_and(|ship| {
+                    touching(st, bullet.entity, ship)
+                })
+        });
+        if let Some(victim) = victim {
+            st.entities.despawn(bullet.entity);
+            let versus = st.mode == Mode::Versus;
+            let by = if versus { Some(bullet.owner) } else { None };
+            kill(st, victim, by);
+            continue;
+        }
+
+        st.bullets.push(bullet);
+    }
+}
+
+// Ships bounce off each other and are destroyed by asteroids.
+fn crash(st: &mut State) {
+    let ships: Vec<Entity> = st.players
+        .iter()
+        .filter_map(|player| player.ship)
+        .collect();
+    for (i, &a) in ships.iter().enumerate() {
+        for &b in ships[i + 1..].iter() {
+            physics::bounce(&mut st.entities, a, b, st.world);
+        }
+    }
+
+    let vulnerable: Vec<(usize, Entity)> = st.players
+        .iter()
+        .enumerate()
+        .filter(|(_, player)| player.invulnerable == 0)
+        .filter_map(|(i, player)| Some((i, player.ship?)))
+        .collect();
+    let ships: Vec<Entity> = vulnerable.iter().map(|&(_, ship)| ship).collect();
+    let near = near_asteroids(st, &ships);
+
+    for (&(i, ship), near) in vulnerable.iter().zip(near) {
+        if near.iter().any(|&asteroid| touching(st, ship, asteroid)) {
+            kill(st, i, None);
+        }
+    }
+}
+
+pub fn update(st: &mut State) {
+    steer(st);
+
+    physics::step(&mut st.entities, DT, st.world, st.tuning.asteroid_bounce);
+    ecs::wrap(&mut st.entities, st.world);
+    ecs::expire(&mut st.entities);
+
+    for player in st.players.iter().filter(|player| player.thrust) {
+        let ship = match player.ship {
+            Some(ship) => ship,
+            None => continue,
+        };
+        let transform = st.entities.transforms.get(ship).unwrap();
+        let Velocity(vel) = *st.entities.velocities.get(ship).unwrap();
+        let [x, y] = transform.pos;
+
+        // The exhaust leaves the back of the ship, opposite to the thrust.
+        let angle = transform.angle.to_radians();
+        st.exhaust.emit(
+            &mut st.fx_rng,
+            [x + angle.sin() * 0.05, y + angle.cos() * 0.05],
+            transform.angle,
+            vel,
+            &mut st.particles,
+        );
+    }
+
+    shoot(st);
+    crash(st);
+
+    for i in 0..st.players.len() {
+        let player = &mut st.players[i];
+        if player.ship.is_some() || player.lives == 0 {
+            continue;
+        }
+        player.respawn = player.respawn.saturating_sub(1);
+        if player.respawn == 0 {
+            spawn_ship(st, i);
+            st.players[i].invulnerable = INVULNERABLE;
+        }
+    }
+
+    if st.asteroids.is_empty() {
+        st.wave = (st.wave + 1).min(MAX_WAVE);
+        spawn_wave(st);
+    }
+
+    if st.beat_timer == 0 {
+        st.events.push(GameEvent::Beat { high: st.beat_high });
+        st.beat_high = !st.beat_high;
+        st.beat_interval = (st.beat_interval - 1).max(BEAT_MIN);
+        st.beat_timer = st.beat_interval;
+    }
+    st.beat_timer -= 1;
+
+    if st.follow {
+        if let Some(ship) = first_ship(st) {
+            st.camera.pos = ship.pos;
+        }
+    }
+    st.camera.update();
+}
+
+pub fn set_tuning(st: &mut State, tuning: Tuning) {
+    let area = st.mesh_areas[ASTEROID_MESH];
+    let mass = physics::mass(area, tuning.asteroid_scale);
+    for &asteroid in st.asteroids.iter() {
+        if let Some(transform) = st.entities.transforms.get_mut(asteroid) {
+            transform.scale = tuning.asteroid_scale;
+        }
+        if let Some(body) = st.entities.bodies.get_mut(asteroid) {
+            body.mass = mass;
+        }
+    }
+
+    for ship in st.players.iter().filter_map(|player| player.ship) {
+        let body = st.entities.bodies.get_mut(ship).unwrap();
+        body.damping = tuning.damping;
+        body.max_speed = tuning.max_speed;
+    }
+
+    st.tuning = tuning;
+}
+
+/// Game units are per frame, so the simulation ticks at the frame rate it
+/// was tuned for, whatever the renderer manages.
+pub const TICK: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Sleeps until the next tick is due. Skips ahead rather than catching up
+/// after a long stall.
+pub fn wait(next: &mut Instant) {
+    *next += TICK;
+    let now = Instant::now();
+    if *next > now {
+        thread::sleep(*next - now);
+    } else {
+        *next = now;
+    }
+}
+
+/// Shows a game run by a server in `view`: everything `t` of the way from
+/// snapshot `from` to snapshot `to`, except the `local` player's ship,
+/// which is where the client `predicted` it is now.
+pub fn blend(
+    view: &mut State,
+    from: &State,
+    to: &State,
+    t: f32,
+    predicted: &State,
+    local: usize,
+) {
+    view.tuning.clone_from(&to.tuning);
+    view.mode = to.mode;
+    view.world = to.world;
+    view.beat_interval = to.beat_interval;
+    view.beat_timer = to.beat_timer;
+    view.beat_high = to.beat_high;
+    view.wave = to.wave;
+    view.players.clone_from(&to.players);
+    view.entities.clone_from(&to.entities);
+    view.bullets.clone_from(&to.bullets);
+    view.asteroids.clone_from(&to.asteroids);
+
+    // Things that wrapped between the snapshots move the short way around.
+    for (entity, transform) in view.entities.transforms.iter_mut() {
+        if let Some(before) = from.entities.transforms.get(entity) {
+            for (v, &start) in transform.pos.iter_mut().zip(before.pos.iter()) {
+                *v = wrap(start + wrap(*v - start, view.world) * t, view.world);
+            }
+            let turned = transform.angle - before.angle;
+            transform.angle = before.angle + turned * t;
+        }
+    }
+
+    let ship = predicted.players.get(local).and_then(|player| player.ship);
+    if let Some(ship) = ship.filter(|&ship| view.entities.is_alive(ship)) {
+        let world = &mut view.entities;
+        let now = &predicted.entities;
+        if let (Some(transform), Some(now)) =
+            (world.transforms.get_mut(ship), now.transforms.get(ship))
+        {
+            *transform = *now;
+        }
+        if let (Some(vel), Some(now)) =
+            (world.velocities.get_mut(ship), now.velocities.get(ship))
+        {
+            *vel = *now;
+        }
+        if let Some(player) = view.players.get_mut(local) {
+            player.set_buttons(predicted.players[local].buttons());
+        }
+    }
+
+    if view.follow {
+        if let Some(ship) = first_ship(view) {
+            view.camera.pos = ship.pos;
+        }
+    }
+    view.camera.update();
+}
+
+// Spawns something that drifts across the world and wraps around it.
+fn spawn_body(
+    entities: &mut World,
+    mesh: MeshId,
+    transform: Transform,
+    vel: [f32; 2],
+    spin: f32,
+    body: Body,
+) -> Entity {
+    let entity = entities.spawn();
+    entities.transforms.insert(entity, transform);
+    entities.velocities.insert(entity, Velocity(vel));
+    entities.angular_velocities.insert(entity, AngularVelocity(spin));
+    entities.wraps.insert(entity, Wrap);
+    entities.renderables.insert(entity, Renderable(mesh));
+    entities.bodies.insert(entity, body);
+    entity
+}
+
+// Where each player's ships appear, spread across the lower half.
+fn spawn_point(player: usize, players: usize) -> [f32; 2] {
+    [(player as f32 + 0.5) / players as f32 - 0.5, 0.5]
+}
+
+fn spawn_ship(st: &mut State, player: usize) {
+    let scale = SHIP_RADIUS;
+    let pos = spawn_point(player, st.players.len());
+    let ship = spawn_body(
+        &mut st.entities,
+        SHIP_MESH,
+        Transform { pos, angle: 0.0, scale },
+        [0.0, 0.0],
+        0.0,
+        Body {
+            damping: st.tuning.damping,
+            max_speed: st.tuning.max_speed,
+            ..Body::new(physics::mass(st.mesh_areas[SHIP_MESH], scale))
+        },
+    );
+    st.entities.tints.insert(ship, Tint(st.players[player].color));
+    st.players[player].ship = Some(ship);
+}
+
+// Spawns the next wave of asteroids, away from the ships.
+fn spawn_wave(st: &mut State) {
+    let ships: Vec<[f32; 2]> = st.players
+        .iter()
+        .filter_map(|player| {
+            Some(st.entities.transforms.get(player.ship?)?.pos)
+        })
+        .collect();
+    let area = st.mesh_areas[ASTEROID_MESH];
+    let mass = physics::mass(area, st.tuning.asteroid_scale);
+
+    let (rng, half) = (&mut st.rng, st.world);
+    while st.asteroids.len() < ASTEROID_COUNT + st.wave {
+        let pos = [rng.gen_range(-half, half), rng.gen_range(-half, half)];
+        let crowded = ships.iter().any(|ship| {
+            let dx = wrap(pos[0] - ship[0], half);
+            let dy = wrap(pos[1] - ship[1], half);
+            dx * dx + dy * dy < ASTEROID_CLEARANCE * ASTEROID_CLEARANCE
+        });
+        if crowded {
+            continue;
+        }
+
+        let vel = [
+            rng.gen_range(-ASTEROID_SPEED, ASTEROID_SPEED),
+            rng.gen_range(-ASTEROID_SPEED, ASTEROID_SPEED),
+        ];
+        st.asteroids.push(spawn_body(
+            &mut st.entities,
+            ASTEROID_MESH,
+            Transform {
+                pos,
+                angle: rng.gen_range(0.0, 360.0),
+                scale: st.tuning.asteroid_scale,
+            },
+            vel,
+            rng.gen_range(-ASTEROID_SPIN, ASTEROID_SPIN),
+            Body { elastic: true, ..Body::new(mass) },
+        ));
+    }
+}
+
+// Starts a new game, keeping the tuning and the view.
+pub fn start(st: &mut State, setup: Setup) {
+    let players = setup.players.clamp(1, PLAYER_COLORS.len());
+
+    st.mode = setup.mode;
+    st.entities = World::new();
+    st.players = PLAYER_COLORS[..players]
+        .iter()
+        .map(|&color| Player {
+            ship: None,
+            color,
+            score: 0,
+            lives: LIVES,
+            left: false,
+            right: false,
+            thrust: false,
+            fire: false,
+            reload: 0,
+            respawn: 0,
+            invulnerable: 0,
+        })
+        .collect();
+    st.bullets.clear();
+    st.asteroids.clear();
+    st.wave = 0;
+    st.rng = StdRng::seed_from_u64(ASTEROID_SEED);
+    st.fx_rng = StdRng::seed_from_u64(FX_SEED);
+    st.beat_interval = BEAT_START;
+    st.beat_timer = 0;
+
+    for player in 0..players {
+        spawn_ship(st, player);
+    }
+    spawn_wave(st);
+}
+
+pub fn new_state(skin: Option<Sprite>, setup: Setup) -> State {
+    let mut st = State {
+        tuning: Tuning::default(),
+        camera: Camera::new(),
+        follow: false,
+        world: 1.0,
+        beat_interval: BEAT_START,
+        beat_timer: 0,
+        beat_high: false,
+        mode: setup.mode,
+        entities: World::new(),
+        players: Vec::new(),
+        bullets: Vec::new(),
+        asteroids: Vec::new(),
+        wave: 0,
+        rng: StdRng::seed_from_u64(ASTEROID_SEED),
+        fx_rng: StdRng::seed_from_u64(FX_SEED),
+        mesh_areas: renderer::mesh_areas(),
+        exhaust: Emitter::exhaust(),
+        explosion: Emitter::explosion(),
+        skin,
+        textured: false,
+        particles: Vec::new(),
+        events: Vec::new(),
+    };
+    start(&mut st, setup);
+    st
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::net::{Game, Replicated};
+
+    fn encoded(st: &State) -> Vec<u8> {
+        let mut out = Vec::new();
+        Replicated::encode(st, &mut out);
+        out
+    }
+
+    fn decodes(st: &State) -> bool {
+        new_state(None, Setup::default()).decode(&encoded(st))
+    }
+
+    #[test]
+    fn snapshots_survive_a_round_trip() {
+        let mut st = new_state(None, Setup::default());
+        st.players[0].fire = true;
+        update(&mut st);
+
+        let mut decoded = new_state(None, Setup::default());
+        assert!(decoded.decode(&encoded(&st)));
+        assert_eq!(encoded(&decoded), encoded(&st));
+        assert_eq!(decoded.checksum(), st.checksum());
+    }
+
+    #[test]
+    fn snapshots_that_would_panic_are_rejected() {
+        let st = new_state(None, Setup::default());
+        assert!(decodes(&st));
+
+        let mut bad = st.clone();
+        bad.players[0].fire = true;
+        update(&mut bad);
+        bad.bullets[0].owner = 7;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        let ship = bad.players[0].ship.unwrap();
+        bad.entities.bodies = World::new().bodies;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        bad.entities.despawn(bad.asteroids[0]);
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        let mesh = renderer::MESHES.len();
+        bad.entities.renderables.insert(ship, Renderable(mesh));
+        assert!(!decodes(&bad));
+
+        let mut bad = st;
+        bad.world = f32::NAN;
+        assert!(!decodes(&bad));
+    }
+
+    #[test]
+    fn snapshots_that_would_hang_or_underflow_are_rejected() {
+        let st = new_state(None, Setup::default());
+
+        let mut bad = st.clone();
+        bad.world = 1e-3;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        bad.beat_interval = 0;
+        bad.beat_timer = 0;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        bad.beat_timer = bad.beat_interval + 1;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        bad.tuning.thrust = f32::NAN;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        bad.tuning.max_speed = -1.0;
+        assert!(!decodes(&bad));
+
+        let mut bad = st.clone();
+        bad.tuning.asteroid_scale = f32::INFINITY;
+        assert!(!decodes(&bad));
+
+        // Clearing the wave would spawn billions of asteroids.
+        let mut bad = st.clone();
+        bad.wave = usize::MAX;
+        assert!(!decodes(&bad));
+
+        let mut bad = st;
+        let player = bad.players[0].clone();
+        bad.players.resize(PLAYER_COLORS.len() + 1, player);
+        assert!(!decodes(&bad));
+    }
+
+    #[test]
+    fn waves_stop_growing_at_the_last() {
+        let mut st = new_state(None, Setup::default());
+        st.wave = MAX_WAVE;
+        for asteroid in std::mem::take(&mut st.asteroids) {
+            st.entities.despawn(asteroid);
+        }
+        update(&mut st);
+        assert_eq!(st.wave, MAX_WAVE);
+        assert_eq!(st.asteroids.len(), ASTEROID_COUNT + MAX_WAVE);
+        assert!(decodes(&st));
+    }
+
+    #[test]
+    fn checksums_are_stable() {
+        // Peers compare checksums, so they must not change with the build.
+        // The state is built by hand, as spawning involves trigonometry
+        // that may differ between platforms.
+        let mut st = new_state(None, Setup::default());
+        st.players.truncate(1);
+        st.entities = World::new();
+        let ship = st.entities.spawn();
+        let pos = [0.5, -0.25];
+        let transform = Transform { pos, angle: 90.0, scale: 0.1 };
+        st.entities.transforms.insert(ship, transform);
+        st.entities.velocities.insert(ship, Velocity([0.01, 0.0]));
+        st.players[0].ship = Some(ship);
+        st.wave = 3;
+
+        assert_eq!(st.checksum(), 4499495440978826119);
+    }
+}
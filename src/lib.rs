@@ -0,0 +1,16 @@
+pub mod broadphase;
+pub mod codec;
+pub mod ecs;
+pub mod files;
+pub mod game;
+pub mod net;
+pub mod physics;
+pub mod renderer;
+pub mod scene;
+pub mod scores;
+pub mod triple_buffer;
+
+/// Wraps `v` into `-half..half`.
+pub fn wrap(v: f32, half: f32) -> f32 {
+    (v + half).rem_euclid(2.0 * half) - half
+}
@@ -3,131 +3,713 @@ use winit::{
         Event,
         WindowEvent,
         KeyboardInput,
+        ModifiersState,
         VirtualKeyCode as Key,
         ElementState as Keyvent,
     },
     event_loop::{ControlFlow, EventLoop},
 };
 
-mod renderer;
-use renderer::{Renderer, InstanceData};
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Instant,
+};
+
+mod audio;
+use audio::{Audio, Sfx, Volumes};
+
+mod controls;
+use controls::{Controls, Pad};
+
+use vulkano_test::{
+    ecs::{Entity, Renderable, Tint, Velocity},
+    game::{
+        self, Action, GameEvent, Mode, Setup, State, Tuning, BULLET_MESH,
+        PLAYER_COLORS, SHIP_MESH,
+    },
+    net::{self, Client, Lobby, Refused, Session, Udp},
+    renderer::{
+        self,
+        debug,
+        display,
+        profile,
+        Align,
+        Display,
+        DisplayMode,
+        InstanceData,
+        Particle,
+        Renderer,
+        Sprite,
+        MESHES,
+    },
+    scene::{Nav, Scenes},
+    scores::HighScores,
+    triple_buffer::{triple_buffer, Writer},
+    wrap,
+};
+
+// Invulnerable ships blink, hidden for half of every period.
+const BLINK_PERIOD: u32 = 16;
+// Zoom factor per key press.
+const ZOOM_STEP: f32 = 1.25;
+// Ships and bullets fly over asteroids.
+const ASTEROID_LAYER: i32 = 0;
+const SHIP_LAYER: i32 = 1;
+
+fn render(st: &State) -> Vec<Vec<InstanceData>> {
+    let mut groups = vec![Vec::new(); MESHES.len()];
+
+    let blinking: Vec<Entity> = st.players
+        .iter()
+        .filter(|player| player.invulnerable % BLINK_PERIOD >= BLINK_PERIOD / 2)
+        .filter_map(|player| player.ship)
+        .collect();
 
-enum Rot {
-    Left,
-    Right,
-    No,
+    for (entity, &Renderable(mesh)) in st.entities.renderables.iter() {
+        let transform = match st.entities.transforms.get(entity) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        if blinking.contains(&entity) {
+            continue;
+        }
+
+        let (layer, sprite) = match mesh {
+            SHIP_MESH if st.textured => (SHIP_LAYER, st.skin),
+            SHIP_MESH | BULLET_MESH => (SHIP_LAYER, None),
+            _ => (ASTEROID_LAYER, None),
+        };
+        let color = match st.entities.tints.get(entity) {
+            Some(&Tint(color)) => color,
+            None => [1.0, 1.0, 1.0, 1.0],
+        };
+
+        groups[mesh].push(InstanceData {
+            pos_offset: transform.pos,
+            angle: transform.angle,
+            scale: transform.scale,
+            layer,
+            sprite,
+            color,
+        });
+    }
+
+    groups
 }
 
-struct Asteroid {
-    x: f32,
-    y: f32,
-    vel_x: f32,
-    vel_y: f32,
-    angle: f32,
+/// Counts frames to show the frame rate, averaged over about a second.
+struct Fps {
+    since: Instant,
+    frames: u32,
+    fps: f32,
 }
 
-struct State {
-    x: f32,
-    y: f32,
-    vel_x: f32,
-    vel_y: f32,
-    accel: bool,
-    angle: f32,
-    rot: Rot,
-    asteroids: Vec<Asteroid>,
+impl Fps {
+    fn new() -> Self {
+        Fps { since: Instant::now(), frames: 0, fps: 0.0 }
+    }
+
+    fn frame(&mut self) {
+        self.frames += 1;
+
+        let elapsed = self.since.elapsed().as_secs_f32();
+        if elapsed >= 1.0 {
+            self.fps = self.frames as f32 / elapsed;
+            self.frames = 0;
+            self.since = Instant::now();
+        }
+    }
 }
 
-fn render(st: &State) -> Vec<Vec<InstanceData>> {
-    let ships = vec![
-        InstanceData {
-            pos_offset: [st.x, st.y],
-            angle: st.angle,
-            scale: 0.05,
-        },
-    ];
-
-    let mut asteroids = Vec::new();
-    for asteroid in st.asteroids.iter() {
-        asteroids.push(InstanceData {
-            pos_offset: [asteroid.x, asteroid.y],
-            angle: asteroid.angle,
-            scale: 0.1,
+const HUD_SIZE: f32 = 0.04;
+const HUD_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+
+fn hud(renderer: &mut Renderer, shown: &Shown, fps: &Fps) {
+    shown.scenes.render(&shown.st, renderer);
+    renderer.draw_text_aligned(
+        [0.95, -0.95],
+        HUD_SIZE,
+        HUD_COLOR,
+        Align::Right,
+        &format!("FPS {:.0}", fps.fps),
+    );
+}
+
+// Stereo position of something at `x`, from its offset to the camera
+// across the shorter way around the world.
+fn pan(st: &State, x: f32) -> f32 {
+    (wrap(x - st.camera.pos[0], st.world) * st.camera.zoom).clamp(-1.0, 1.0)
+}
+
+const DEBUG_COLOR: [f32; 4] = [0.0, 1.0, 0.0, 0.8];
+
+// Velocities are tiny per frame, so they are exaggerated to be visible.
+const DEBUG_VEL_SCALE: f32 = 20.0;
+
+fn debug_draw(st: &State) {
+    if !debug::enabled() {
+        return;
+    }
+
+    debug::rect([-st.world, -st.world], [st.world, st.world], DEBUG_COLOR);
+
+    for (entity, transform) in st.entities.transforms.iter() {
+        let [x, y] = transform.pos;
+        debug::circle([x, y], transform.scale, DEBUG_COLOR);
+
+        if let Some(Velocity(vel)) = st.entities.velocities.get(entity) {
+            debug::arrow(
+                [x, y],
+                [x + vel[0] * DEBUG_VEL_SCALE, y + vel[1] * DEBUG_VEL_SCALE],
+                DEBUG_COLOR,
+            );
+        }
+    }
+}
+
+// Returns whether a new game was asked for.
+fn tweak_panel(
+    renderer: &mut Renderer,
+    tuning: &mut Tuning,
+    volumes: &mut Volumes,
+    setup: &mut Setup,
+) -> bool {
+    let mut new_game = false;
+    let sample_counts = renderer.sample_counts();
+    let present_modes = renderer.present_modes();
+    let monitors = renderer
+        .window()
+        .map(display::monitors)
+        .unwrap_or_default();
+    let monitor = renderer.settings.display.monitor;
+    let resolutions = renderer
+        .window()
+        .map(|window| display::resolutions(window, monitor))
+        .unwrap_or_default();
+    let ctx = renderer.gui_frame();
+    let settings = &mut renderer.settings;
+
+    egui::Window::new("Tweaks").show(&ctx, |ui| {
+        ui.heading("Game");
+        ui.add(egui::Slider::new(&mut tuning.rotation_speed, 0.0..=20.0)
+            .text("rotation speed"));
+        ui.add(egui::Slider::new(&mut tuning.thrust, 0.0..=0.005)
+            .logarithmic(true)
+            .text("thrust"));
+        ui.add(egui::Slider::new(&mut tuning.damping, 0.0..=0.1)
+            .text("damping"));
+        ui.add(egui::Slider::new(&mut tuning.max_speed, 0.001..=0.1)
+            .logarithmic(true)
+            .text("max speed"));
+        ui.add(egui::Slider::new(&mut tuning.asteroid_scale, 0.01..=0.5)
+            .text("asteroid scale"));
+        ui.checkbox(&mut tuning.asteroid_bounce, "Asteroids bounce");
+        ui.checkbox(&mut tuning.friendly_fire, "Friendly fire");
+        if ui.button("Reset").clicked() {
+            *tuning = Tuning::default();
+        }
+
+        ui.separator();
+        ui.heading("New game");
+        ui.add(egui::Slider::new(&mut setup.players, 1..=PLAYER_COLORS.len())
+            .text("players"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut setup.mode, Mode::Coop, "Co-op");
+            ui.radio_value(&mut setup.mode, Mode::Versus, "Versus");
         });
+        if ui.button("Start").clicked() {
+            new_game = true;
+        }
+
+        ui.separator();
+        ui.heading("Audio");
+        let mut volume = |value: &mut f32, text: &str| {
+            ui.add(egui::Slider::new(value, 0.0..=1.0).text(text));
+        };
+        volume(&mut volumes.master, "master");
+        volume(&mut volumes.sfx, "effects");
+        volume(&mut volumes.music, "music");
+
+        ui.separator();
+        ui.heading("Renderer");
+        ui.horizontal(|ui| {
+            ui.label("MSAA");
+            for samples in sample_counts {
+                let text = format!("{}x", samples);
+                ui.radio_value(&mut settings.msaa, samples, text);
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Present mode");
+            for mode in present_modes {
+                let text = format!("{:?}", mode);
+                ui.radio_value(&mut settings.present_mode, mode, text);
+            }
+        });
+        ui.add(egui::Slider::new(
+            &mut settings.frames_in_flight,
+            1..=renderer::MAX_FRAMES_IN_FLIGHT,
+        ).text("frames in flight"));
+        ui.checkbox(&mut settings.parallel_recording, "Parallel recording");
+        ui.checkbox(&mut settings.depth, "Depth buffer");
+        ui.horizontal(|ui| {
+            ui.label("Clear color");
+            ui.color_edit_button_rgb(&mut settings.clear_color);
+        });
+
+        ui.separator();
+        ui.heading("Display");
+        let display = &mut settings.display;
+        ui.horizontal(|ui| {
+            let modes = [
+                (DisplayMode::Windowed, "Windowed"),
+                (DisplayMode::Borderless, "Borderless"),
+                (DisplayMode::Exclusive, "Exclusive"),
+            ];
+            for (mode, text) in modes {
+                ui.radio_value(&mut display.mode, mode, text);
+            }
+        });
+        let monitor = monitors
+            .get(display.monitor)
+            .cloned()
+            .unwrap_or_default();
+        egui::ComboBox::from_label("Monitor")
+            .selected_text(monitor)
+            .show_ui(ui, |ui| {
+                for (i, name) in monitors.iter().enumerate() {
+                    ui.selectable_value(&mut display.monitor, i, name);
+                }
+            });
+        let size = |size: Option<[u32; 2]>| match size {
+            Some([w, h]) => format!("{}x{}", w, h),
+            None => "Largest".to_string(),
+        };
+        egui::ComboBox::from_label("Resolution")
+            .selected_text(size(display.resolution))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut display.resolution, None, size(None));
+                for &resolution in resolutions.iter() {
+                    let text = size(Some(resolution));
+                    let value = Some(resolution);
+                    ui.selectable_value(&mut display.resolution, value, text);
+                }
+            });
+    });
+
+    renderer.gui.end_frame();
+    new_game
+}
+
+// Where traces are saved, in the working directory.
+const TRACE_PATH: &str = "trace.json";
+
+// Starts recording a trace, or saves the one being recorded.
+fn toggle_trace() {
+    if !profile::tracing() {
+        profile::start_trace();
+        return;
     }
 
-    vec![ships, asteroids]
+    let trace = profile::finish_trace().unwrap();
+    match std::fs::write(TRACE_PATH, trace) {
+        Ok(()) => println!("Saved trace to {}", TRACE_PATH),
+        Err(e) => println!("Failed to save {}: {}", TRACE_PATH, e),
+    }
 }
 
-fn update(st: &mut State) {
-    st.angle = match st.rot {
-        Rot::Left => st.angle + 5.0,
-        Rot::Right => st.angle - 5.0,
-        Rot::No => st.angle,
-    };
+/// Input from the window thread for the simulation.
+enum Input {
+    Action { player: usize, action: Action, held: bool },
+    Nav(Nav),
+    NewGame(Setup),
+    ToggleTextured,
+    ToggleFollow,
+    Zoom(f32),
+    Tuning(Tuning),
+}
+
+fn apply_input(st: &mut State, scenes: &mut Scenes, input: Input) {
+    match input {
+        Input::Action { player, action, held } => {
+            // Bindings exist for more players than may be playing.
+            if let Some(player) = st.players.get_mut(player) {
+                match action {
+                    Action::Left => player.left = held,
+                    Action::Right => player.right = held,
+                    Action::Thrust => player.thrust = held,
+                    Action::Fire => player.fire = held,
+                }
+            }
+        }
+        Input::Nav(nav) => scenes.input(nav, st),
+        Input::NewGame(setup) => scenes.new_game(st, setup),
+        Input::ToggleTextured => st.textured = !st.textured,
+        Input::ToggleFollow => game::toggle_follow(st),
+        Input::Zoom(factor) => st.camera.zoom *= factor,
+        Input::Tuning(tuning) => game::set_tuning(st, tuning),
+    }
+}
 
-    let angle = st.angle.to_radians();
+/// What the simulation publishes for the window thread every tick.
+#[derive(Clone)]
+struct Shown {
+    st: State,
+    scenes: Scenes,
+    // Debug shapes drawn on the simulation thread during the tick.
+    debug: Vec<debug::DebugVert>,
+}
 
-    if st.accel {
-        let delta_vel_x = angle.sin() * 0.0005;
-        let delta_vel_y = angle.cos() * 0.0005;
-        st.vel_x += delta_vel_x;
-        st.vel_y += delta_vel_y;
+// Hands what the last tick produced to the window thread. Particles and
+// game events are not state, so they are sent separately and none are lost
+// or repeated when the renderer is slower or faster than the simulation.
+// Returns false once the window thread has hung up.
+fn publish(
+    st: &mut State,
+    scenes: &Scenes,
+    particles: &Sender<Vec<Particle>>,
+    events: &Sender<Vec<GameEvent>>,
+    snapshots: &mut Writer<Shown>,
+) -> bool {
+    if !st.particles.is_empty() {
+        let spawned = std::mem::take(&mut st.particles);
+        if particles.send(spawned).is_err() {
+            return false;
+        }
+    }
+    if !st.events.is_empty() {
+        let raised = std::mem::take(&mut st.events);
+        if events.send(raised).is_err() {
+            return false;
+        }
     }
+    snapshots.publish(|snapshot| {
+        snapshot.st.clone_from(st);
+        snapshot.scenes.clone_from(scenes);
+        snapshot.debug = debug::take();
+    });
+    true
+}
 
-    // println!("angle: {}, vel_x: {}, vel_y: {}", angle, st.vel_x, st.vel_y);
+// Runs the game on its own thread until the window thread hangs up, and
+// publishes a snapshot for the renderer every tick.
+fn simulate(
+    mut st: State,
+    inputs: Receiver<Input>,
+    particles: Sender<Vec<Particle>>,
+    events: Sender<Vec<GameEvent>>,
+    mut snapshots: Writer<Shown>,
+) {
+    let setup = Setup { players: st.players.len(), mode: st.mode };
+    let mut scenes = Scenes::new(setup);
+    if let Some(path) = HighScores::path() {
+        scenes = scenes.with_scores(path);
+    }
+    let mut next = Instant::now();
+
+    loop {
+        loop {
+            match inputs.try_recv() {
+                Ok(input) => apply_input(&mut st, &mut scenes, input),
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => return,
+            }
+        }
+
+        scenes.update(&mut st);
 
-    st.x -= st.vel_x;
-    st.y -= st.vel_y;
+        if !publish(&mut st, &scenes, &particles, &events, &mut snapshots) {
+            return;
+        }
+        game::wait(&mut next);
+    }
+}
 
-    if st.x > 1.0 {
-        st.x -= 2.0;
-    } else if st.x < -1.0 {
-        st.x += 2.0;
+// Drains input from the window thread into the first player's buttons,
+// returning false once it has hung up.
+fn take_buttons(inputs: &Receiver<Input>, buttons: &mut u8) -> bool {
+    loop {
+        match inputs.try_recv() {
+            Ok(Input::Action { player: 0, action, held }) => {
+                if held {
+                    *buttons |= action.bit();
+                } else {
+                    *buttons &= !action.bit();
+                }
+            }
+            Ok(_) => (),
+            Err(std::sync::mpsc::TryRecvError::Empty) => return true,
+            Err(std::sync::mpsc::TryRecvError::Disconnected) => return false,
+        }
     }
+}
+
+/// An online game about to start, from the command line.
+struct Online {
+    transport: Udp,
+    lobby: Lobby,
+    // The host is player 0 and the guest player 1.
+    local: usize,
+}
+
+/// How an online game from the command line is played.
+enum Network {
+    Peer(Online),
+    /// As a client of a server.
+    Server(Udp),
+}
+
+// Like `simulate`, but plays against a peer over the network. Only the
+// first player's bindings steer, whichever player this peer is. Other
+// input would change the simulation on one peer only, so it is ignored.
+fn simulate_online(
+    mut st: State,
+    online: Online,
+    inputs: Receiver<Input>,
+    particles: Sender<Vec<Particle>>,
+    events: Sender<Vec<GameEvent>>,
+    mut snapshots: Writer<Shown>,
+) {
+    let Online { mut transport, mut lobby, local } = online;
+    let mut next = Instant::now();
+    let mut buttons = 0;
+    let take_input = |buttons: &mut u8| take_buttons(&inputs, buttons);
+
+    let settings = loop {
+        if !take_input(&mut buttons) {
+            return;
+        }
+        match lobby.poll(&mut transport) {
+            Ok(Some(settings)) => break settings,
+            Ok(None) => (),
+            Err(rejected) => {
+                println!(
+                    "The host runs version {} of the protocol",
+                    rejected.version,
+                );
+                return;
+            }
+        }
+        game::wait(&mut next);
+    };
+
+    let mode = match settings.as_slice() {
+        [1] => Mode::Versus,
+        _ => Mode::Coop,
+    };
+    let setup = Setup { players: net::PLAYERS, mode };
+    game::start(&mut st, setup.clone());
+    let mut session = Session::new(st, local, net::INPUT_DELAY);
+    // Neither peer can pause the other, so there are no menus.
+    let scenes = Scenes::playing(setup);
 
-    if st.y > 1.0 {
-        st.y -= 2.0;
-    } else if st.y < -1.0 {
-        st.y += 2.0;
+    loop {
+        if !take_input(&mut buttons) {
+            return;
+        }
+        let ticked = session.tick(buttons, &mut transport);
+        if let Err(net::Desync { frame }) = ticked {
+            println!("Out of sync with the other player at frame {}", frame);
+            return;
+        }
+
+        let st = session.game_mut();
+        if !publish(st, &scenes, &particles, &events, &mut snapshots) {
+            return;
+        }
+        game::wait(&mut next);
     }
+}
 
-    for asteroid in st.asteroids.iter_mut() {
-        // asteroid.angle += 3.0;
-        asteroid.x += asteroid.vel_x;
-        asteroid.y += asteroid.vel_y;
+// Like `simulate_online`, but the game runs on a server and this is one of
+// its clients. Only the first player's bindings steer, and the game on
+// screen is what the server last said, with this client's ship predicted.
+fn simulate_client(
+    mut st: State,
+    mut transport: Udp,
+    inputs: Receiver<Input>,
+    particles: Sender<Vec<Particle>>,
+    events: Sender<Vec<GameEvent>>,
+    mut snapshots: Writer<Shown>,
+) {
+    let mut client = Client::new(st.clone());
+    let setup = Setup { players: st.players.len(), mode: st.mode };
+    let scenes = Scenes::playing(setup);
+    let mut next = Instant::now();
+    let mut buttons = 0;
 
-        if asteroid.x > 1.0 {
-            asteroid.x -= 2.0;
-        } else if asteroid.x < -1.0 {
-            asteroid.x += 2.0;
+    loop {
+        if !take_buttons(&inputs, &mut buttons) {
+            return;
+        }
+        match client.tick(buttons, &mut transport) {
+            Ok(()) => (),
+            Err(Refused::Version(version)) => {
+                println!("The server runs version {} of the protocol", version);
+                return;
+            }
+            Err(Refused::Full) => {
+                println!("The server is full");
+                return;
+            }
+        }
+
+        for received in client.received() {
+            st.events.append(&mut received.events);
+        }
+        if let (Some(local), Some((from, to, t)), Some(predicted)) =
+            (client.player(), client.interpolated(), client.predicted())
+        {
+            game::blend(&mut st, from, to, t, predicted, local);
         }
 
-        if asteroid.y > 1.0 {
-            asteroid.y -= 2.0;
-        } else if asteroid.y < -1.0 {
-            asteroid.y += 2.0;
+        if !publish(&mut st, &scenes, &particles, &events, &mut snapshots) {
+            return;
         }
+        game::wait(&mut next);
+    }
+}
+
+fn load_skin(renderer: &mut Renderer) -> Option<Sprite> {
+    let skin_path = renderer::asset_dir().join("textures/skin.png");
+    match renderer.load_texture(&skin_path) {
+        Ok(atlas) => Some(atlas.full()),
+        Err(e) => {
+            println!("Failed to load {:?}: {}", skin_path, e);
+            None
+        }
+    }
+}
+
+/// Renders the first frame without a window and saves it to `path`.
+fn snapshot(path: &str) {
+    let mut renderer = Renderer::headless([1280, 720]);
+    let skin = load_skin(&mut renderer);
+    let shown = Shown {
+        st: game::new_state(skin, Setup::default()),
+        scenes: Scenes::playing(Setup::default()),
+        debug: Vec::new(),
+    };
+
+    hud(&mut renderer, &shown, &Fps::new());
+    renderer.view = shown.st.camera.view();
+    renderer.redraw(render(&shown.st));
+
+    let image = renderer.snapshot().unwrap();
+    if let Err(e) = image.save(path) {
+        println!("Failed to save {}: {}", path, e);
     }
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let online = match args.as_slice() {
+        [_, flag, path] if flag == "--snapshot" => return snapshot(path),
+        [_, flag, port, mode @ ..] if flag == "--host" => {
+            let mode = match mode {
+                [mode] if mode == "versus" => Mode::Versus,
+                _ => Mode::Coop,
+            };
+            let port: u16 = match port.parse() {
+                Ok(port) => port,
+                Err(e) => {
+                    println!("Invalid port {}: {}", port, e);
+                    return;
+                }
+            };
+            match Udp::host(("0.0.0.0", port)) {
+                Ok(transport) => {
+                    let addr = transport.local_addr().unwrap();
+                    println!("Waiting for a player on {}", addr);
+                    let lobby = Lobby::host(vec![mode as u8]);
+                    Some(Network::Peer(Online { transport, lobby, local: 0 }))
+                }
+                Err(e) => {
+                    println!("Failed to host on port {}: {}", port, e);
+                    return;
+                }
+            }
+        }
+        [_, flag, addr] if flag == "--join" => match Udp::join(addr.as_str()) {
+            Ok(transport) => {
+                let lobby = Lobby::join();
+                Some(Network::Peer(Online { transport, lobby, local: 1 }))
+            }
+            Err(e) => {
+                println!("Failed to join {}: {}", addr, e);
+                return;
+            }
+        },
+        [_, flag, addr] if flag == "--connect" => {
+            match Udp::join(addr.as_str()) {
+                Ok(transport) => Some(Network::Server(transport)),
+                Err(e) => {
+                    println!("Failed to connect to {}: {}", addr, e);
+                    return;
+                }
+            }
+        }
+        _ => None,
+    };
+
     let event_loop = EventLoop::new();
-    let mut renderer = Renderer::new(&event_loop);
-
-    let mut game_state = State {
-        x: 0.5,
-        y: 0.5,
-        vel_x: 0.0,
-        vel_y: 0.0,
-        angle: 0.0,
-        accel: false,
-        rot: Rot::No,
-        asteroids: vec![
-            Asteroid { x: 0.0, y: 0.0, vel_x: 0.0, vel_y: 0.0, angle: 0.0 },
-        ],
+    let display_path = Display::path();
+    let display = display_path
+        .as_deref()
+        .map_or_else(Display::default, Display::load_or_default);
+    let mut renderer = Renderer::new(&event_loop, display);
+    let mut modifiers = ModifiersState::empty();
+
+    let skin = load_skin(&mut renderer);
+    let mut setup = Setup::default();
+    let game_state = game::new_state(skin, setup.clone());
+    let mut fps = Fps::new();
+    let mut controls = Controls::new();
+
+    // The window thread's copy of the tuning, edited in the GUI and sent to
+    // the simulation when it changes.
+    let mut tuning = game_state.tuning.clone();
+
+    let mut audio = Audio::new();
+    audio.play_music(0);
+
+    let (input_tx, input_rx) = channel();
+    let (particles_tx, particles_rx) = channel();
+    let (events_tx, events_rx) = channel();
+    let (writer, mut snapshots) = triple_buffer(Shown {
+        st: game_state.clone(),
+        scenes: Scenes::new(setup.clone()),
+        debug: Vec::new(),
+    });
+    thread::spawn(move || {
+        let (st, inputs) = (game_state, input_rx);
+        let (particles, events) = (particles_tx, events_tx);
+        match online {
+            Some(Network::Peer(online)) => {
+                simulate_online(st, online, inputs, particles, events, writer)
+            }
+            Some(Network::Server(server)) => {
+                simulate_client(st, server, inputs, particles, events, writer)
+            }
+            None => simulate(st, inputs, particles, events, writer),
+        }
+    });
+    // An online game ends the simulation when it fails, leaving the last
+    // frame on screen.
+    let send = move |input| {
+        let _ = input_tx.send(input);
     };
 
     event_loop.run(move |event, _, control_flow| {
+        // The GUI gets the first look at input, so typing into it or
+        // dragging a slider does not steer a ship.
+        if let Event::WindowEvent { event, .. } = &event {
+            if renderer.gui.handle_event(event) {
+                return;
+            }
+        }
+
         match event {
             Event::WindowEvent {
                 event: WindowEvent::KeyboardInput {
@@ -140,18 +722,31 @@ fn main() {
                 },
                 ..
             } => {
-                if state == Keyvent::Pressed {
-                    match key {
-                        Key::A => game_state.rot = Rot::Left,
-                        Key::F => game_state.rot = Rot::Right,
-                        Key::D => game_state.accel = true,
-                        _ => (),
-                    }
-                } else {
+                let pressed = state == Keyvent::Pressed;
+                // Releases always reach the ships, so nothing stays held
+                // after a menu.
+                let nav = controls.nav(key).filter(|_| pressed);
+                if pressed && key == Key::Return && modifiers.alt() {
+                    renderer.settings.display.toggle_fullscreen();
+                } else if let Some(nav) =
+                    nav.filter(|_| snapshots.read().scenes.in_menu())
+                {
+                    send(Input::Nav(nav));
+                } else if let Some((player, action)) = controls.key(key) {
+                    send(Input::Action { player, action, held: pressed });
+                } else if pressed {
                     match key {
-                        Key::A => game_state.rot = Rot::No,
-                        Key::F => game_state.rot = Rot::No,
-                        Key::D => game_state.accel = false,
+                        Key::Escape | Key::P => send(Input::Nav(Nav::Back)),
+                        Key::N => send(Input::NewGame(setup.clone())),
+                        Key::T => send(Input::ToggleTextured),
+                        Key::G => debug::toggle(),
+                        Key::F1 => renderer.gui.toggle(),
+                        Key::F2 => profile::toggle(),
+                        Key::F3 => toggle_trace(),
+                        Key::C => send(Input::ToggleFollow),
+                        Key::M => audio.next_track(),
+                        Key::Equals => send(Input::Zoom(ZOOM_STEP)),
+                        Key::Minus => send(Input::Zoom(1.0 / ZOOM_STEP)),
                         _ => (),
                     }
                 }
@@ -159,12 +754,111 @@ fn main() {
             Event::WindowEvent { event: WindowEvent::CloseRequested, ..  } => {
                 *control_flow = ControlFlow::Exit;
             }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(state), ..
+            } => {
+                modifiers = state;
+            }
             Event::WindowEvent { event: WindowEvent::Resized(_), ..  } => {
                 renderer.recreate_swapchain = true;
+                renderer.track_window();
+            }
+            Event::WindowEvent { event: WindowEvent::Moved(_), ..  } => {
+                renderer.track_window();
+            }
+            Event::LoopDestroyed => {
+                if let Some(path) = &display_path {
+                    if let Err(e) = renderer.settings.display.save(path) {
+                        println!(
+                            "Failed to save display settings to {:?}: {}",
+                            path, e,
+                        );
+                    }
+                }
             }
             Event::RedrawEventsCleared => {
-                update(&mut game_state);
-                renderer.redraw(render(&game_state));
+                // Nothing is drawn while minimized, so there's no present to
+                // wait on. Wake up once a tick to keep the sound going.
+                *control_flow = if renderer.minimized() {
+                    ControlFlow::WaitUntil(Instant::now() + game::TICK)
+                } else {
+                    ControlFlow::Poll
+                };
+
+                let in_menu = snapshots.read().scenes.in_menu();
+                for pad in controls.poll_pads(in_menu) {
+                    match pad {
+                        Pad::Action { player, action, held } => {
+                            send(Input::Action { player, action, held });
+                        }
+                        Pad::Nav(nav) => send(Input::Nav(nav)),
+                    }
+                }
+
+                let shown = snapshots.read();
+                if shown.scenes.done() {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                let st = &shown.st;
+                renderer.particles.spawn(particles_rx.try_iter().flatten());
+                for event in events_rx.try_iter().flatten() {
+                    match event {
+                        GameEvent::Hit { x } => {
+                            audio.play(Sfx::Explosion, pan(st, x));
+                            audio.play(Sfx::Death, pan(st, x));
+                        }
+                        GameEvent::Fire { x } => {
+                            audio.play(Sfx::Fire, pan(st, x));
+                        }
+                        GameEvent::Explosion { x } => {
+                            audio.play(Sfx::Explosion, pan(st, x));
+                        }
+                        GameEvent::Beat { high: false } => {
+                            audio.play(Sfx::ThumpLow, 0.0);
+                        }
+                        GameEvent::Beat { high: true } => {
+                            audio.play(Sfx::ThumpHigh, 0.0);
+                        }
+                    }
+                }
+                // One thrust loop for everyone, heard from the first ship
+                // using it.
+                let thrusting = st.players
+                    .iter()
+                    .filter(|player| player.thrust)
+                    .find_map(|player| {
+                        st.entities.transforms.get(player.ship?)
+                    });
+                match thrusting {
+                    Some(ship) => {
+                        audio.looping(Sfx::Thrust, true, pan(st, ship.pos[0]));
+                    }
+                    None => audio.looping(Sfx::Thrust, false, 0.0),
+                }
+                audio.update();
+                fps.frame();
+                hud(&mut renderer, shown, &fps);
+                debug::extend(&shown.debug);
+                debug_draw(st);
+                if renderer.gui.visible {
+                    let before = tuning.clone();
+                    let new_game = tweak_panel(
+                        &mut renderer,
+                        &mut tuning,
+                        &mut audio.volumes,
+                        &mut setup,
+                    );
+                    if tuning != before {
+                        send(Input::Tuning(tuning.clone()));
+                    }
+                    if new_game {
+                        send(Input::NewGame(setup.clone()));
+                    }
+                }
+                renderer.view = st.camera.view();
+                renderer.world = st.world;
+                renderer.redraw(render(st));
             }
             _ => (),
         }
@@ -0,0 +1,54 @@
+// Online play, either between two peers with rollback or with clients of
+// an authoritative server. Peers find each other with a `Lobby` handshake,
+// then each runs a `Session` that exchanges inputs over a `Transport`: UDP,
+// or a simulated link for testing. A `Server` runs the game itself and
+// sends snapshots of it to each `Client`.
+
+mod client;
+pub mod delta;
+mod lobby;
+mod protocol;
+mod rollback;
+mod server;
+pub mod sim;
+mod transport;
+
+pub use client::{Client, Refused, INTERPOLATION_DELAY};
+pub use lobby::Lobby;
+pub use rollback::{Desync, Session};
+pub use server::Server;
+pub use transport::{Transport, Udp};
+
+/// Players in an online game, one per peer. The host is player 0.
+pub const PLAYERS: usize = 2;
+
+/// Frames between pressing a button and the ship reacting online. A little
+/// delay gives inputs time to reach the peer, so fewer frames are guessed.
+pub const INPUT_DELAY: u32 = 2;
+
+/// A simulation that rollback can drive. It must be deterministic: the same
+/// state and inputs always give the same next state, on both peers.
+pub trait Game: Clone {
+    /// Simulates a frame with each player holding `buttons`. `replay` is
+    /// set when simulating a frame again after a rollback, so its sounds and
+    /// effects can be skipped.
+    fn advance(&mut self, buttons: &[u8], replay: bool);
+
+    /// A hash of everything that matters to the simulation, compared
+    /// between peers to find desyncs.
+    fn checksum(&self) -> u64;
+}
+
+/// A game a server can run for clients, which decode snapshots of it.
+pub trait Replicated: Game {
+    /// Writes everything a client needs to show the game and predict its
+    /// player. An unchanged game must encode to the same bytes.
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// Replaces the replicated parts of the game with `encoded`. Returns
+    /// false if it isn't a valid encoding, leaving the game half decoded.
+    fn decode(&mut self, encoded: &[u8]) -> bool;
+
+    /// The buttons each player is holding.
+    fn buttons(&self) -> Vec<u8>;
+}
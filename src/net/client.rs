@@ -0,0 +1,336 @@
+use super::{
+    delta,
+    protocol::{Command, Message, Snapshot, MAX_INPUTS, VERSION},
+    Replicated, Transport,
+};
+
+use std::collections::VecDeque;
+
+// Ticks between hellos while waiting to join.
+const RESEND: u32 = 10;
+
+// Snapshots kept to decode deltas against and to interpolate between. The
+// server keeps as many, so any it sends a delta against is still here.
+const KEPT: usize = 32;
+
+/// How far behind the newest snapshot the interpolated view is, in ticks.
+/// Being behind leaves a snapshot to interpolate towards when one is late
+/// or lost.
+pub const INTERPOLATION_DELAY: f32 = 2.0;
+
+// How much of the gap to the ideal render time is closed each tick, and
+// the gap past which the render time jumps instead.
+const CATCH_UP: f32 = 0.1;
+const MAX_LAG: f32 = 8.0;
+
+/// Why the server turned the client away.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Refused {
+    /// The server runs this version of the protocol instead.
+    Version(u16),
+    /// Every player is taken.
+    Full,
+}
+
+/// Plays a game run by a `Server`. Inputs are sent to the server every
+/// tick, and the client's own player is predicted by applying those the
+/// server hasn't yet to its newest snapshot. Everything else is shown as
+/// the snapshots say, interpolated a little behind the newest one so it
+/// moves smoothly.
+pub struct Client<G> {
+    // A game to decode snapshots into. Whatever isn't replicated comes from
+    // here.
+    template: G,
+    player: Option<usize>,
+    wait: u32,
+    // Inputs the server hasn't applied, oldest first, and the sequence
+    // number of the next one.
+    pending: VecDeque<u8>,
+    next_input: u32,
+    // Recent snapshots, oldest first, as the tick, the encoded game and the
+    // game, and how many of the newest haven't been handed out.
+    snapshots: VecDeque<(u32, Vec<u8>, G)>,
+    unseen: usize,
+    predicted: Option<G>,
+    // The tick shown, between snapshots.
+    render_tick: f32,
+}
+
+impl<G: Replicated> Client<G> {
+    /// A client that decodes snapshots into copies of `template`.
+    pub fn new(template: G) -> Self {
+        Client {
+            template,
+            player: None,
+            wait: 0,
+            pending: VecDeque::new(),
+            next_input: 0,
+            snapshots: VecDeque::new(),
+            unseen: 0,
+            predicted: None,
+            render_tick: 0.0,
+        }
+    }
+
+    /// The player the server gave this client, once it has joined.
+    pub fn player(&self) -> Option<usize> {
+        self.player
+    }
+
+    /// The newest snapshot and its tick.
+    pub fn latest(&self) -> Option<(u32, &G)> {
+        self.snapshots.back().map(|(tick, _, game)| (*tick, game))
+    }
+
+    /// The newest snapshot with the inputs the server hasn't applied yet
+    /// applied, which is where the client's own player is now.
+    pub fn predicted(&self) -> Option<&G> {
+        self.predicted.as_ref()
+    }
+
+    /// Inputs sent that the newest snapshot doesn't include yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The two snapshots to show between, and how far from the first to
+    /// the second.
+    pub fn interpolated(&self) -> Option<(&G, &G, f32)> {
+        let after = self.snapshots
+            .iter()
+            .position(|&(tick, _, _)| tick as f32 >= self.render_tick)
+            .unwrap_or(self.snapshots.len().checked_sub(1)?);
+        let (to_tick, _, to) = &self.snapshots[after];
+        let (from_tick, _, from) = &self.snapshots[after.saturating_sub(1)];
+
+        let t = if to_tick > from_tick {
+            let span = (to_tick - from_tick) as f32;
+            (self.render_tick - *from_tick as f32) / span
+        } else {
+            1.0
+        };
+        Some((from, to, t.clamp(0.0, 1.0)))
+    }
+
+    /// Snapshots received since the last call, oldest first, to take what
+    /// happened in them.
+    pub fn received(&mut self) -> impl Iterator<Item = &mut G> {
+        let unseen = std::mem::take(&mut self.unseen);
+        let skip = self.snapshots.len() - unseen;
+        self.snapshots.iter_mut().skip(skip).map(|(_, _, game)| game)
+    }
+
+    /// Runs one tick with the player holding `buttons`: joins the server if
+    /// it hasn't, takes in its snapshots and sends it the input.
+    pub fn tick(
+        &mut self,
+        buttons: u8,
+        transport: &mut impl Transport,
+    ) -> Result<(), Refused> {
+        self.receive(transport)?;
+
+        if self.player.is_none() {
+            if self.wait == 0 {
+                transport.send(&Message::Hello { version: VERSION }.encode());
+                self.wait = RESEND;
+            }
+            self.wait -= 1;
+            return Ok(());
+        }
+
+        // Should the server stop hearing from us for a while, the oldest
+        // inputs are given up rather than resending more than fit.
+        if self.pending.len() == MAX_INPUTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(buttons);
+        self.next_input += 1;
+
+        let command = Command {
+            ack: self.latest().map(|(tick, _)| tick),
+            start: self.next_input - self.pending.len() as u32,
+            buttons: self.pending.iter().copied().collect(),
+        };
+        transport.send(&Message::Command(command).encode());
+
+        self.predict();
+        if let Some((newest, _)) = self.latest() {
+            let ideal = newest as f32 - INTERPOLATION_DELAY;
+            self.render_tick += 1.0;
+            if (ideal - self.render_tick).abs() > MAX_LAG {
+                self.render_tick = ideal;
+            } else {
+                self.render_tick += (ideal - self.render_tick) * CATCH_UP;
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(
+        &mut self,
+        transport: &mut impl Transport,
+    ) -> Result<(), Refused> {
+        while let Some(packet) = transport.recv() {
+            match Message::decode(&packet) {
+                Some(Message::Joined { player }) if self.player.is_none() => {
+                    self.player = Some(player as usize);
+                }
+                Some(Message::Reject { version }) => {
+                    return Err(Refused::Version(version));
+                }
+                Some(Message::Full) if self.player.is_none() => {
+                    return Err(Refused::Full);
+                }
+                Some(Message::Snapshot(snapshot)) if self.player.is_some() => {
+                    self.snapshot(snapshot);
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    // Decodes a snapshot newer than any so far, and forgets the inputs it
+    // includes. One without the player the server gave us is bogus, as
+    // predicting and showing the game look the player up in it.
+    fn snapshot(&mut self, snapshot: Snapshot) {
+        if self.latest().is_some_and(|(newest, _)| snapshot.tick <= newest) {
+            return;
+        }
+
+        let encoded = match snapshot.base {
+            None => snapshot.data,
+            Some(base) => {
+                let applied = self.snapshots
+                    .iter()
+                    .find(|&&(tick, _, _)| tick == base)
+                    .and_then(|(_, base, _)| {
+                        delta::apply(base, &snapshot.data)
+                    });
+                match applied {
+                    Some(encoded) => encoded,
+                    None => return,
+                }
+            }
+        };
+        let mut game = self.template.clone();
+        let player = self.player.unwrap_or(0);
+        if !game.decode(&encoded) || game.buttons().len() <= player {
+            return;
+        }
+
+        self.snapshots.push_back((snapshot.tick, encoded, game));
+        self.unseen += 1;
+        if self.snapshots.len() > KEPT {
+            self.snapshots.pop_front();
+            self.unseen = self.unseen.min(KEPT);
+        }
+
+        let applied = snapshot.applied.min(self.next_input);
+        let pending = (self.next_input - applied) as usize;
+        while self.pending.len() > pending {
+            self.pending.pop_front();
+        }
+    }
+
+    // Replays the pending inputs on the newest snapshot, everyone else
+    // holding what they held in it. Done every tick, so a wrong prediction
+    // is corrected as soon as a snapshot shows it.
+    fn predict(&mut self) {
+        let (player, newest) = match (self.player, self.snapshots.back()) {
+            (Some(player), Some((_, _, newest))) => (player, newest),
+            _ => return,
+        };
+
+        let predicted = match &mut self.predicted {
+            Some(predicted) => {
+                predicted.clone_from(newest);
+                predicted
+            }
+            slot => slot.insert(newest.clone()),
+        };
+
+        let mut buttons = newest.buttons();
+        for &input in self.pending.iter() {
+            if let Some(held) = buttons.get_mut(player) {
+                *held = input;
+            }
+            // Whatever happens is heard when the server says so.
+            predicted.advance(&buttons, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        game::{self, Setup, State},
+        net::sim::{Conditions, SimEnd, SimLink},
+    };
+
+    // A client, and the end of a perfect link to it a server would have.
+    fn connect() -> (Client<State>, SimEnd, SimEnd, SimLink) {
+        let conditions = Conditions { latency: 0, jitter: 0, loss: 0.0 };
+        let link = SimLink::new(conditions, 1);
+        let (client, server) = link.ends();
+        let template = game::new_state(None, Setup::default());
+        (Client::new(template), client, server, link)
+    }
+
+    fn snapshot(tick: u32, st: &State) -> Vec<u8> {
+        let mut data = Vec::new();
+        Replicated::encode(st, &mut data);
+        let snapshot = Snapshot { tick, base: None, applied: 0, data };
+        Message::Snapshot(snapshot).encode()
+    }
+
+    #[test]
+    fn snapshots_without_our_player_are_dropped() {
+        let (mut client, mut transport, mut server, link) = connect();
+        // Two players.
+        let st = game::new_state(None, Setup::default());
+
+        server.send(&Message::Joined { player: 9 }.encode());
+        server.send(&snapshot(1, &st));
+        link.tick();
+        client.tick(0, &mut transport).unwrap();
+        assert_eq!(client.player(), Some(9));
+        assert!(client.latest().is_none());
+        assert!(client.predicted().is_none());
+        assert!(client.interpolated().is_none());
+
+        let (mut client, mut transport, mut server, link) = connect();
+        server.send(&Message::Joined { player: 1 }.encode());
+        server.send(&snapshot(1, &st));
+        link.tick();
+        client.tick(0, &mut transport).unwrap();
+        assert_eq!(client.latest().map(|(tick, _)| tick), Some(1));
+        assert!(client.predicted().is_some());
+    }
+
+    #[test]
+    fn hostile_snapshots_are_dropped() {
+        let (mut client, mut transport, mut server, link) = connect();
+        let mut st = game::new_state(None, Setup::default());
+        server.send(&Message::Joined { player: 0 }.encode());
+
+        // The next wave would never finish spawning.
+        st.wave = usize::MAX;
+        st.asteroids.clear();
+        server.send(&snapshot(1, &st));
+        // Neither would growing a world this small.
+        let mut tiny = game::new_state(None, Setup::default());
+        tiny.world = 1e-3;
+        server.send(&snapshot(2, &tiny));
+        server.send(&[0xff; 64]);
+        link.tick();
+
+        for _ in 0..3 {
+            client.tick(0, &mut transport).unwrap();
+        }
+        assert!(client.latest().is_none());
+        assert!(client.predicted().is_none());
+    }
+}
@@ -0,0 +1,135 @@
+// Snapshot deltas. A delta is the new snapshot XORed with its base, which
+// is mostly zeros when little changed, with the zeros run length encoded:
+// the new length, then pairs of a count of unchanged bytes and a count of
+// changed ones followed by those bytes XORed. Counts are LEB128.
+
+use crate::codec::Reader;
+
+// Snapshots are nowhere near this big, so a longer delta is corrupt.
+const MAX_LEN: usize = 1 << 20;
+
+// Changed bytes are sent as they are across shorter runs of unchanged ones,
+// as a pair of counts costs at least two bytes.
+const MIN_SKIP: usize = 3;
+
+fn xor(base: &[u8], new: &[u8], i: usize) -> u8 {
+    new[i] ^ base.get(i).copied().unwrap_or(0)
+}
+
+fn put(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn get(r: &mut Reader) -> Option<usize> {
+    let mut n = 0;
+    for shift in (0..35).step_by(7) {
+        let byte = r.take(1)?[0];
+        n |= ((byte & 0x7f) as usize) << shift;
+        if byte < 0x80 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+/// The delta that turns `base` into `new`.
+pub fn diff(base: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    put(&mut out, new.len());
+
+    let mut i = 0;
+    while i < new.len() {
+        let start = i;
+        while i < new.len() && xor(base, new, i) == 0 {
+            i += 1;
+        }
+        if i == new.len() {
+            break;
+        }
+        let skip = i - start;
+
+        let changed = i;
+        let mut zeros = 0;
+        while i < new.len() && zeros < MIN_SKIP {
+            zeros = if xor(base, new, i) == 0 { zeros + 1 } else { 0 };
+            i += 1;
+        }
+        i -= zeros;
+
+        put(&mut out, skip);
+        put(&mut out, i - changed);
+        out.extend((changed..i).map(|j| xor(base, new, j)));
+    }
+
+    out
+}
+
+/// `base` with `delta` applied, or `None` if the delta is corrupt.
+pub fn apply(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut r = Reader(delta);
+    let len = get(&mut r)?;
+    if len > MAX_LEN {
+        return None;
+    }
+
+    let mut new: Vec<u8> = (0..len)
+        .map(|i| base.get(i).copied().unwrap_or(0))
+        .collect();
+    let mut i = 0;
+    while !r.is_empty() {
+        i += get(&mut r)?;
+        let count = get(&mut r)?;
+        let changed = new.get_mut(i..i.checked_add(count)?)?;
+        for (byte, &x) in changed.iter_mut().zip(r.take(count)?) {
+            *byte ^= x;
+        }
+        i += count;
+    }
+
+    Some(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[u8], new: &[u8]) -> Vec<u8> {
+        let delta = diff(base, new);
+        assert_eq!(apply(base, &delta).as_deref(), Some(new));
+        delta
+    }
+
+    #[test]
+    fn deltas_rebuild_the_new_snapshot() {
+        let base: Vec<u8> = (0..1000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut new = base.clone();
+        new[3] ^= 1;
+        new[500..520].iter_mut().for_each(|byte| *byte = 0);
+        new[998] = 42;
+
+        let delta = round_trip(&base, &new);
+        assert!(delta.len() < 40, "delta is {} bytes", delta.len());
+
+        assert_eq!(round_trip(&base, &base).len(), 2);
+        round_trip(&base, &new[..600]);
+        round_trip(&base[..10], &new);
+        round_trip(&[], &new);
+        round_trip(&base, &[]);
+    }
+
+    #[test]
+    fn corrupt_deltas_are_rejected() {
+        let base = [1, 2, 3];
+        assert_eq!(apply(&base, &[]), None);
+        // Changes past the end.
+        assert_eq!(apply(&base, &[3, 2, 2, 9, 9]), None);
+        // Fewer changed bytes than counted.
+        assert_eq!(apply(&base, &[3, 0, 2, 9]), None);
+        // A length too long for a snapshot.
+        assert_eq!(apply(&base, &[0xff, 0xff, 0x7f]), None);
+    }
+}
@@ -0,0 +1,154 @@
+use super::{
+    protocol::{Message, VERSION},
+    Transport,
+};
+
+// Ticks between a guest's hellos and the host's welcomes while they wait
+// for each other.
+const RESEND: u32 = 10;
+
+/// The handshake before a game. The guest says hello until the host
+/// welcomes it with the game's settings. The host keeps welcoming until
+/// the guest's first inputs show the welcome arrived, so either side can
+/// start at frame 0 knowing the other will too.
+pub enum Lobby {
+    Host { settings: Vec<u8>, welcomed: bool, wait: u32 },
+    Guest { wait: u32 },
+}
+
+/// The host runs another version of the game.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rejected {
+    pub version: u16,
+}
+
+impl Lobby {
+    pub fn host(settings: Vec<u8>) -> Self {
+        Lobby::Host { settings, welcomed: false, wait: 0 }
+    }
+
+    pub fn join() -> Self {
+        Lobby::Guest { wait: 0 }
+    }
+
+    /// Handles the handshake for a tick, returning the game's settings once
+    /// the game can start.
+    pub fn poll(
+        &mut self,
+        transport: &mut impl Transport,
+    ) -> Result<Option<Vec<u8>>, Rejected> {
+        match self {
+            Lobby::Host { settings, welcomed, wait } => {
+                while let Some(packet) = transport.recv() {
+                    match Message::decode(&packet) {
+                        Some(Message::Hello { version })
+                            if version == VERSION =>
+                        {
+                            *welcomed = true;
+                            *wait = 0;
+                        }
+                        Some(Message::Hello { .. }) => {
+                            let reject = Message::Reject { version: VERSION };
+                            transport.send(&reject.encode());
+                        }
+                        Some(Message::Inputs(_)) if *welcomed => {
+                            return Ok(Some(settings.clone()));
+                        }
+                        _ => (),
+                    }
+                }
+
+                if *welcomed {
+                    if *wait == 0 {
+                        let settings = settings.clone();
+                        transport.send(&Message::Welcome { settings }.encode());
+                        *wait = RESEND;
+                    }
+                    *wait -= 1;
+                }
+                Ok(None)
+            }
+            Lobby::Guest { wait } => {
+                while let Some(packet) = transport.recv() {
+                    match Message::decode(&packet) {
+                        Some(Message::Welcome { settings }) => {
+                            return Ok(Some(settings));
+                        }
+                        Some(Message::Reject { version }) => {
+                            return Err(Rejected { version });
+                        }
+                        _ => (),
+                    }
+                }
+
+                if *wait == 0 {
+                    let hello = Message::Hello { version: VERSION };
+                    transport.send(&hello.encode());
+                    *wait = RESEND;
+                }
+                *wait -= 1;
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::{
+        protocol::Inputs,
+        sim::{Conditions, SimLink},
+    };
+
+    #[test]
+    fn guest_joins_over_a_lossy_link() {
+        let conditions = Conditions { latency: 3, jitter: 2, loss: 0.5 };
+        let link = SimLink::new(conditions, 1);
+        let (mut host_end, mut guest_end) = link.ends();
+        let mut host = Lobby::host(vec![2, 1]);
+        let mut guest = Lobby::join();
+
+        let mut joined = None;
+        let mut started = None;
+        for _ in 0..1000 {
+            if joined.is_none() {
+                joined = guest.poll(&mut guest_end).unwrap();
+            } else {
+                // The guest's game has started and sends inputs every tick.
+                let inputs = Inputs {
+                    ack: 0,
+                    frame: 0,
+                    advantage: 0,
+                    start: 0,
+                    buttons: Vec::new(),
+                    checksum: None,
+                };
+                guest_end.send(&Message::Inputs(inputs).encode());
+            }
+            if started.is_none() {
+                started = host.poll(&mut host_end).unwrap();
+            }
+            link.tick();
+        }
+
+        assert_eq!(joined, Some(vec![2, 1]));
+        assert_eq!(started, Some(vec![2, 1]));
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let conditions = Conditions { latency: 1, jitter: 0, loss: 0.0 };
+        let link = SimLink::new(conditions, 1);
+        let (mut host_end, mut guest_end) = link.ends();
+        let mut host = Lobby::host(Vec::new());
+
+        guest_end.send(&Message::Hello { version: VERSION + 1 }.encode());
+        link.tick();
+        assert_eq!(host.poll(&mut host_end), Ok(None));
+        link.tick();
+
+        let reply = Message::decode(&guest_end.recv().unwrap());
+        assert_eq!(reply, Some(Message::Reject { version: VERSION }));
+    }
+}
@@ -0,0 +1,249 @@
+use crate::codec::{Encode, Reader};
+
+// Every message starts with these, so stray datagrams are ignored.
+const MAGIC: [u8; 2] = *b"AS";
+
+/// Bumped whenever messages or the simulation change, since peers running
+/// different versions would desync.
+pub const VERSION: u16 = 2;
+
+/// The most inputs sent in one message.
+pub const MAX_INPUTS: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// A guest asking to join.
+    Hello { version: u16 },
+    /// The host accepting a guest, with the game's settings.
+    Welcome { settings: Vec<u8> },
+    /// The host turning a guest away for running another version.
+    Reject { version: u16 },
+    Inputs(Inputs),
+    /// A server accepting a client as `player`.
+    Joined { player: u8 },
+    /// A server turning a client away because every player is taken.
+    Full,
+    Command(Command),
+    Snapshot(Snapshot),
+}
+
+/// Sent every tick while playing. Inputs are resent until acknowledged, so
+/// a lost message costs nothing once a later one arrives.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inputs {
+    /// How many of the receiver's inputs the sender has.
+    pub ack: u32,
+    /// The sender's frame, and how far ahead of the receiver it thinks it
+    /// is, to keep the peers in step.
+    pub frame: u32,
+    pub advantage: i32,
+    /// The frame of the first of `buttons`.
+    pub start: u32,
+    pub buttons: Vec<u8>,
+    /// The sender's newest checksum of a frame both peers agree on the
+    /// inputs for.
+    pub checksum: Option<(u32, u64)>,
+}
+
+/// Sent by a client to the server every tick, like `Inputs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    /// The newest snapshot the client has, to send the next one against.
+    pub ack: Option<u32>,
+    /// The sequence number of the first of `buttons`, counting the client's
+    /// inputs from 0.
+    pub start: u32,
+    pub buttons: Vec<u8>,
+}
+
+/// Sent by the server to every client every tick.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    /// The snapshot `data` is a delta from, or `None` if it is a whole one.
+    pub base: Option<u32>,
+    /// How many of the client's inputs the game has used.
+    pub applied: u32,
+    pub data: Vec<u8>,
+}
+
+const HELLO: u8 = 0;
+const WELCOME: u8 = 1;
+const REJECT: u8 = 2;
+const INPUTS: u8 = 3;
+const JOINED: u8 = 4;
+const FULL: u8 = 5;
+const COMMAND: u8 = 6;
+const SNAPSHOT: u8 = 7;
+
+// Buttons are sent as a count and the bytes, up to `MAX_INPUTS` of them.
+fn encode_buttons(buttons: &[u8], out: &mut Vec<u8>) {
+    let count = buttons.len().min(MAX_INPUTS);
+    out.push(count as u8);
+    out.extend(&buttons[..count]);
+}
+
+fn decode_buttons(r: &mut Reader) -> Option<Vec<u8>> {
+    let count = r.read::<u8>()? as usize;
+    Some(r.take(count)?.to_vec())
+}
+
+impl Message {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        match self {
+            Message::Hello { version } => {
+                out.push(HELLO);
+                version.encode(&mut out);
+            }
+            Message::Welcome { settings } => {
+                out.push(WELCOME);
+                out.extend(settings);
+            }
+            Message::Reject { version } => {
+                out.push(REJECT);
+                version.encode(&mut out);
+            }
+            Message::Inputs(inputs) => {
+                out.push(INPUTS);
+                inputs.ack.encode(&mut out);
+                inputs.frame.encode(&mut out);
+                inputs.advantage.encode(&mut out);
+                inputs.start.encode(&mut out);
+                encode_buttons(&inputs.buttons, &mut out);
+                if let Some(checksum) = inputs.checksum {
+                    checksum.encode(&mut out);
+                }
+            }
+            Message::Joined { player } => {
+                out.push(JOINED);
+                out.push(*player);
+            }
+            Message::Full => out.push(FULL),
+            Message::Command(command) => {
+                out.push(COMMAND);
+                command.ack.encode(&mut out);
+                command.start.encode(&mut out);
+                encode_buttons(&command.buttons, &mut out);
+            }
+            Message::Snapshot(snapshot) => {
+                out.push(SNAPSHOT);
+                snapshot.tick.encode(&mut out);
+                snapshot.base.encode(&mut out);
+                snapshot.applied.encode(&mut out);
+                out.extend(&snapshot.data);
+            }
+        }
+        out
+    }
+
+    /// The message in `packet`, or `None` if it isn't one.
+    pub fn decode(packet: &[u8]) -> Option<Message> {
+        let mut r = Reader(packet);
+        if r.take(2)? != MAGIC {
+            return None;
+        }
+
+        let message = match r.read::<u8>()? {
+            HELLO => Message::Hello { version: r.read()? },
+            WELCOME => Message::Welcome { settings: r.rest().to_vec() },
+            REJECT => Message::Reject { version: r.read()? },
+            INPUTS => {
+                let ack = r.read()?;
+                let frame = r.read()?;
+                let advantage = r.read()?;
+                let start = r.read()?;
+                let buttons = decode_buttons(&mut r)?;
+                let checksum =
+                    if r.is_empty() { None } else { Some(r.read()?) };
+                Message::Inputs(Inputs {
+                    ack,
+                    frame,
+                    advantage,
+                    start,
+                    buttons,
+                    checksum,
+                })
+            }
+            JOINED => Message::Joined { player: r.read()? },
+            FULL => Message::Full,
+            COMMAND => Message::Command(Command {
+                ack: r.read()?,
+                start: r.read()?,
+                buttons: decode_buttons(&mut r)?,
+            }),
+            SNAPSHOT => Message::Snapshot(Snapshot {
+                tick: r.read()?,
+                base: r.read()?,
+                applied: r.read()?,
+                data: r.rest().to_vec(),
+            }),
+            _ => return None,
+        };
+
+        if r.is_empty() { Some(message) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_survive_a_round_trip() {
+        let messages = [
+            Message::Hello { version: VERSION },
+            Message::Welcome { settings: vec![2, 1] },
+            Message::Reject { version: 7 },
+            Message::Inputs(Inputs {
+                ack: 12,
+                frame: 40,
+                advantage: -3,
+                start: 30,
+                buttons: vec![0, 1, 5, 8],
+                checksum: Some((30, 0xdead_beef_0123)),
+            }),
+            Message::Inputs(Inputs {
+                ack: 0,
+                frame: 0,
+                advantage: 0,
+                start: 0,
+                buttons: Vec::new(),
+                checksum: None,
+            }),
+            Message::Joined { player: 3 },
+            Message::Full,
+            Message::Command(Command {
+                ack: Some(99),
+                start: 7,
+                buttons: vec![1, 2],
+            }),
+            Message::Command(Command {
+                ack: None,
+                start: 0,
+                buttons: Vec::new(),
+            }),
+            Message::Snapshot(Snapshot {
+                tick: 100,
+                base: Some(98),
+                applied: 40,
+                data: vec![0, 4, 1, 9],
+            }),
+        ];
+
+        for message in messages.iter() {
+            let decoded = Message::decode(&message.encode());
+            assert_eq!(decoded.as_ref(), Some(message));
+        }
+    }
+
+    #[test]
+    fn garbage_is_not_a_message() {
+        let hello = Message::Hello { version: VERSION }.encode();
+
+        assert_eq!(Message::decode(b""), None);
+        assert_eq!(Message::decode(b"XX\x00\x01\x00"), None);
+        assert_eq!(Message::decode(&hello[..hello.len() - 1]), None);
+        assert_eq!(Message::decode(&[hello.as_slice(), &[0]].concat()), None);
+    }
+}
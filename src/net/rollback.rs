@@ -0,0 +1,394 @@
+use super::{
+    protocol::{Inputs, Message, MAX_INPUTS},
+    Game, Transport, PLAYERS,
+};
+
+use std::collections::VecDeque;
+
+/// How many frames a peer may run ahead of the last input it has from the
+/// other, guessing the missing inputs, before it waits for them.
+pub const MAX_PREDICTION: u32 = 8;
+
+// Saved states, enough to roll back to any frame that was guessed.
+const SAVED: usize = MAX_PREDICTION as usize + 2;
+
+// Frames between checksums, and how many of our own are kept to compare
+// with the peer's.
+const CHECKSUM_INTERVAL: u32 = 30;
+const CHECKSUMS_KEPT: usize = 16;
+
+// Frames a peer may be ahead of the other before it waits a tick for it to
+// catch up.
+const MAX_DRIFT: i32 = 1;
+
+/// What a tick did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tick {
+    Advanced,
+    /// Waited for the peer, either for its inputs or for it to catch up.
+    Stalled,
+}
+
+/// The peers simulated a frame differently.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: u32,
+}
+
+/// A game played by two peers with rollback, as GGPO does. Local inputs are
+/// applied after a short delay and sent to the peer every tick. Rather than
+/// waiting for the peer's inputs, each frame is simulated with a guess,
+/// the peer's last known input. When a guess turns out wrong, the game is
+/// rolled back to the frame it was made for and simulated again with the
+/// real inputs.
+pub struct Session<G> {
+    game: G,
+    // Which player is local. The other is the peer.
+    local: usize,
+    // Frames simulated so far, and so the frame `game` is at the start of.
+    frame: u32,
+    // Each player's inputs from frame 0. The local player's run ahead of
+    // the game by the input delay.
+    inputs: [Vec<u8>; PLAYERS],
+    // The peer's input each frame was simulated with, to notice wrong
+    // guesses.
+    predicted: Vec<u8>,
+    // States at the start of recent frames, indexed by frame modulo the
+    // length, to roll back to.
+    saved: Vec<Option<(u32, G)>>,
+    // How many of the local inputs the peer has.
+    acked: u32,
+    // The peer's newest frame and advantage, and its newest checksum.
+    remote_frame: u32,
+    remote_advantage: i32,
+    remote_checksum: Option<(u32, u64)>,
+    // Recent checksums of frames whose inputs are all known, and the next
+    // frame to check.
+    checksums: VecDeque<(u32, u64)>,
+    next_checksum: u32,
+}
+
+impl<G: Game> Session<G> {
+    /// Starts at frame 0 of `game` as player `local`, with local inputs
+    /// applied `delay` frames after they are given.
+    pub fn new(game: G, local: usize, delay: u32) -> Self {
+        let mut inputs = [Vec::new(), Vec::new()];
+        inputs[local] = vec![0; delay as usize];
+
+        Session {
+            game,
+            local,
+            frame: 0,
+            inputs,
+            predicted: Vec::new(),
+            saved: (0..SAVED).map(|_| None).collect(),
+            acked: 0,
+            remote_frame: 0,
+            remote_advantage: 0,
+            remote_checksum: None,
+            checksums: VecDeque::new(),
+            next_checksum: 0,
+        }
+    }
+
+    /// The game as of the latest frame, which may be based on guesses.
+    pub fn game_mut(&mut self) -> &mut G {
+        &mut self.game
+    }
+
+    /// Runs one tick with the local player holding `buttons`: takes in what
+    /// the peer sent, rolls back if a guess was wrong, simulates the next
+    /// frame if it can and tells the peer about it.
+    pub fn tick(
+        &mut self,
+        buttons: u8,
+        transport: &mut impl Transport,
+    ) -> Result<Tick, Desync> {
+        if let Some(frame) = self.receive(transport) {
+            self.rollback(frame);
+        }
+        self.checksum();
+        self.check()?;
+
+        let remote = &self.inputs[1 - self.local];
+        let waiting = self.frame >= remote.len() as u32 + MAX_PREDICTION;
+        let ahead = (self.advantage() - self.remote_advantage) / 2 > MAX_DRIFT;
+        let tick = if waiting || ahead {
+            Tick::Stalled
+        } else {
+            self.inputs[self.local].push(buttons);
+            self.simulate(false);
+            Tick::Advanced
+        };
+
+        self.send(transport);
+        Ok(tick)
+    }
+
+    // How far ahead of the peer this end thinks it is. Both ends see the
+    // other's frame late by the same latency, so comparing advantages
+    // cancels it out.
+    fn advantage(&self) -> i32 {
+        self.frame as i32 - self.remote_frame as i32
+    }
+
+    // Takes in everything the peer sent, and returns the first frame that
+    // was simulated with a wrong guess, if any.
+    fn receive(&mut self, transport: &mut impl Transport) -> Option<u32> {
+        let mut rollback: Option<u32> = None;
+
+        while let Some(packet) = transport.recv() {
+            let inputs = match Message::decode(&packet) {
+                Some(Message::Inputs(inputs)) => inputs,
+                _ => continue,
+            };
+
+            self.acked = self.acked.max(inputs.ack);
+            // Datagrams can arrive out of order.
+            if inputs.frame >= self.remote_frame {
+                self.remote_frame = inputs.frame;
+                self.remote_advantage = inputs.advantage;
+            }
+            if inputs.checksum > self.remote_checksum {
+                self.remote_checksum = inputs.checksum;
+            }
+
+            let known = &mut self.inputs[1 - self.local];
+            let frames = inputs.start as usize..;
+            for (frame, &buttons) in frames.zip(inputs.buttons.iter()) {
+                if frame > known.len() {
+                    break;
+                }
+                if frame < known.len() {
+                    continue;
+                }
+
+                known.push(buttons);
+                let guessed = self.predicted.get(frame);
+                if guessed.is_some() && guessed != Some(&buttons) {
+                    let frame = frame as u32;
+                    rollback = Some(rollback.map_or(frame, |f| f.min(frame)));
+                }
+            }
+        }
+
+        rollback
+    }
+
+    // Goes back to the start of `frame` and simulates up to the current
+    // frame again.
+    fn rollback(&mut self, frame: u32) {
+        let saved = match &self.saved[frame as usize % SAVED] {
+            Some((saved, game)) if *saved == frame => game,
+            _ => unreachable!("frame {} was not saved", frame),
+        };
+        self.game.clone_from(saved);
+
+        let end = self.frame;
+        self.frame = frame;
+        while self.frame < end {
+            self.simulate(true);
+        }
+    }
+
+    // Saves the state and simulates a frame.
+    fn simulate(&mut self, replay: bool) {
+        let frame = self.frame as usize;
+        match &mut self.saved[frame % SAVED] {
+            Some((saved, game)) => {
+                *saved = self.frame;
+                game.clone_from(&self.game);
+            }
+            slot => *slot = Some((self.frame, self.game.clone())),
+        }
+
+        let remote = &self.inputs[1 - self.local];
+        let guess = remote
+            .get(frame)
+            .or_else(|| remote.last())
+            .copied()
+            .unwrap_or(0);
+        self.predicted.truncate(frame);
+        self.predicted.push(guess);
+
+        let mut buttons = [0; PLAYERS];
+        buttons[self.local] = self.inputs[self.local][frame];
+        buttons[1 - self.local] = guess;
+        self.game.advance(&buttons, replay);
+        self.frame += 1;
+    }
+
+    // Checksums frames once both players' inputs before them are known, so
+    // they are final.
+    fn checksum(&mut self) {
+        let remote = &self.inputs[1 - self.local];
+        let confirmed = self.frame.min(remote.len() as u32);
+
+        while self.next_checksum <= confirmed {
+            let frame = self.next_checksum;
+            let game = if frame == self.frame {
+                Some(&self.game)
+            } else {
+                match &self.saved[frame as usize % SAVED] {
+                    Some((saved, game)) if *saved == frame => Some(game),
+                    _ => None,
+                }
+            };
+
+            if let Some(game) = game {
+                self.checksums.push_back((frame, game.checksum()));
+                if self.checksums.len() > CHECKSUMS_KEPT {
+                    self.checksums.pop_front();
+                }
+            }
+            self.next_checksum += CHECKSUM_INTERVAL;
+        }
+    }
+
+    // Compares the peer's newest checksum with ours for the same frame.
+    fn check(&self) -> Result<(), Desync> {
+        let (frame, theirs) = match self.remote_checksum {
+            Some(checksum) => checksum,
+            None => return Ok(()),
+        };
+
+        match self.checksums.iter().find(|&&(ours, _)| ours == frame) {
+            Some(&(_, ours)) if ours != theirs => Err(Desync { frame }),
+            _ => Ok(()),
+        }
+    }
+
+    // Sends every local input the peer does not have yet.
+    fn send(&mut self, transport: &mut impl Transport) {
+        let local = &self.inputs[self.local];
+        let start = (self.acked as usize).min(local.len());
+        let end = local.len().min(start + MAX_INPUTS);
+
+        let inputs = Inputs {
+            ack: self.inputs[1 - self.local].len() as u32,
+            frame: self.frame,
+            advantage: self.advantage(),
+            start: start as u32,
+            buttons: local[start..end].to_vec(),
+            checksum: self.checksums.back().copied(),
+        };
+        transport.send(&Message::Inputs(inputs).encode());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::sim::{Conditions, SimEnd, SimLink};
+
+    // Something whose state depends on every input in order.
+    #[derive(Clone)]
+    struct Mix {
+        state: [u64; PLAYERS],
+        // Makes the second player's simulation differ.
+        broken: bool,
+    }
+
+    impl Game for Mix {
+        fn advance(&mut self, buttons: &[u8], _replay: bool) {
+            for (state, &buttons) in self.state.iter_mut().zip(buttons.iter()) {
+                *state = state.wrapping_mul(31).wrapping_add(buttons as u64);
+            }
+            if self.broken {
+                self.state[0] += 1;
+            }
+        }
+
+        fn checksum(&self) -> u64 {
+            self.state[0] ^ self.state[1].rotate_left(32)
+        }
+    }
+
+    fn mix() -> Mix {
+        Mix { state: [1, 2], broken: false }
+    }
+
+    // Inputs change every few ticks, so guesses are often wrong.
+    fn buttons(player: usize, tick: u32) -> u8 {
+        ((tick / (3 + player as u32 * 4)) % 16) as u8
+    }
+
+    fn run(
+        conditions: Conditions,
+        games: [Mix; 2],
+        ticks: u32,
+    ) -> (Result<(), Desync>, [Session<Mix>; 2]) {
+        let link = SimLink::new(conditions, 42);
+        let (a, b) = link.ends();
+        let mut ends: [SimEnd; 2] = [a, b];
+        let [first, second] = games;
+        let mut sessions =
+            [Session::new(first, 0, 2), Session::new(second, 1, 2)];
+
+        for tick in 0..ticks {
+            let pairs = sessions.iter_mut().zip(ends.iter_mut());
+            for (player, (session, end)) in pairs.enumerate() {
+                if let Err(desync) = session.tick(buttons(player, tick), end) {
+                    return (Err(desync), sessions);
+                }
+            }
+            link.tick();
+        }
+
+        (Ok(()), sessions)
+    }
+
+    // The checksum after simulating `frames` frames with `inputs`.
+    fn replay(inputs: &[Vec<u8>; PLAYERS], frames: u32) -> u64 {
+        let mut game = mix();
+        let pairs = inputs[0].iter().zip(inputs[1].iter());
+        for (&a, &b) in pairs.take(frames as usize) {
+            game.advance(&[a, b], false);
+        }
+        game.checksum()
+    }
+
+    #[test]
+    fn peers_agree_over_a_lossy_link() {
+        let conditions = Conditions { latency: 4, jitter: 3, loss: 0.25 };
+        let (result, sessions) = run(conditions, [mix(), mix()], 600);
+        assert_eq!(result, Ok(()));
+
+        for session in sessions.iter() {
+            let frame = session.frame;
+            assert!(frame > 500, "only reached frame {}", frame);
+            assert!(session.checksums.len() > 5);
+            for &(frame, checksum) in session.checksums.iter() {
+                assert_eq!(replay(&session.inputs, frame), checksum);
+            }
+        }
+
+        let [a, b] = &sessions;
+        let shared = a.checksums
+            .iter()
+            .filter(|checksum| b.checksums.contains(checksum));
+        assert!(shared.count() > 5);
+    }
+
+    #[test]
+    fn desyncs_are_detected() {
+        let conditions = Conditions { latency: 2, jitter: 0, loss: 0.0 };
+        let broken = Mix { broken: true, ..mix() };
+        let (result, _) = run(conditions, [mix(), broken], 600);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn stalls_without_the_peer() {
+        let conditions = Conditions { latency: 1, jitter: 0, loss: 0.0 };
+        let link = SimLink::new(conditions, 1);
+        let (mut end, _) = link.ends();
+        let mut session = Session::new(mix(), 0, 2);
+
+        for _ in 0..MAX_PREDICTION * 2 {
+            session.tick(0, &mut end).unwrap();
+        }
+        assert_eq!(session.tick(0, &mut end), Ok(Tick::Stalled));
+        assert!(session.frame <= MAX_PREDICTION);
+    }
+}
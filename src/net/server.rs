@@ -0,0 +1,323 @@
+use super::{
+    delta,
+    protocol::{Command, Message, Snapshot, MAX_INPUTS, VERSION},
+    transport::MAX_PACKET,
+    Replicated,
+};
+
+use std::{
+    collections::VecDeque,
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+// Snapshots kept to send deltas against. A client that has none of them
+// gets a whole snapshot.
+const HISTORY: usize = 32;
+
+// Inputs a client may be ahead of the game. Inputs arrive in bursts after a
+// hiccup, and applying one a tick would leave the client that much further
+// behind from then on, so the oldest are dropped instead.
+const MAX_QUEUED: usize = 4;
+
+// Ticks without hearing from a client before its player is given up.
+const TIMEOUT: u32 = 300;
+
+// A client playing one of the players.
+struct Remote {
+    addr: SocketAddr,
+    player: usize,
+    // The tick the client was last heard from.
+    heard: u32,
+    // Inputs received but not applied yet, and how many came before them.
+    queue: VecDeque<u8>,
+    applied: u32,
+    // The input applied last, held until the next one arrives.
+    buttons: u8,
+    // The newest snapshot the client has.
+    ack: Option<u32>,
+}
+
+/// Runs a game for clients over UDP. The server's game is the real one:
+/// clients send their inputs, the server applies them a tick at a time and
+/// sends every client a snapshot of the result each tick, as a delta from
+/// the newest snapshot the client has.
+pub struct Server<G> {
+    game: G,
+    socket: UdpSocket,
+    players: usize,
+    tick: u32,
+    clients: Vec<Remote>,
+    // Recent snapshots, oldest first.
+    history: VecDeque<(u32, Vec<u8>)>,
+    buf: Vec<u8>,
+}
+
+impl<G: Replicated> Server<G> {
+    /// Serves `game`, with up to `players` clients, on `socket`.
+    pub fn new(game: G, socket: UdpSocket, players: usize) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Server {
+            game,
+            socket,
+            players,
+            tick: 0,
+            clients: Vec::new(),
+            history: VecDeque::new(),
+            buf: vec![0; MAX_PACKET],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn game(&self) -> &G {
+        &self.game
+    }
+
+    /// Ticks simulated so far.
+    pub fn tick_count(&self) -> u32 {
+        self.tick
+    }
+
+    /// Clients playing.
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Takes in what clients sent, simulates a tick with their inputs and
+    /// sends them the result.
+    pub fn tick(&mut self) {
+        self.receive();
+
+        let tick = self.tick;
+        self.clients.retain(|client| tick - client.heard < TIMEOUT);
+
+        // Players without a client hold nothing.
+        let mut buttons = vec![0; self.players];
+        for client in self.clients.iter_mut() {
+            let stale = client.queue.len().saturating_sub(MAX_QUEUED);
+            client.queue.drain(..stale);
+            client.applied += stale as u32;
+            if let Some(next) = client.queue.pop_front() {
+                client.buttons = next;
+                client.applied += 1;
+            }
+            buttons[client.player] = client.buttons;
+        }
+
+        self.game.advance(&buttons, false);
+        self.tick += 1;
+
+        let mut snapshot = Vec::new();
+        self.game.encode(&mut snapshot);
+        self.history.push_back((self.tick, snapshot));
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+        self.send();
+    }
+
+    fn receive(&mut self) {
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                // Would block, or an error from an earlier send.
+                Err(_) => return,
+            };
+
+            match Message::decode(&self.buf[..len]) {
+                Some(Message::Hello { version }) if version == VERSION => {
+                    self.hello(from);
+                }
+                Some(Message::Hello { .. }) => {
+                    self.send_to(&Message::Reject { version: VERSION }, from);
+                }
+                Some(Message::Command(command)) => self.command(command, from),
+                _ => (),
+            }
+        }
+    }
+
+    // Gives a new client the first free player. Hellos are resent until
+    // answered, so one from a known client is answered again.
+    fn hello(&mut self, from: SocketAddr) {
+        let known = self.clients.iter().find(|client| client.addr == from);
+        let player = match known {
+            Some(client) => client.player,
+            None => {
+                let taken = |player| {
+                    self.clients.iter().any(|client| client.player == player)
+                };
+                let free = (0..self.players).find(|&player| !taken(player));
+                let player = match free {
+                    Some(player) => player,
+                    None => return self.send_to(&Message::Full, from),
+                };
+                self.clients.push(Remote {
+                    addr: from,
+                    player,
+                    heard: self.tick,
+                    queue: VecDeque::new(),
+                    applied: 0,
+                    buttons: 0,
+                    ack: None,
+                });
+                player
+            }
+        };
+        self.send_to(&Message::Joined { player: player as u8 }, from);
+    }
+
+    fn command(&mut self, command: Command, from: SocketAddr) {
+        let tick = self.tick;
+        let known = self.clients.iter_mut().find(|client| client.addr == from);
+        let client = match known {
+            Some(client) => client,
+            None => return,
+        };
+
+        client.heard = tick;
+        // Datagrams can arrive out of order.
+        if command.ack > client.ack {
+            client.ack = command.ack;
+        }
+
+        // Inputs the client gave up resending are filled in with the one
+        // before them. A client sends an input a tick and is given up after
+        // `TIMEOUT` ticks unheard, so inputs further ahead are bogus.
+        let mut next = client.applied + client.queue.len() as u32;
+        let limit = next.saturating_add(TIMEOUT + MAX_INPUTS as u32);
+        for (i, &buttons) in command.buttons.iter().enumerate() {
+            let seq = match command.start.checked_add(i as u32) {
+                Some(seq) if seq <= limit => seq,
+                _ => break,
+            };
+            while seq > next {
+                let last =
+                    client.queue.back().copied().unwrap_or(client.buttons);
+                client.queue.push_back(last);
+                next += 1;
+            }
+            if seq == next {
+                client.queue.push_back(buttons);
+                next += 1;
+            }
+        }
+    }
+
+    fn send(&self) {
+        let (tick, newest) = self.history.back().unwrap();
+
+        for client in self.clients.iter() {
+            let base = client.ack.and_then(|ack| {
+                self.history.iter().find(|&&(tick, _)| tick == ack)
+            });
+            let (base, data) = match base {
+                Some((tick, base)) => (Some(*tick), delta::diff(base, newest)),
+                None => (None, newest.clone()),
+            };
+
+            let snapshot = Snapshot {
+                tick: *tick,
+                base,
+                applied: client.applied,
+                data,
+            };
+            let packet = Message::Snapshot(snapshot).encode();
+            let _ = self.socket.send_to(&packet, client.addr);
+        }
+    }
+
+    // Datagrams are lost all the time anyway, so failing to send one is not
+    // an error.
+    fn send_to(&self, message: &Message, to: SocketAddr) {
+        let _ = self.socket.send_to(&message.encode(), to);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::game::{self, Action, Mode, Setup, State};
+
+    use std::{thread, time::Duration};
+
+    // A server for one player, and a socket to send it datagrams from.
+    fn serve() -> (Server<State>, UdpSocket) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let st = game::new_state(None, Setup { players: 1, mode: Mode::Coop });
+        let server = Server::new(st, socket, 1).unwrap();
+
+        let remote = UdpSocket::bind("127.0.0.1:0").unwrap();
+        remote.connect(server.local_addr().unwrap()).unwrap();
+        remote.set_nonblocking(true).unwrap();
+        (server, remote)
+    }
+
+    // Sends `packets` and ticks the server once they have had time to
+    // arrive.
+    fn deliver(
+        server: &mut Server<State>,
+        remote: &UdpSocket,
+        packets: &[Vec<u8>],
+    ) {
+        for packet in packets {
+            remote.send(packet).unwrap();
+        }
+        thread::sleep(Duration::from_millis(5));
+        server.tick();
+    }
+
+    fn command(start: u32, buttons: Vec<u8>) -> Vec<u8> {
+        Message::Command(Command { ack: None, start, buttons }).encode()
+    }
+
+    #[test]
+    fn garbage_datagrams_are_ignored() {
+        let (mut server, remote) = serve();
+        let hello = Message::Hello { version: VERSION }.encode();
+        // Only clients are sent snapshots.
+        let snapshot = Snapshot {
+            tick: 1,
+            base: None,
+            applied: 0,
+            data: vec![1, 2, 3],
+        };
+        let snapshot = Message::Snapshot(snapshot).encode();
+
+        deliver(&mut server, &remote, &[
+            Vec::new(),
+            b"garbage".to_vec(),
+            hello[..hello.len() - 1].to_vec(),
+            command(0, vec![1, 2])[..6].to_vec(),
+            snapshot,
+            vec![0xff; 2000],
+        ]);
+        assert_eq!(server.clients(), 0);
+        assert_eq!(server.tick_count(), 1);
+    }
+
+    #[test]
+    fn inputs_from_far_ahead_are_dropped() {
+        let (mut server, remote) = serve();
+        let hello = Message::Hello { version: VERSION }.encode();
+        deliver(&mut server, &remote, &[hello]);
+        assert_eq!(server.clients(), 1);
+
+        // Would overflow the sequence numbers, or fill the gap up to them
+        // with billions of inputs.
+        deliver(&mut server, &remote, &[
+            command(u32::MAX - 1, vec![1, 2, 3]),
+            command(1 << 30, vec![1]),
+        ]);
+        assert_eq!(server.clients(), 1);
+        assert!(server.clients[0].queue.len() <= MAX_QUEUED);
+
+        let thrust = command(0, vec![Action::Thrust.bit()]);
+        deliver(&mut server, &remote, &[thrust]);
+        assert_eq!(server.clients[0].buttons, Action::Thrust.bit());
+    }
+}
@@ -0,0 +1,105 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use std::{cell::RefCell, rc::Rc};
+
+use super::Transport;
+
+/// How badly a `SimLink` treats datagrams, in ticks of its clock.
+#[derive(Debug, Clone, Copy)]
+pub struct Conditions {
+    pub latency: u32,
+    /// Extra random delay of up to this many ticks, which reorders
+    /// datagrams.
+    pub jitter: u32,
+    /// Fraction of datagrams dropped.
+    pub loss: f32,
+}
+
+// Datagrams in flight to each end, with the tick they arrive on.
+struct Link {
+    now: u64,
+    rng: StdRng,
+    conditions: Conditions,
+    queues: [Vec<(u64, Vec<u8>)>; 2],
+}
+
+/// An in-process link between two `SimEnd`s with latency, jitter and loss,
+/// for testing without a network. Time only passes on `tick`, so runs are
+/// reproducible for a given seed.
+#[derive(Clone)]
+pub struct SimLink(Rc<RefCell<Link>>);
+
+impl SimLink {
+    pub fn new(conditions: Conditions, seed: u64) -> Self {
+        SimLink(Rc::new(RefCell::new(Link {
+            now: 0,
+            rng: StdRng::seed_from_u64(seed),
+            conditions,
+            queues: [Vec::new(), Vec::new()],
+        })))
+    }
+
+    /// The two ends of the link.
+    pub fn ends(&self) -> (SimEnd, SimEnd) {
+        (
+            SimEnd { link: self.clone(), side: 0 },
+            SimEnd { link: self.clone(), side: 1 },
+        )
+    }
+
+    /// Advances the link's clock by one tick.
+    pub fn tick(&self) {
+        self.0.borrow_mut().now += 1;
+    }
+}
+
+pub struct SimEnd {
+    link: SimLink,
+    side: usize,
+}
+
+impl Transport for SimEnd {
+    fn send(&mut self, packet: &[u8]) {
+        let mut link = self.link.0.borrow_mut();
+        let Conditions { latency, jitter, loss } = link.conditions;
+        if link.rng.gen::<f32>() < loss {
+            return;
+        }
+
+        let delay = latency + link.rng.gen_range(0, jitter + 1);
+        let arrival = link.now + delay as u64;
+        link.queues[1 - self.side].push((arrival, packet.to_vec()));
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        let mut link = self.link.0.borrow_mut();
+        let now = link.now;
+        let queue = &mut link.queues[self.side];
+        let next = queue.iter().position(|&(arrival, _)| arrival <= now)?;
+        Some(queue.remove(next).1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sim_link_delays_and_drops() {
+        let conditions = Conditions { latency: 2, jitter: 0, loss: 0.5 };
+        let link = SimLink::new(conditions, 7);
+        let (mut a, mut b) = link.ends();
+
+        for i in 0..100u8 {
+            a.send(&[i]);
+        }
+        link.tick();
+        assert!(b.recv().is_none());
+        link.tick();
+
+        let received: Vec<_> = std::iter::from_fn(|| b.recv()).collect();
+        let count = received.len();
+        assert!(count > 25 && count < 75, "{}", count);
+        assert!(a.recv().is_none());
+    }
+}
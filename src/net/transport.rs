@@ -0,0 +1,100 @@
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+// The largest UDP payload. Larger datagrams are truncated.
+pub const MAX_PACKET: usize = 65507;
+
+/// Unreliable, unordered datagrams to and from the other player.
+pub trait Transport {
+    /// Sends a datagram to the peer. It may arrive late, out of order or not
+    /// at all.
+    fn send(&mut self, packet: &[u8]);
+    /// The next datagram from the peer, if one has arrived.
+    fn recv(&mut self) -> Option<Vec<u8>>;
+}
+
+/// A UDP socket talking to a single peer. The host learns its peer from the
+/// first datagram it receives and ignores everyone else.
+pub struct Udp {
+    socket: UdpSocket,
+    peer: Option<SocketAddr>,
+    buf: Vec<u8>,
+}
+
+impl Udp {
+    /// Waits for a peer on `addr`.
+    pub fn host(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Udp::new(UdpSocket::bind(addr)?, None)
+    }
+
+    /// Talks to the host at `addr` from any local port.
+    pub fn join(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let peer = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to join")
+        })?;
+        let any = if peer.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+        Udp::new(UdpSocket::bind(any)?, Some(peer))
+    }
+
+    fn new(socket: UdpSocket, peer: Option<SocketAddr>) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Udp { socket, peer, buf: vec![0; MAX_PACKET] })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl Transport for Udp {
+    fn send(&mut self, packet: &[u8]) {
+        // Datagrams are lost all the time anyway, so failing to send one,
+        // e.g. because the peer's port is closed, is not an error.
+        if let Some(peer) = self.peer {
+            let _ = self.socket.send_to(packet, peer);
+        }
+    }
+
+    fn recv(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                // Would block, or an error from an earlier send.
+                Err(_) => return None,
+            };
+            match self.peer {
+                Some(peer) if peer != from => continue,
+                _ => self.peer = Some(from),
+            }
+            return Some(self.buf[..len].to_vec());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn udp_peers_talk_over_localhost() {
+        let mut host = Udp::host("127.0.0.1:0").unwrap();
+        let mut guest = Udp::join(host.local_addr().unwrap()).unwrap();
+
+        let receive = |transport: &mut Udp| {
+            for _ in 0..200 {
+                if let Some(packet) = transport.recv() {
+                    return packet;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            panic!("nothing arrived");
+        };
+
+        guest.send(b"hello");
+        assert_eq!(receive(&mut host), b"hello");
+        host.send(b"welcome");
+        assert_eq!(receive(&mut guest), b"welcome");
+    }
+}
@@ -0,0 +1,340 @@
+use crate::{
+    broadphase::Broadphase,
+    codec::{Encode, Reader},
+    ecs::{self, Entity, Transform, Velocity, World},
+};
+
+/// How the simulation moves a body. Everything is per tick, the fixed
+/// timestep the simulation runs at.
+#[derive(Debug, Clone, Copy)]
+pub struct Body {
+    pub mass: f32,
+    /// Fraction of the velocity lost every tick.
+    pub damping: f32,
+    /// Terminal velocity, in units per tick.
+    pub max_speed: f32,
+    /// Bounces off other elastic bodies, conserving momentum and energy.
+    pub elastic: bool,
+    /// Acceleration for the next step, cleared by it.
+    pub accel: [f32; 2],
+}
+
+impl Body {
+    pub fn new(mass: f32) -> Self {
+        Body {
+            mass,
+            damping: 0.0,
+            max_speed: f32::INFINITY,
+            elastic: false,
+            accel: [0.0, 0.0],
+        }
+    }
+}
+
+impl Encode for Body {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.mass.encode(out);
+        self.damping.encode(out);
+        self.max_speed.encode(out);
+        self.elastic.encode(out);
+        self.accel.encode(out);
+    }
+
+    fn decode(r: &mut Reader) -> Option<Self> {
+        Some(Body {
+            mass: r.read()?,
+            damping: r.read()?,
+            max_speed: r.read()?,
+            elastic: r.read()?,
+            accel: r.read()?,
+        })
+    }
+}
+
+/// Mass of something drawn with a mesh of `area` at `scale`, with a
+/// density of 1.
+pub fn mass(area: f32, scale: f32) -> f32 {
+    area * scale * scale
+}
+
+/// Advances bodies by `dt` ticks with semi-implicit Euler: accelerations,
+/// damping and the speed limit update velocities first, then positions
+/// move by the new velocities. With `bounce`, overlapping elastic bodies
+/// then bounce off each other, the shorter way around a world spanning
+/// `-half..half`.
+pub fn step(world: &mut World, dt: f32, half: f32, bounce: bool) {
+    for (entity, body) in world.bodies.iter_mut() {
+        if let Some(Velocity(vel)) = world.velocities.get_mut(entity) {
+            let keep = (1.0 - body.damping).powf(dt);
+            for (v, a) in vel.iter_mut().zip(body.accel.iter()) {
+                *v = (*v + a * dt) * keep;
+            }
+
+            let speed = (vel[0] * vel[0] + vel[1] * vel[1]).sqrt();
+            if speed > body.max_speed {
+                let scale = body.max_speed / speed;
+                vel[0] *= scale;
+                vel[1] *= scale;
+            }
+        }
+        body.accel = [0.0, 0.0];
+    }
+
+    ecs::integrate(world, dt);
+
+    if bounce {
+        collide(world, half);
+    }
+}
+
+// An elastic body's state while resolving collisions.
+struct Contact {
+    entity: Entity,
+    inv_mass: f32,
+    transform: Transform,
+    vel: [f32; 2],
+}
+
+// Resolves every overlapping pair of elastic bodies, treating them as
+// circles of radius `scale`. Each pair exchanges an impulse along the line
+// between their centres and is pushed apart in proportion to their inverse
+// masses. Only the broadphase's candidates are tested.
+fn collide(world: &mut World, half: f32) {
+    let transforms = &world.transforms;
+    let velocities = &world.velocities;
+    let mut contacts: Vec<Contact> = world.bodies
+        .iter()
+        .filter(|(_, body)| body.elastic && body.mass > 0.0)
+        .filter_map(|(entity, body)| {
+            Some(Contact {
+                entity,
+                inv_mass: 1.0 / body.mass,
+                transform: *transforms.get(entity)?,
+                vel: velocities.get(entity)?.0,
+            })
+        })
+        .collect();
+
+    let circles: Vec<_> = contacts
+        .iter()
+        .map(|c| (c.transform.pos, c.transform.scale))
+        .collect();
+    let mut pairs = Vec::new();
+    Broadphase::new().pairs(&circles, half, &mut pairs);
+
+    for (i, j) in pairs {
+        // Pairs are ordered, so `a` comes before `b`.
+        let (done, rest) = contacts.split_at_mut(j);
+        resolve(&mut done[i], &mut rest[0], half);
+    }
+
+    for contact in contacts {
+        *world.transforms.get_mut(contact.entity).unwrap() = contact.transform;
+        world.velocities.get_mut(contact.entity).unwrap().0 = contact.vel;
+    }
+}
+
+/// Bounces two bodies off each other if they overlap, like elastic bodies
+/// do in `step`, whether or not they are elastic themselves.
+pub fn bounce(world: &mut World, a: Entity, b: Entity, half: f32) {
+    let contact = |entity| {
+        let body = world.bodies.get(entity)?;
+        if body.mass <= 0.0 {
+            return None;
+        }
+        Some(Contact {
+            entity,
+            inv_mass: 1.0 / body.mass,
+            transform: *world.transforms.get(entity)?,
+            vel: world.velocities.get(entity)?.0,
+        })
+    };
+    let (mut a, mut b) = match (contact(a), contact(b)) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return,
+    };
+
+    resolve(&mut a, &mut b, half);
+
+    for contact in [a, b] {
+        *world.transforms.get_mut(contact.entity).unwrap() = contact.transform;
+        world.velocities.get_mut(contact.entity).unwrap().0 = contact.vel;
+    }
+}
+
+// Separates a pair of overlapping bodies and exchanges an impulse between
+// them if they are approaching each other.
+fn resolve(a: &mut Contact, b: &mut Contact, half: f32) {
+    let dx = crate::wrap(b.transform.pos[0] - a.transform.pos[0], half);
+    let dy = crate::wrap(b.transform.pos[1] - a.transform.pos[1], half);
+    let dist = (dx * dx + dy * dy).sqrt();
+    let reach = a.transform.scale + b.transform.scale;
+    if dist >= reach || dist == 0.0 {
+        return;
+    }
+
+    let n = [dx / dist, dy / dist];
+    let inv_mass = a.inv_mass + b.inv_mass;
+
+    let overlap = (reach - dist) / inv_mass;
+    for (k, n) in n.iter().enumerate() {
+        a.transform.pos[k] -= n * overlap * a.inv_mass;
+        b.transform.pos[k] += n * overlap * b.inv_mass;
+    }
+
+    // Only bodies moving towards each other bounce, or pairs that are still
+    // overlapping after a bounce would stick together.
+    let approach = (a.vel[0] - b.vel[0]) * n[0] + (a.vel[1] - b.vel[1]) * n[1];
+    if approach <= 0.0 {
+        return;
+    }
+
+    let impulse = 2.0 * approach / inv_mass;
+    for (k, n) in n.iter().enumerate() {
+        a.vel[k] -= n * impulse * a.inv_mass;
+        b.vel[k] += n * impulse * b.inv_mass;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn(
+        world: &mut World,
+        pos: [f32; 2],
+        vel: [f32; 2],
+        mass: f32,
+    ) -> Entity {
+        let entity = world.spawn();
+        let transform = Transform { pos, angle: 0.0, scale: 0.1 };
+        world.transforms.insert(entity, transform);
+        world.velocities.insert(entity, Velocity(vel));
+        world.bodies.insert(entity, Body { elastic: true, ..Body::new(mass) });
+        entity
+    }
+
+    fn momentum(world: &World) -> [f32; 2] {
+        let mut total = [0.0, 0.0];
+        for (entity, body) in world.bodies.iter() {
+            let Velocity(vel) = world.velocities.get(entity).unwrap();
+            total[0] += vel[0] * body.mass;
+            total[1] += vel[1] * body.mass;
+        }
+        total
+    }
+
+    fn energy(world: &World) -> f32 {
+        world.bodies
+            .iter()
+            .map(|(entity, body)| {
+                let Velocity(vel) = world.velocities.get(entity).unwrap();
+                0.5 * body.mass * (vel[0] * vel[0] + vel[1] * vel[1])
+            })
+            .sum()
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn head_on_bounce_conserves_momentum_and_energy() {
+        let mut world = World::new();
+        let a = spawn(&mut world, [-0.05, 0.0], [0.01, 0.0], 1.0);
+        let b = spawn(&mut world, [0.05, 0.0], [-0.02, 0.0], 3.0);
+        let (p, e) = (momentum(&world), energy(&world));
+
+        step(&mut world, 1.0, 1.0, true);
+
+        assert_close(momentum(&world)[0], p[0]);
+        assert_close(momentum(&world)[1], p[1]);
+        assert_close(energy(&world), e);
+        // The light one bounced back, the heavy one only slowed down.
+        assert_close(world.velocities.get(a).unwrap().0[0], -0.035);
+        assert_close(world.velocities.get(b).unwrap().0[0], -0.005);
+    }
+
+    #[test]
+    fn glancing_bounce_conserves_momentum_and_energy() {
+        let mut world = World::new();
+        spawn(&mut world, [0.0, 0.0], [0.02, 0.005], 2.0);
+        spawn(&mut world, [0.15, 0.08], [-0.01, 0.0], 0.5);
+        let (p, e) = (momentum(&world), energy(&world));
+
+        step(&mut world, 1.0, 1.0, true);
+
+        assert_close(momentum(&world)[0], p[0]);
+        assert_close(momentum(&world)[1], p[1]);
+        assert_close(energy(&world), e);
+    }
+
+    #[test]
+    fn bounces_across_the_wrap_conserve_momentum() {
+        let mut world = World::new();
+        let a = spawn(&mut world, [0.95, 0.0], [0.01, 0.0], 1.0);
+        spawn(&mut world, [-0.95, 0.0], [-0.01, 0.0], 1.0);
+        let p = momentum(&world);
+
+        step(&mut world, 1.0, 1.0, true);
+
+        assert_close(momentum(&world)[0], p[0]);
+        assert!(world.velocities.get(a).unwrap().0[0] < 0.0);
+    }
+
+    #[test]
+    fn crowded_world_conserves_momentum() {
+        let mut world = World::new();
+        for i in 0..20 {
+            let t = i as f32;
+            spawn(
+                &mut world,
+                [(t * 0.37).sin() * 0.9, (t * 0.73).cos() * 0.9],
+                [(t * 1.3).cos() * 0.01, (t * 2.1).sin() * 0.01],
+                0.5 + (i % 4) as f32,
+            );
+        }
+        let p = momentum(&world);
+
+        for _ in 0..600 {
+            step(&mut world, 1.0, 1.0, true);
+            ecs::wrap(&mut world, 1.0);
+        }
+
+        let after = momentum(&world);
+        assert!((after[0] - p[0]).abs() < 1e-4);
+        assert!((after[1] - p[1]).abs() < 1e-4);
+    }
+
+    #[test]
+    fn bounce_works_on_inelastic_bodies() {
+        let mut world = World::new();
+        let a = spawn(&mut world, [-0.05, 0.0], [0.01, 0.0], 1.0);
+        let b = spawn(&mut world, [0.05, 0.0], [-0.02, 0.0], 3.0);
+        world.bodies.get_mut(a).unwrap().elastic = false;
+        world.bodies.get_mut(b).unwrap().elastic = false;
+        let (p, e) = (momentum(&world), energy(&world));
+
+        bounce(&mut world, a, b, 1.0);
+
+        assert_close(momentum(&world)[0], p[0]);
+        assert_close(energy(&world), e);
+        assert_close(world.velocities.get(a).unwrap().0[0], -0.035);
+    }
+
+    #[test]
+    fn damping_and_max_speed_slow_bodies_down() {
+        let mut world = World::new();
+        let entity = spawn(&mut world, [0.0, 0.0], [0.0, 0.0], 1.0);
+        let body = world.bodies.get_mut(entity).unwrap();
+        body.damping = 0.1;
+        body.max_speed = 0.05;
+        body.accel = [1.0, 0.0];
+
+        step(&mut world, 1.0, 1.0, false);
+        assert_close(world.velocities.get(entity).unwrap().0[0], 0.05);
+
+        step(&mut world, 1.0, 1.0, false);
+        assert_close(world.velocities.get(entity).unwrap().0[0], 0.045);
+    }
+}
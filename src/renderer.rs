@@ -10,11 +10,21 @@ use vulkano::{
         Subpass
     },
     format::{ Format, ClearValue },
-    image::{ ImageUsage, SwapchainImage, AttachmentImage },
-    instance::{ Instance, PhysicalDevice },
+    image::{
+        ImageAccess,
+        ImageUsage,
+        ImageViewAccess,
+        SwapchainImage,
+        AttachmentImage,
+    },
+    instance::{ Instance, InstanceExtensions, PhysicalDevice },
+    descriptor::descriptor::ShaderStages,
     pipeline::{
+        depth_stencil::{Compare, DepthStencil},
         viewport::Viewport,
         GraphicsPipeline,
+        GraphicsPipelineCreationError,
+        shader::{GraphicsEntryPoint, GraphicsShaderType, ShaderModule},
         vertex::SingleBufferDefinition,
     },
     swapchain,
@@ -34,89 +44,323 @@ use vulkano::{
 
 use vulkano_win::VkSurfaceBuild;
 use winit::{
+    dpi::PhysicalSize,
     event_loop::EventLoop,
     window::{Window, WindowBuilder},
 };
 
-use std::sync::Arc;
+use image::RgbaImage;
+use shaderc::ShaderKind;
+
+use std::{ffi::CStr, ops::Range, sync::Arc, time::Instant};
+
+
+mod assets;
+use assets::{Asset, Assets};
+
+mod background;
+use background::Background;
+
+mod camera;
+pub use camera::{Camera, View};
 
+mod compute;
+pub use compute::Compute;
+
+pub mod display;
+pub use display::{Display, DisplayMode};
+
+mod frames;
+use frames::Frames;
+
+mod layers;
+use layers::CachedLayer;
+pub use frames::MAX_FRAMES_IN_FLIGHT;
 
 mod mesh;
-use mesh::{ship_mesh, asteroid_mesh};
+use mesh::Vertex;
+pub use mesh::{MeshId, MESHES};
+
+mod particles;
+use particles::Particles;
+pub use particles::{Emitter, Particle};
+
+mod sprites;
+use sprites::Sprites;
+pub use sprites::{Atlas, Sprite};
+
+mod wrap;
+
+mod text;
+use text::Text;
+pub use text::Align;
+
+mod unsynced;
+use unsynced::Unsynced;
+
+/// Debug shapes, drawable from anywhere on the render thread.
+pub mod debug;
+use debug::DebugDraw;
+
+mod gui;
+use gui::Gui;
+
+/// CPU and GPU frame timings, drawn as a graph and exportable as traces.
+pub mod profile;
+use profile::GpuTimer;
+
+pub use assets::asset_dir;
+
+/// The area of each built in mesh, indexed by `MeshId`.
+pub fn mesh_areas() -> Vec<f32> {
+    mesh::builtin_meshes().iter().map(|m| mesh::area(m)).collect()
+}
+
+const TITLE: &str = "vulkano-test";
+
+// Format of the image drawn to by headless renderers. It is sRGB like a
+// typical swapchain, so snapshots look the same as the window.
+const HEADLESS_FORMAT: Format = Format::R8G8B8A8Srgb;
+
+/// Renderer options that can be changed while running. They are applied at
+/// the start of the next frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    /// Samples per pixel, 1 disables multisampling. Must be one of
+    /// `Renderer::sample_counts`.
+    pub msaa: u32,
+    /// Must be one of `Renderer::present_modes`. Ignored when headless.
+    pub present_mode: PresentMode,
+    /// The background color, in linear RGB.
+    pub clear_color: [f32; 3],
+    /// How many frames the CPU may record ahead of the GPU, from 1 to
+    /// `MAX_FRAMES_IN_FLIGHT`. More hides stalls at the cost of latency.
+    pub frames_in_flight: u32,
+    /// Record the world's layers on worker threads.
+    pub parallel_recording: bool,
+    /// Use a depth buffer, so that instance layers apply across mesh groups
+    /// and sprites. Without it, things are drawn in the order they are
+    /// recorded.
+    pub depth: bool,
+    /// Windowed or fullscreen, and where. Ignored when headless.
+    pub display: Display,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            msaa: 4,
+            present_mode: PresentMode::Fifo,
+            clear_color: [0.0, 0.0, 0.0],
+            frames_in_flight: 2,
+            parallel_recording: true,
+            depth: true,
+            display: Display::default(),
+        }
+    }
+}
 
 mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
-        path: "src/shaders/vertex.glsl"
+        path: "assets/shaders/vertex.glsl"
     }
 }
 
 mod fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        path: "src/shaders/fragment.glsl"
+        path: "assets/shaders/fragment.glsl"
     }
 }
 
-fn mk_shaders(device: Arc<Device>) -> (vs::Shader, fs::Shader) {
+fn mk_shaders(device: Arc<Device>) -> (Arc<ShaderModule>, Arc<ShaderModule>) {
     let vs = vs::Shader::load(device.clone()).unwrap();
     let fs = fs::Shader::load(device).unwrap();
 
-    (vs, fs)
+    (vs.module().clone(), fs.module().clone())
+}
+
+// Shaders that are reloaded at runtime are not reflected by
+// `vulkano_shaders`, so their entry points reuse the interface generated for
+// the built-in versions. Changing the inputs, outputs or descriptors of a
+// shader therefore still requires a rebuild.
+fn vs_entry_point(module: &ShaderModule) ->
+    GraphicsEntryPoint<'_, (), vs::MainInput, vs::MainOutput, vs::Layout>
+{
+    unsafe {
+        module.graphics_entry_point(
+            CStr::from_bytes_with_nul_unchecked(b"main\0"),
+            vs::MainInput,
+            vs::MainOutput,
+            vs::Layout(ShaderStages { vertex: true, ..ShaderStages::none() }),
+            GraphicsShaderType::Vertex,
+        )
+    }
+}
+
+fn fs_entry_point(module: &ShaderModule) ->
+    GraphicsEntryPoint<'_, (), fs::MainInput, fs::MainOutput, fs::Layout>
+{
+    unsafe {
+        module.graphics_entry_point(
+            CStr::from_bytes_with_nul_unchecked(b"main\0"),
+            fs::MainInput,
+            fs::MainOutput,
+            fs::Layout(ShaderStages { fragment: true, ..ShaderStages::none() }),
+            GraphicsShaderType::Fragment,
+        )
+    }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct InstanceData {
     pub pos_offset: [f32; 2],
     pub angle: f32,
     pub scale: f32,
+    /// Higher layers are drawn on top of lower ones, from `-MAX_LAYER` to
+    /// `MAX_LAYER`. Particles are on layer -1, just behind the default one.
+    pub layer: i32,
+    /// Draw a textured quad instead of the group's mesh.
+    pub sprite: Option<Sprite>,
+    /// Multiplies the mesh's white, or the sprite's texture.
+    pub color: [f32; 4],
 }
 
-#[derive(Default, Debug, Clone)]
+impl Default for InstanceData {
+    fn default() -> Self {
+        InstanceData {
+            pos_offset: [0.0, 0.0],
+            angle: 0.0,
+            scale: 0.0,
+            layer: 0,
+            sprite: None,
+            color: [1.0, 1.0, 1.0, 1.0],
+        }
+    }
+}
+
+/// The highest layer an instance can be on, and the lowest negated.
+pub const MAX_LAYER: i32 = 127;
+
+// The depth a layer is drawn at. Depths are compared with `LessOrEqual` and
+// cleared to 1, so higher layers get lower depths.
+fn layer_depth(layer: i32) -> f32 {
+    0.5 - layer.clamp(-MAX_LAYER, MAX_LAYER) as f32 / 256.0
+}
+
+// Depth testing for pipelines that draw the world. Opaque things write
+// depth, translucent ones are only tested against it and are sorted
+// instead. Without a depth attachment the state has to be disabled.
+fn depth_stencil(
+    render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    write: bool,
+) -> DepthStencil {
+    if !Subpass::from(render_pass.clone(), 0).unwrap().has_depth() {
+        return DepthStencil::disabled();
+    }
+
+    DepthStencil {
+        depth_write: write,
+        depth_compare: Compare::LessOrEqual,
+        ..DepthStencil::disabled()
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy)]
 pub struct InstVert {
     pos: [f32; 2],
     pos_offset: [f32; 2],
     angle: f32,
     scale: f32,
+    depth: f32,
+    color: [f32; 4],
 }
 
-vulkano::impl_vertex!(InstVert, pos, pos_offset, angle, scale);
+vulkano::impl_vertex!(InstVert, pos, pos_offset, angle, scale, depth, color);
+
+/// Fills `vec` with a copy of each group's mesh per instance, and returns
+/// the range of `vec` each group ended up in.
+pub fn mk_inst_verts(
+    meshes: &[Vec<Vertex>],
+    data: &[Vec<InstanceData>],
+    vec: &mut Vec<InstVert>,
+) -> Vec<Range<usize>> {
+    vec.clear();
+    let mut groups = Vec::new();
+
+    for (mesh, insts) in meshes.iter().zip(data.iter()) {
+        let start = vec.len();
+        for inst in insts.iter().filter(|inst| inst.sprite.is_none()) {
+            for vert in mesh.iter() {
+                vec.push(InstVert {
+                    pos: vert.pos,
+                    pos_offset: inst.pos_offset,
+                    angle: inst.angle,
+                    scale: inst.scale,
+                    depth: layer_depth(inst.layer),
+                    color: inst.color,
+                });
+            }
+        }
+        groups.push(start..vec.len());
+    }
+
+    groups
+}
 
-pub fn mk_inst_buf(device: Arc<Device>, data: Vec<Vec<InstanceData>>) ->
-    Arc<CpuBuf<[InstVert]>>
+/// Where frames are drawn to.
+pub enum Target {
+    /// A window, presented through a swapchain.
+    Window {
+        surface: Arc<Surface<Window>>,
+        swapchain: Arc<Swapchain<Window>>,
+        images: Vec<Arc<SwapchainImage<Window>>>,
+    },
+    /// A single offscreen image that is read back with `Renderer::snapshot`,
+    /// so frames can be checked without a window.
+    Headless {
+        image: Arc<AttachmentImage>,
+    },
+}
 
-{
-    let mut vec = Vec::new();
-
-    for inst in data[0].iter() {
-        for vert in ship_mesh().iter() {
-            vec.push(InstVert {
-                pos: vert.pos,
-                pos_offset: inst.pos_offset,
-                angle: inst.angle,
-                scale: inst.scale,
-            });
+impl Target {
+    fn format(&self) -> Format {
+        match self {
+            Target::Window { swapchain, .. } => swapchain.format(),
+            Target::Headless { .. } => HEADLESS_FORMAT,
         }
     }
 
-    let asteroid = asteroid_mesh();
-    for inst in data[1].iter() {
-        for vert in asteroid.iter() {
-            vec.push(InstVert {
-                pos: vert.pos,
-                pos_offset: inst.pos_offset,
-                angle: inst.angle,
-                scale: inst.scale,
-            });
+    fn framebuffers(
+        &self,
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        samples: u32,
+        depth: bool,
+        dynamic_state: &mut DynamicState,
+    ) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
+        match self {
+            Target::Window { images, .. } => window_size_dependent_setup(
+                device,
+                self.format(),
+                samples,
+                depth,
+                images,
+                render_pass,
+                dynamic_state,
+            ),
+            Target::Headless { image } => window_size_dependent_setup(
+                device,
+                self.format(),
+                samples,
+                depth,
+                std::slice::from_ref(image),
+                render_pass,
+                dynamic_state,
+            ),
         }
     }
-
-    CpuBuf::from_iter(
-        device,
-        BufferUsage::all(),
-        false,
-        vec.into_iter(),
-    ).unwrap()
 }
 
 pub struct Renderer {
@@ -124,13 +368,42 @@ pub struct Renderer {
     pub phy_index: usize,
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
-    pub surface: Arc<Surface<Window>>,
-    pub swapchain: Arc<Swapchain<Window>>,
-    pub images: Vec<Arc<SwapchainImage<Window>>>,
+    pub target: Target,
     pub render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
-    pub vs: vs::Shader,
-    pub fs: fs::Shader,
+    pub vs: Arc<ShaderModule>,
+    pub fs: Arc<ShaderModule>,
     pub pipeline: MyPipeline,
+    pub meshes: Vec<Vec<Vertex>>,
+    /// Bounding radius of each mesh, used to find instances that cross the
+    /// edge of the world.
+    pub radii: Vec<f32>,
+    pub assets: Option<Assets>,
+    pub compute: Compute,
+    pub background: Background,
+    background_layer: CachedLayer,
+    pub particles: Particles,
+    pub sprites: Sprites,
+    pub text: Text,
+    pub debug: DebugDraw,
+    pub gui: Gui,
+    frames: Frames,
+    // Kept between frames to avoid reallocating it.
+    inst_verts: Vec<InstVert>,
+    // None when the queue cannot write timestamps.
+    gpu_timer: Option<GpuTimer>,
+    pub settings: Settings,
+    /// The camera to draw the world with this frame.
+    pub view: View,
+    /// Half the width of the square world, which wraps around at
+    /// `-world..world`. Particles wrap at its edges and instances crossing
+    /// them are drawn on both sides.
+    pub world: f32,
+    // The settings the current render pass and swapchain were made with.
+    applied: Settings,
+    /// Why assets failed to reload in the last batch of changes, shown until
+    /// a reload succeeds.
+    pub asset_error: Option<String>,
+    pub started: Instant,
     pub dynamic_state: DynamicState,
     pub framebuffers: Vec<Arc<dyn FramebufferAbstract + Send + Sync>>,
     pub recreate_swapchain: bool,
@@ -138,7 +411,8 @@ pub struct Renderer {
 }
 
 impl Renderer {
-    pub fn new(event_loop: &EventLoop<()>) -> Self {
+    /// Opens a window shown as `display` says.
+    pub fn new(event_loop: &EventLoop<()>, display: Display) -> Self {
         let instance = {
             let extensions = vulkano_win::required_extensions();
             Instance::new(None, &extensions, None).unwrap()
@@ -149,10 +423,18 @@ impl Renderer {
             .expect("No available device");
 
         let surface = WindowBuilder::new()
+            .with_title(TITLE)
+            .with_inner_size(
+                PhysicalSize::new(display.size[0], display.size[1]),
+            )
             .build_vk_surface(event_loop, instance.clone())
             .unwrap();
+        let window = surface.window();
+        display.place(window);
+        window.set_fullscreen(display.fullscreen(window));
 
-        let (device, queue) = mk_device(physical, &surface);
+        let (device, queue, compute_queue) =
+            mk_device(physical, Some(&surface));
 
         // Before we can draw on the surface, we have to create what is called a
         // swapchain. Creating a swapchain allocates the color buffers that will
@@ -162,8 +444,65 @@ impl Renderer {
             physical,
             surface.clone(),
             device.clone(),
-            queue.clone()
+            queue.clone(),
+            Settings::default().present_mode,
+            display.exclusive(),
+            None,
+        ).unwrap();
+
+        let target = Target::Window { surface, swapchain, images };
+
+        let mut renderer = Renderer::with_target(
+            instance,
+            device,
+            queue,
+            compute_queue,
+            target,
         );
+        renderer.settings.display = display;
+        renderer.applied.display = display;
+        renderer
+    }
+
+    /// Creates a renderer without a window that draws into an offscreen image
+    /// of the given size. Use `snapshot` to read back the last frame.
+    pub fn headless(dimensions: [u32; 2]) -> Self {
+        let instance = Instance::new(
+            None,
+            &InstanceExtensions::none(),
+            None,
+        ).unwrap();
+
+        let physical = PhysicalDevice::enumerate(&instance)
+            .next()
+            .expect("No available device");
+
+        let (device, queue, compute_queue) = mk_device(physical, None);
+
+        let image = AttachmentImage::with_usage(
+            device.clone(),
+            dimensions,
+            HEADLESS_FORMAT,
+            ImageUsage {
+                color_attachment: true,
+                transfer_source: true,
+                ..ImageUsage::none()
+            },
+        ).unwrap();
+
+        let target = Target::Headless { image };
+
+        Renderer::with_target(instance, device, queue, compute_queue, target)
+    }
+
+    fn with_target(
+        instance: Arc<Instance>,
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        compute_queue: Arc<Queue>,
+        target: Target,
+    ) -> Self {
+        let physical = device.physical_device();
 
         // At this point, OpenGL initialization would be finished. However in
         // Vulkan it is not. OpenGL implicitly does a lot of computation
@@ -173,7 +512,13 @@ impl Renderer {
         // describes where the output of the graphics pipeline will go. It
         // describes the layout of the images where the colors, depth and/or
         // stencil information will be written.
-        let render_pass = mk_render_pass(device.clone(), swapchain.clone());
+        let settings = Settings::default();
+        let render_pass = mk_render_pass(
+            device.clone(),
+            target.format(),
+            settings.msaa,
+            settings.depth,
+        );
 
         let (vs, fs) = mk_shaders(device.clone());
 
@@ -184,8 +529,57 @@ impl Renderer {
             render_pass.clone(),
             &vs,
             &fs
+        ).unwrap();
+
+        let meshes = mesh::builtin_meshes();
+        let radii = meshes.iter().map(|m| mesh::bounding_radius(m)).collect();
+
+        // The fractal background is rendered by a compute shader, possibly on
+        // a different queue family than the one drawing it. Vulkan rejects
+        // duplicate families when sharing images, so only list it once.
+        let mut families = vec![queue.family()];
+        if compute_queue.family().id() != queue.family().id() {
+            families.push(compute_queue.family());
+        }
+        let compute = Compute::new(
+            device.clone(),
+            compute_queue.clone(),
+            families,
+        );
+        let background = Background::new(
+            device.clone(),
+            render_pass.clone(),
+            compute.fractal.clone(),
         );
 
+        let particles = Particles::new(
+            device.clone(),
+            &queue,
+            render_pass.clone(),
+        );
+
+        let sprites = Sprites::new(
+            device.clone(),
+            queue.clone(),
+            render_pass.clone(),
+        );
+
+        let text = Text::new(device.clone(), render_pass.clone());
+        let debug = DebugDraw::new(device.clone(), render_pass.clone());
+        let gui = Gui::new(device.clone(), queue.clone(), render_pass.clone());
+        let gpu_timer = GpuTimer::new(queue.clone());
+        let frames = Frames::new(device.clone(), settings.frames_in_flight);
+
+        // Hot reloading is a development aid, so carry on without it when the
+        // assets directory is missing, e.g. when the binary has been moved.
+        let assets = match Assets::watch(assets::asset_dir()) {
+            Ok(assets) => Some(assets),
+            Err(e) => {
+                println!("Not watching assets: {:?}", e);
+                None
+            }
+        };
+
         // Dynamic viewports allow us to recreate just the viewport when the
         // window is resized, otherwise we would have to recreate the whole
         // pipeline.
@@ -197,30 +591,49 @@ impl Renderer {
         //
         // Since we need to draw to multiple images, we are going to create a
         // different framebuffer for each image.
-        let framebuffers = window_size_dependent_setup(
+        let framebuffers = target.framebuffers(
             device.clone(),
-            swapchain.format(),
-            &images,
             render_pass.clone(),
+            settings.msaa,
+            settings.depth,
             &mut dynamic_state
         );
 
         let phy_index = physical.index();
         let recreate_swapchain = false;
         let previous_frame_end = Some(sync::now(device.clone()).boxed());
+        let started = Instant::now();
 
         Renderer {
             instance,
             phy_index,
             device,
             queue,
-            surface,
-            swapchain,
-            images,
+            target,
             render_pass,
             vs,
             fs,
             pipeline,
+            meshes,
+            radii,
+            assets,
+            compute,
+            background,
+            background_layer: CachedLayer::new(),
+            particles,
+            sprites,
+            text,
+            debug,
+            gui,
+            frames,
+            inst_verts: Vec::new(),
+            gpu_timer,
+            settings,
+            view: View::default(),
+            world: 1.0,
+            applied: settings,
+            asset_error: None,
+            started,
             dynamic_state,
             framebuffers,
             recreate_swapchain,
@@ -228,18 +641,179 @@ impl Renderer {
         }
     }
 
-    #[allow(dead_code)]
     pub fn physical(&self) -> PhysicalDevice<'_> {
         PhysicalDevice::from_index(&self.instance, self.phy_index)
             .expect("Unable to find physical device")
     }
 
+    /// The window drawn to, if any.
+    pub fn window(&self) -> Option<&Window> {
+        match &self.target {
+            Target::Window { surface, .. } => Some(surface.window()),
+            Target::Headless { .. } => None,
+        }
+    }
+
+    /// Sample counts that `Settings::msaa` can be set to.
+    pub fn sample_counts(&self) -> Vec<u32> {
+        // The flags have the same values as the counts they stand for.
+        let supported = self.physical()
+            .limits()
+            .framebuffer_color_sample_counts();
+
+        [1, 2, 4, 8, 16]
+            .iter()
+            .cloned()
+            .filter(|samples| supported & samples != 0)
+            .collect()
+    }
+
+    /// Present modes that `Settings::present_mode` can be set to.
+    pub fn present_modes(&self) -> Vec<PresentMode> {
+        match &self.target {
+            Target::Window { surface, .. } => surface
+                .capabilities(self.physical())
+                .unwrap()
+                .present_modes
+                .iter()
+                .collect(),
+            Target::Headless { .. } => Vec::new(),
+        }
+    }
+
+    /// Remembers where the window is in `settings.display`, to open it there
+    /// next time. Call when it's moved or resized.
+    pub fn track_window(&mut self) {
+        if let Target::Window { surface, .. } = &self.target {
+            self.settings.display.track(surface.window());
+        }
+    }
+
+    /// Whether the window is minimized, which leaves nothing to draw on.
+    pub fn minimized(&self) -> bool {
+        self.window().is_some_and(|window| {
+            let size: [u32; 2] = window.inner_size().into();
+            size.contains(&0)
+        })
+    }
+
+    /// Starts a GUI frame covering the whole target. Add widgets to the
+    /// returned context, then call `gui.end_frame` before `redraw`.
+    pub fn gui_frame(&mut self) -> egui::CtxRef {
+        let viewport = &self.dynamic_state.viewports.as_ref().unwrap()[0];
+        let size = viewport.dimensions;
+        let scale = self.window().map_or(1.0, |w| w.scale_factor() as f32);
+
+        self.gui.begin_frame(size, scale)
+    }
+
+    // Rebuilds whatever depends on settings that changed since the last
+    // frame. The swapchain itself is rebuilt by `recreate_swapchain`.
+    fn apply_settings(&mut self) {
+        if self.settings.present_mode != self.applied.present_mode {
+            self.recreate_swapchain = true;
+        }
+
+        let display = self.settings.display;
+        if !display.same_screen(&self.applied.display) {
+            if display.mode != DisplayMode::Windowed {
+                self.settings.display.fullscreen = display.mode;
+            }
+            if let Some(window) = self.window() {
+                window.set_fullscreen(display.fullscreen(window));
+            }
+            // Resizes aren't always reported when going fullscreen, and a
+            // swapchain that may be exclusive has to be made anew.
+            self.recreate_swapchain = true;
+        }
+        self.applied.display = self.settings.display;
+
+        if self.settings.frames_in_flight != self.applied.frames_in_flight {
+            self.frames.resize(self.settings.frames_in_flight);
+            self.applied.frames_in_flight = self.settings.frames_in_flight;
+        }
+
+        if self.settings.msaa == self.applied.msaa
+            && self.settings.depth == self.applied.depth
+        {
+            return;
+        }
+
+        // Every pipeline has to be rebuilt for a render pass with a
+        // different sample count or attachments.
+        self.render_pass = mk_render_pass(
+            self.device.clone(),
+            self.target.format(),
+            self.settings.msaa,
+            self.settings.depth,
+        );
+        self.pipeline = mk_pipeline(
+            self.device.clone(),
+            self.render_pass.clone(),
+            &self.vs,
+            &self.fs,
+        ).unwrap();
+        self.background.set_render_pass(self.render_pass.clone());
+        self.particles.set_render_pass(self.render_pass.clone());
+        self.sprites.set_render_pass(self.render_pass.clone());
+        self.text.set_render_pass(self.render_pass.clone());
+        self.debug.set_render_pass(self.render_pass.clone());
+        self.gui.set_render_pass(self.render_pass.clone());
+
+        self.framebuffers = self.target.framebuffers(
+            self.device.clone(),
+            self.render_pass.clone(),
+            self.settings.msaa,
+            self.settings.depth,
+            &mut self.dynamic_state,
+        );
+        self.background_layer.invalidate();
+
+        self.applied.msaa = self.settings.msaa;
+        self.applied.depth = self.settings.depth;
+    }
+
     pub fn recreate_swapchain(&mut self) {
-        // Get the new dimensions of the window.
-        let dimensions: [u32; 2] = self.surface.window().inner_size().into();
+        // Headless images never change size.
+        let (surface, swapchain, images) = match &mut self.target {
+            Target::Window { surface, swapchain, images } =>
+                (surface, swapchain, images),
+            Target::Headless { .. } => {
+                self.applied.present_mode = self.settings.present_mode;
+                self.recreate_swapchain = false;
+                return;
+            }
+        };
+
+        // Get the new dimensions of the window. A minimized window has none,
+        // and no swapchain can be made for it until it's restored.
+        let dimensions: [u32; 2] = surface.window().inner_size().into();
+        if dimensions.contains(&0) {
+            return;
+        }
+
+        // Resizing keeps the present mode and fullscreen exclusivity,
+        // changing either needs a new swapchain.
+        let present_mode = self.settings.present_mode;
+        let exclusive = self.settings.display.exclusive();
+        let result = if present_mode == self.applied.present_mode
+            && exclusive == swapchain.fullscreen_exclusive()
+        {
+            swapchain.recreate_with_dimensions(dimensions)
+        } else {
+            mk_swapchain(
+                self.device.physical_device(),
+                surface.clone(),
+                self.device.clone(),
+                self.queue.clone(),
+                present_mode,
+                exclusive,
+                Some(swapchain.clone()),
+            )
+        };
 
         let (new_swapchain, new_images) =
-            match self.swapchain.recreate_with_dimensions(dimensions) {
+            match result {
                 Ok(r) => r,
                 // This error tends to happen when the user is manually resizing
                 // the window.  Simply restarting the loop is the easiest way to
@@ -248,22 +822,160 @@ impl Renderer {
                 Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
             };
 
-        self.swapchain = new_swapchain;
+        *swapchain = new_swapchain;
+        *images = new_images;
+        self.applied.present_mode = present_mode;
 
         // Because framebuffers contains an Arc on the old swapchain, we need to
         // recreate framebuffers as well.
-        self.framebuffers = window_size_dependent_setup(
+        self.framebuffers = self.target.framebuffers(
             self.device.clone(),
-            self.swapchain.format(),
-            &new_images,
             self.render_pass.clone(),
+            self.applied.msaa,
+            self.applied.depth,
             &mut self.dynamic_state,
         );
+        self.background_layer.invalidate();
 
         self.recreate_swapchain = false;
     }
 
-    pub fn redraw(&mut self, data: Vec<Vec<InstanceData>>) {
+    /// Loads an image file as a texture that sprites can be drawn from. The
+    /// upload runs before the next frame is drawn.
+    pub fn load_texture(&mut self, path: &std::path::Path) ->
+        Result<Atlas, String>
+    {
+        let (atlas, upload) = self.sprites.load(path)?;
+
+        let future = self.previous_frame_end
+            .take().unwrap()
+            .then_execute(self.queue.clone(), upload).unwrap();
+        self.previous_frame_end = Some(future.boxed());
+
+        Ok(atlas)
+    }
+
+    /// Queues left aligned text to be drawn on top of the next frame. `pos`
+    /// is the top left corner in clip space and `size` the height of a
+    /// capital letter.
+    pub fn draw_text(
+        &mut self,
+        pos: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        text: &str,
+    ) {
+        self.text.queue(pos, size, color, Align::Left, text);
+    }
+
+    /// Like `draw_text`, with `pos` on the edge of each line given by `align`.
+    pub fn draw_text_aligned(
+        &mut self,
+        pos: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        align: Align,
+        text: &str,
+    ) {
+        self.text.queue(pos, size, color, align, text);
+    }
+
+    /// The width and height of `text` drawn `size` high, in clip space
+    /// before the window's aspect ratio is corrected for.
+    pub fn measure_text(&self, size: f32, text: &str) -> [f32; 2] {
+        self.text.font.measure(size, text)
+    }
+
+    /// Reloads any shaders and meshes that were edited since the last frame.
+    /// Assets that fail to load leave the previous version in place and the
+    /// error is shown on screen until a reload succeeds.
+    pub fn reload_assets(&mut self) {
+        let changed = match &self.assets {
+            Some(assets) => assets.changed(),
+            None => return,
+        };
+
+        if changed.is_empty() {
+            return;
+        }
+
+        // Every asset that failed is reported, not just the last one.
+        let mut errors = Vec::new();
+        for asset in changed {
+            let result = match &asset {
+                Asset::Shader(kind, path) => self.reload_shader(*kind, path),
+                Asset::Mesh(name, path) => self.reload_mesh(name, path),
+            };
+
+            if let Err(e) = result {
+                println!("Failed to reload {:?}: {}", asset, e);
+                let file = asset.path().file_name().unwrap_or_default();
+                errors.push(format!("{}: {}", file.to_string_lossy(), e));
+            }
+        }
+        self.asset_error = if errors.is_empty() {
+            None
+        } else {
+            Some(errors.join("; "))
+        };
+
+        if let Some(window) = self.window() {
+            match &self.asset_error {
+                Some(e) => window.set_title(&format!("{} - {}", TITLE, e)),
+                None => window.set_title(TITLE),
+            }
+        }
+    }
+
+    fn reload_shader(
+        &mut self,
+        kind: ShaderKind,
+        path: &std::path::Path,
+    ) -> Result<(), String> {
+        let words = assets::compile_shader(path, kind)?;
+        let module = unsafe {
+            ShaderModule::from_words(self.device.clone(), &words)
+                .map_err(|e| e.to_string())?
+        };
+
+        let (vs, fs) = match kind {
+            ShaderKind::Vertex => (module, self.fs.clone()),
+            ShaderKind::Fragment => (self.vs.clone(), module),
+            _ => return Err(format!("unsupported shader kind {:?}", kind)),
+        };
+
+        // Only swap the pipeline in once it has been built successfully, so a
+        // broken shader keeps drawing with the last good one.
+        self.pipeline = mk_pipeline(
+            self.device.clone(),
+            self.render_pass.clone(),
+            &vs,
+            &fs,
+        ).map_err(|e| e.to_string())?;
+        self.vs = vs;
+        self.fs = fs;
+
+        Ok(())
+    }
+
+    fn reload_mesh(
+        &mut self,
+        name: &str,
+        path: &std::path::Path,
+    ) -> Result<(), String> {
+        let index = mesh::mesh_index(name)
+            .ok_or_else(|| format!("unknown mesh {}", name))?;
+        let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+        self.meshes[index] = mesh::tessellate(&data)?;
+        self.radii[index] = mesh::bounding_radius(&self.meshes[index]);
+
+        Ok(())
+    }
+
+    pub fn redraw(&mut self, mut data: Vec<Vec<InstanceData>>) {
+        profile::frame();
+
         // It is important to call this function from time to time, otherwise
         // resources will keep accumulating and you will eventually reach an out
         // of memory error.  Calling this function polls various fences in order
@@ -271,6 +983,8 @@ impl Renderer {
         // resources that are no longer needed.
         self.previous_frame_end.as_mut().unwrap().cleanup_finished();
 
+        self.apply_settings();
+
         // Whenever the window resizes we need to recreate everything dependent
         // on the window size.  In this example that includes the swapchain, the
         // framebuffers and the dynamic state viewport.
@@ -279,6 +993,23 @@ impl Renderer {
             self.recreate_swapchain();
         }
 
+        // Nothing can be shown while minimized. What was queued for the
+        // frame is dropped rather than piling up until it's restored.
+        if self.minimized() {
+            self.text.clear();
+            self.particles.clear();
+            return;
+        }
+
+        self.reload_assets();
+
+        // Resources used by the frame that last had this slot are reused
+        // once it is done.
+        {
+            let _scope = profile::scope("frame_wait");
+            self.frames.next();
+        }
+
         // Before we can draw on the output, we have to *acquire* an image from
         // the swapchain. If no image is available (which happens if you submit
         // draw commands too quickly), then the function will block.  This
@@ -287,15 +1018,24 @@ impl Renderer {
         //
         // This function can block if no image is available. The parameter is an
         // optional timeout after which the function call will return an error.
-        let (image_num, suboptimal, acquire_future) =
-            match swapchain::acquire_next_image(self.swapchain.clone(), None) {
-                Ok(r) => r,
-                Err(AcquireError::OutOfDate) => {
-                    self.recreate_swapchain = true;
-                    return;
-                }
-                Err(e) => panic!("Failed to acquire next image: {:?}", e),
-            };
+        //
+        // Headless renderers only have the one image, which is always ready.
+        let acquire = profile::scope("acquire");
+        let (image_num, suboptimal, acquire_future) = match &self.target {
+            Target::Window { swapchain, .. } =>
+                match swapchain::acquire_next_image(swapchain.clone(), None) {
+                    Ok((num, suboptimal, future)) =>
+                        (num, suboptimal, future.boxed()),
+                    Err(AcquireError::OutOfDate) => {
+                        self.recreate_swapchain = true;
+                        return;
+                    }
+                    Err(e) => panic!("Failed to acquire next image: {:?}", e),
+                },
+            Target::Headless { .. } =>
+                (0, false, sync::now(self.device.clone()).boxed()),
+        };
+        drop(acquire);
 
         // acquire_next_image can be successful, but suboptimal. This means that
         // the swapchain image will still work, but it may not display
@@ -305,11 +1045,19 @@ impl Renderer {
             self.recreate_swapchain = true;
         }
 
-        // Specify the color to clear the framebuffer with i.e. blue
-        let clear_values = vec![
-            [0.0, 0.0, 0.0, 1.0].into(),
-            ClearValue::None,
-        ];
+        let commands = profile::scope("commands");
+
+        // Specify the color to clear the framebuffer with.
+        let [r, g, b] = self.settings.clear_color;
+        let mut clear_values = vec![[r, g, b, 1.0].into()];
+        // The resolved attachment is not cleared.
+        if self.applied.msaa > 1 {
+            clear_values.push(ClearValue::None);
+        }
+        // Everything is in front of the far plane.
+        if self.applied.depth {
+            clear_values.push(1f32.into());
+        }
 
         // In order to draw, we have to build a *command buffer*. The command
         // buffer object holds the list of commands that are going to be
@@ -327,12 +1075,124 @@ impl Renderer {
             self.queue.family(),
         ).unwrap();
 
-        let inst = mk_inst_buf(self.device.clone(), data);
+        // The world wraps around, so anything on an edge is also drawn on
+        // the other side.
+        let (inst, groups) = {
+            let _scope = profile::scope("mk_inst_verts");
+            wrap::add_ghosts(&mut data, &self.radii, self.world);
+            let groups =
+                mk_inst_verts(&self.meshes, &data, &mut self.inst_verts);
+            (self.frames.inst_buf(&self.inst_verts), groups)
+        };
+
+        let time = self.started.elapsed().as_secs_f32();
+        let fractal = self.compute.fractal_commands(time);
+
+        // Particles are simulated, and the GUI's font uploaded, in a command
+        // buffer of their own. They are used by secondary command buffers,
+        // which vulkano doesn't synchronize with them.
+        let mut uploads = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.queue.family(),
+        ).unwrap();
+        self.particles.update(&mut uploads, self.world);
+        self.gui.update(&mut uploads);
+        let uploads = uploads.build().unwrap();
+
+        // Everything in the render pass is recorded into secondary command
+        // buffers. The background never changes, so it is recorded once and
+        // replayed until the framebuffers are rebuilt.
+        let device = &self.device;
+        let family = self.queue.family();
+        let render_pass = &self.render_pass;
+        let dynamic_state = &self.dynamic_state;
+        let view = self.view;
+
+        let background = &self.background;
+        let background = self.background_layer.commands(
+            device,
+            family,
+            render_pass,
+            &|b| background.draw(b, dynamic_state),
+        );
+
+        // The world layers only read the renderer, so they can be recorded
+        // on worker threads. Each mesh group is a layer of its own.
+        let (particles, pipeline, sprites) =
+            (&self.particles, &self.pipeline, &self.sprites);
+        let data = &data;
+        let draw_particles = |b: &mut AutoCommandBufferBuilder| {
+            particles.draw(b, dynamic_state, view)
+        };
+        let draw_groups: Vec<_> = groups
+            .into_iter()
+            .filter(|group| !group.is_empty())
+            .map(|group| {
+                let verts = inst.clone().slice(group).unwrap();
+                // The last two parameters contain the list of resources to
+                // pass to the shaders. There are no descriptor sets, only
+                // the camera in the push constants.
+                move |b: &mut AutoCommandBufferBuilder| {
+                    let verts = verts.clone();
+                    b.draw(pipeline.clone(), dynamic_state, verts, (), view)
+                        .unwrap();
+                }
+            })
+            .collect();
+        // Textured instances are drawn on top of the meshes.
+        let draw_sprites = |b: &mut AutoCommandBufferBuilder| {
+            sprites.draw(b, dynamic_state, view, data)
+        };
+
+        let mut world_layers: Vec<&layers::Layer<'_>> =
+            vec![&draw_particles];
+        world_layers
+            .extend(draw_groups.iter().map(|l| l as &layers::Layer<'_>));
+        world_layers.push(&draw_sprites);
+
+        let world = layers::record(
+            device,
+            family,
+            render_pass,
+            &world_layers,
+            self.settings.parallel_recording,
+        );
+
+        // Overlays use state local to this thread, so they are recorded here.
+        let mut overlay = layers::secondary(
+            device.clone(),
+            family,
+            render_pass.clone(),
+        );
+
+        if let Some(e) = &self.asset_error {
+            let red = [1.0, 0.2, 0.2, 1.0];
+            self.text.queue([-0.95, -0.95], 0.03, red, Align::Left, e);
+        }
+
+        self.debug.draw(&mut overlay, dynamic_state, view);
+
+        if profile::visible() {
+            let (lines, labels) = profile::graph();
+            self.debug.draw_screen(&mut overlay, dynamic_state, &lines);
+            for (pos, color, label) in labels {
+                let size = profile::TEXT_SIZE;
+                self.text.queue(pos, size, color, Align::Left, &label);
+            }
+        }
+
+        // Text goes last so the HUD is never covered.
+        self.text.draw(&mut overlay, dynamic_state);
+
+        // Except by the GUI, which has to stay usable.
+        self.gui.draw(&mut overlay, dynamic_state);
+
+        let overlay = overlay.build().unwrap();
 
         builder
-            // Before we can draw, we have to *enter a render pass*. There are
-            // two methods to do this: `draw_inline` and `draw_secondary`. The
-            // latter is a bit more advanced and is not covered here.
+            // Before we can draw, we have to *enter a render pass*. The
+            // second parameter says the subpass is made of secondary command
+            // buffers rather than inline draws.
             //
             // The third parameter builds the list of values to clear the
             // attachments with. The API is similar to the list of attachments
@@ -340,22 +1200,24 @@ impl Renderer {
             // that use `load: Clear` appear in the list.
             .begin_render_pass(
                 self.framebuffers[image_num].clone(),
-                false,
+                true,
                 clear_values,
-            ).unwrap()
-            // We are now inside the first subpass of the render pass. We add a
-            // draw command.
-            //
-            // The last two parameters contain the list of resources to pass to
-            // the shaders.  Since we used an `EmptyPipeline` object, the
-            // objects have to be `()`.
-            .draw(
-                self.pipeline.clone(),
-                &self.dynamic_state,
-                inst,
-                (),
-                (),
-            ).unwrap()
+            ).unwrap();
+
+        // vulkano does not track what secondary command buffers use. Their
+        // resources are immutable, created for this frame, reused behind the
+        // frame fences or written by the fractal and uploads, which are
+        // fenced off from this with barriers when the frame is submitted.
+        unsafe {
+            builder
+                // The background goes first so that everything else is drawn
+                // on top.
+                .execute_commands(background).unwrap()
+                .execute_commands_from_vec(world).unwrap()
+                .execute_commands(overlay).unwrap();
+        }
+
+        builder
             // We leave the render pass by calling `draw_end`. Note that if we
             // had multiple subpasses we could have called `next_inline` (or
             // `next_secondary`) to jump to the next subpass.
@@ -363,11 +1225,48 @@ impl Renderer {
 
         // Finish building the command buffer by calling `build`.
         let command_buffer = builder.build().unwrap();
+        drop(commands);
 
-        let future = self.previous_frame_end
+        let drawn = self.previous_frame_end
             .take().unwrap()
             .join(acquire_future)
-            .then_execute(self.queue.clone(), command_buffer).unwrap()
+            // Nothing is written before the previous frame is done reading it.
+            .then_execute(self.queue.clone(), Unsynced::barrier(&self.queue))
+            .unwrap();
+
+        // Render the fractal before drawing, vulkano inserts the semaphore
+        // needed when the compute queue is a different one.
+        let drawn = match fractal {
+            Some(fractal) => drawn
+                .then_execute(self.compute.queue.clone(), fractal).unwrap()
+                .boxed(),
+            None => drawn.boxed(),
+        };
+
+        let drawn = drawn
+            .then_execute(self.queue.clone(), uploads).unwrap()
+            // Nor read before it is written. Ending `uploads` has also moved
+            // the font image into the layout it's sampled in.
+            .then_execute(self.queue.clone(), Unsynced::barrier(&self.queue))
+            .unwrap();
+
+        // Timestamps are written right before and after the frame's commands
+        // on the same queue.
+        let drawn = match &mut self.gpu_timer {
+            Some(timer) => {
+                let (begin, end) = timer.frame();
+                drawn
+                    .then_execute(self.queue.clone(), begin).unwrap()
+                    .then_execute(self.queue.clone(), command_buffer).unwrap()
+                    .then_execute(self.queue.clone(), end).unwrap()
+                    .boxed()
+            }
+            None => drawn
+                .then_execute(self.queue.clone(), command_buffer).unwrap()
+                .boxed(),
+        };
+
+        let future = match &self.target {
             // The color output is now expected to contain our triangle. But in
             // order to show it on the screen, we have to *present* the image by
             // calling `present`.
@@ -376,15 +1275,27 @@ impl Renderer {
             // Instead it submits a present command at the end of the queue.
             // This means that it will only be presented once the GPU has
             // finished executing the command buffer that draws the triangle.
-            .then_swapchain_present(
-                self.queue.clone(),
-                self.swapchain.clone(),
-                image_num
-            )
-            .then_signal_fence_and_flush();
+            Target::Window { swapchain, .. } => drawn
+                .then_swapchain_present(
+                    self.queue.clone(),
+                    swapchain.clone(),
+                    image_num
+                )
+                .boxed(),
+            // Nothing to present, the image is read back by `snapshot`.
+            Target::Headless { .. } => drawn.boxed(),
+        }.then_signal_fence_and_flush();
 
         self.previous_frame_end = match future {
-            Ok(future) => Some(future.boxed()),
+            Ok(future) => {
+                // Shared with the frame slot, which waits on it before the
+                // slot is reused. vulkano can only chain fences through an
+                // `Arc`, even though they never leave this thread.
+                #[allow(clippy::arc_with_non_send_sync)]
+                let fence = Arc::new(future);
+                self.frames.set_fence(fence.clone());
+                Some(fence.boxed())
+            }
             Err(FlushError::OutOfDate) => {
                 self.recreate_swapchain = true;
                 Some(sync::now(self.device.clone()).boxed())
@@ -395,47 +1306,105 @@ impl Renderer {
             }
         };
     }
+
+    /// Waits for the last frame and reads it back. Only headless renderers
+    /// can be read back, windows return `None`.
+    pub fn snapshot(&mut self) -> Option<RgbaImage> {
+        let image = match &self.target {
+            Target::Headless { image } => image.clone(),
+            Target::Window { .. } => return None,
+        };
+        let [width, height] = ImageAccess::dimensions(&image).width_height();
+
+        let buf = CpuBuf::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_destination(),
+            false,
+            (0..width * height * 4).map(|_| 0u8),
+        ).unwrap();
+
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.queue.family(),
+        ).unwrap();
+        builder.copy_image_to_buffer(image, buf.clone()).unwrap();
+        let command_buffer = builder.build().unwrap();
+
+        self.previous_frame_end
+            .take().unwrap()
+            .then_execute(self.queue.clone(), command_buffer).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+        self.previous_frame_end = Some(sync::now(self.device.clone()).boxed());
+
+        let pixels = buf.read().unwrap().to_vec();
+        RgbaImage::from_raw(width, height, pixels)
+    }
 }
 
+/// Creates the device with a graphics queue, and a compute queue that may be
+/// the same one. Without a surface no swapchain support is required.
 fn mk_device(
     physical: PhysicalDevice<'_>,
-    surface: &Arc<Surface<Window>>
+    surface: Option<&Arc<Surface<Window>>>
 ) -> (
     Arc<Device>,
+    Arc<Queue>,
     Arc<Queue>
 ) {
     let family = physical
         .queue_families()
         .find(|&q| {
             q.supports_graphics()
-                && surface.is_supported(q).unwrap_or(false)
+                && surface
+                    .into_iter()
+                    .all(|s| s.is_supported(q).unwrap_or(false))
         }).unwrap();
 
+    // Prefer a dedicated compute family so compute work can overlap with
+    // drawing, otherwise share the graphics queue.
+    let compute_family = physical
+        .queue_families()
+        .find(|&q| q.supports_compute() && !q.supports_graphics());
+
     let device_ext = DeviceExtensions {
-        khr_swapchain: true,
+        khr_swapchain: surface.is_some(),
         ..DeviceExtensions::none()
     };
 
+    let mut families = vec![(family, 0.5)];
+    if let Some(compute_family) = compute_family {
+        families.push((compute_family, 0.5));
+    }
+
     let (device, mut queues) = Device::new(
         physical,
         physical.supported_features(),
         &device_ext,
-        [(family, 0.5)].iter().cloned(),
+        families,
     ).unwrap();
 
-    (device, queues.next().unwrap())
+    let queue = queues.next().unwrap();
+    let compute_queue = queues.next().unwrap_or_else(|| queue.clone());
+
+    (device, queue, compute_queue)
 }
 
 
+type SwapchainParts =
+    (Arc<Swapchain<Window>>, Vec<Arc<SwapchainImage<Window>>>);
+
+/// Creates a swapchain for `surface`. When replacing a swapchain, `old`
+/// must be the one in use.
 fn mk_swapchain(
     physical: PhysicalDevice<'_>,
     surface: Arc<Surface<Window>>,
     device: Arc<Device>,
     queue: Arc<Queue>,
-) -> (
-    Arc<Swapchain<Window>>,
-    Vec<Arc<SwapchainImage<Window>>>
-) {
+    present_mode: PresentMode,
+    exclusive: FullscreenExclusive,
+    old: Option<Arc<Swapchain<Window>>>,
+) -> Result<SwapchainParts, SwapchainCreationError> {
     // Querying the capabilities of the surface. When we create the
     // swapchain we can only pass values that are allowed by the
     // capabilities.
@@ -465,52 +1434,155 @@ fn mk_swapchain(
 
     // Please take a look at the docs for the meaning of the parameters we
     // didn't mention.
-    Swapchain::new(
-        device,
-        surface,
-        caps.min_image_count,
-        format,
-        dimensions,
-        1,
-        ImageUsage::color_attachment(),
-        &queue,
-        SurfaceTransform::Identity,
-        alpha,
-        PresentMode::Fifo,
-        FullscreenExclusive::Default,
-        true,
-        ColorSpace::SrgbNonLinear,
-    ).unwrap()
+    match old {
+        None => Swapchain::new(
+            device,
+            surface,
+            caps.min_image_count,
+            format,
+            dimensions,
+            1,
+            ImageUsage::color_attachment(),
+            &queue,
+            SurfaceTransform::Identity,
+            alpha,
+            present_mode,
+            exclusive,
+            true,
+            ColorSpace::SrgbNonLinear,
+        ),
+        Some(old) => Swapchain::with_old_swapchain(
+            device,
+            surface,
+            caps.min_image_count,
+            format,
+            dimensions,
+            1,
+            ImageUsage::color_attachment(),
+            &queue,
+            SurfaceTransform::Identity,
+            alpha,
+            present_mode,
+            exclusive,
+            true,
+            ColorSpace::SrgbNonLinear,
+            old,
+        ),
+    }
 }
 
-fn mk_render_pass(device: Arc<Device>, swapchain: Arc<Swapchain<Window>>) ->
-    Arc<dyn RenderPassAbstract + Send + Sync>
-{
-    Arc::new(
-        vulkano::single_pass_renderpass!(
-            device,
-            attachments: {
-                intermediary: {
-                    load: Clear,
-                    store: DontCare,
-                    format: swapchain.format(),
-                    samples: 4,
+// Format of the optional depth buffer.
+const DEPTH_FORMAT: Format = Format::D16Unorm;
+
+/// Creates the render pass. With multisampling it draws to an intermediary
+/// image that is resolved into the final one. The depth attachment, if any,
+/// always comes last and has as many samples as the color it goes with.
+fn mk_render_pass(
+    device: Arc<Device>,
+    format: Format,
+    samples: u32,
+    depth: bool,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+    match (samples > 1, depth) {
+        (false, false) => Arc::new(
+            vulkano::single_pass_renderpass!(
+                device,
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    }
                 },
-                color: {
-                    load: DontCare,
-                    store: Store,
-                    format: swapchain.format(),
-                    samples: 1,
+                pass: {
+                    color: [color],
+                    depth_stencil: {}
                 }
-            },
-            pass: {
-                color: [intermediary],
-                depth_stencil: {}
-                resolve: [color],
-            }
-        )
-        .unwrap(),
-    )
+            )
+            .unwrap(),
+        ),
+        (false, true) => Arc::new(
+            vulkano::single_pass_renderpass!(
+                device,
+                attachments: {
+                    color: {
+                        load: Clear,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Clear,
+                        store: DontCare,
+                        format: DEPTH_FORMAT,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [color],
+                    depth_stencil: {depth}
+                }
+            )
+            .unwrap(),
+        ),
+        (true, false) => Arc::new(
+            vulkano::single_pass_renderpass!(
+                device,
+                attachments: {
+                    intermediary: {
+                        load: Clear,
+                        store: DontCare,
+                        format: format,
+                        samples: samples,
+                    },
+                    color: {
+                        load: DontCare,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    }
+                },
+                pass: {
+                    color: [intermediary],
+                    depth_stencil: {}
+                    resolve: [color],
+                }
+            )
+            .unwrap(),
+        ),
+        (true, true) => Arc::new(
+            vulkano::single_pass_renderpass!(
+                device,
+                attachments: {
+                    intermediary: {
+                        load: Clear,
+                        store: DontCare,
+                        format: format,
+                        samples: samples,
+                    },
+                    color: {
+                        load: DontCare,
+                        store: Store,
+                        format: format,
+                        samples: 1,
+                    },
+                    depth: {
+                        load: Clear,
+                        store: DontCare,
+                        format: DEPTH_FORMAT,
+                        samples: samples,
+                    }
+                },
+                pass: {
+                    color: [intermediary],
+                    depth_stencil: {depth}
+                    resolve: [color],
+                }
+            )
+            .unwrap(),
+        ),
+    }
 }
 
 type MyPipeline = Arc<GraphicsPipeline<
@@ -522,10 +1594,10 @@ type MyPipeline = Arc<GraphicsPipeline<
 fn mk_pipeline(
     device: Arc<Device>,
     render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
-    vs: &vs::Shader,
-    fs: &fs::Shader
-) -> MyPipeline {
-    Arc::new(
+    vs: &ShaderModule,
+    fs: &ShaderModule
+) -> Result<MyPipeline, GraphicsPipelineCreationError> {
+    Ok(Arc::new(
         GraphicsPipeline::start()
         // We need to indicate the layout of the vertices.  The type
         // `SingleBufferDefinition` actually contains a template parameter
@@ -536,34 +1608,40 @@ fn mk_pipeline(
         // we have to specify which one. The `main` word of
         // `main_entry_point` actually corresponds to the name of the entry
         // point.
-        .vertex_shader(vs.main_entry_point(), ())
+        .vertex_shader(vs_entry_point(vs), ())
         // The content of the vertex buffer describes a list of triangles.
         .triangle_list()
         // Use a resizable viewport set to draw over the entire window
         .viewports_dynamic_scissors_irrelevant(1)
         // See `vertex_shader`.
-        .fragment_shader(fs.main_entry_point(), ())
+        .fragment_shader(fs_entry_point(fs), ())
+        // Meshes are opaque, so they write depth.
+        .depth_stencil(depth_stencil(&render_pass, true))
         // We have to indicate which subpass of which render pass this
         // pipeline is going to be used in. The pipeline will only be usable
         // from this particular subpass.
         .render_pass(Subpass::from(render_pass, 0).unwrap())
         // Now that our builder is filled, we call `build()` to obtain an
         // actual pipeline.
-        .build(device).unwrap(),
-    )
+        .build(device)?,
+    ))
 }
 
 
 /// This method is called once during initialization, then again whenever the
 /// window is resized
-pub fn window_size_dependent_setup(
+pub fn window_size_dependent_setup<I>(
     device: Arc<Device>,
     format: Format,
-    images: &[Arc<SwapchainImage<Window>>],
+    samples: u32,
+    depth: bool,
+    images: &[Arc<I>],
     render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
     dynamic_state: &mut DynamicState,
-) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
-    let dimensions = images[0].dimensions();
+) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>>
+    where I: ImageAccess + ImageViewAccess + Send + Sync + 'static
+{
+    let dimensions = ImageAccess::dimensions(&*images[0]).width_height();
 
     let viewport = Viewport {
         origin: [0.0, 0.0],
@@ -572,22 +1650,57 @@ pub fn window_size_dependent_setup(
     };
     dynamic_state.viewports = Some(vec![viewport]);
 
-    let intermediary = AttachmentImage::transient_multisampled(
-        device.clone(),
-        dimensions,
-        4,
-        format,
-    ).unwrap();
+    // Without multisampling the images are drawn to directly.
+    let intermediary = if samples > 1 {
+        Some(AttachmentImage::transient_multisampled(
+            device.clone(),
+            dimensions,
+            samples,
+            format,
+        ).unwrap())
+    } else {
+        None
+    };
+
+    let depth = if depth {
+        Some(AttachmentImage::transient_multisampled(
+            device.clone(),
+            dimensions,
+            samples,
+            DEPTH_FORMAT,
+        ).unwrap())
+    } else {
+        None
+    };
 
+    // Attachments are added in the order `mk_render_pass` declares them.
     images
         .iter()
-        .map(|image| {
-            Arc::new(
+        .map(|image| match (&intermediary, &depth) {
+            (Some(intermediary), Some(depth)) => Arc::new(
                 Framebuffer::start(render_pass.clone())
                     .add(intermediary.clone()).unwrap()
+                    .add(image.clone()).unwrap()
+                    .add(depth.clone()).unwrap()
+                    .build().unwrap(),
+            ) as Arc<dyn FramebufferAbstract + Send + Sync>,
+            (Some(intermediary), None) => Arc::new(
+                Framebuffer::start(render_pass.clone())
+                    .add(intermediary.clone()).unwrap()
+                    .add(image.clone()).unwrap()
+                    .build().unwrap(),
+            ) as Arc<dyn FramebufferAbstract + Send + Sync>,
+            (None, Some(depth)) => Arc::new(
+                Framebuffer::start(render_pass.clone())
+                    .add(image.clone()).unwrap()
+                    .add(depth.clone()).unwrap()
+                    .build().unwrap(),
+            ) as Arc<dyn FramebufferAbstract + Send + Sync>,
+            (None, None) => Arc::new(
+                Framebuffer::start(render_pass.clone())
                     .add(image.clone()).unwrap()
                     .build().unwrap(),
-            ) as Arc<dyn FramebufferAbstract + Send + Sync>
+            ) as Arc<dyn FramebufferAbstract + Send + Sync>,
         })
         .collect::<Vec<_>>()
 }
@@ -0,0 +1,141 @@
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use shaderc::{Compiler, CompileOptions, ShaderKind, TargetEnv};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+    time::Duration,
+};
+
+// Overrides where the assets are looked for.
+const ASSETS_VAR: &str = "VULKANO_TEST_ASSETS";
+
+/// Directory holding the shaders and meshes that can be edited while the game
+/// is running. It is the one named by `VULKANO_TEST_ASSETS`, else `assets`
+/// next to the executable, else the one in the source tree.
+pub fn asset_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(ASSETS_VAR) {
+        return dir.into();
+    }
+
+    let beside = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("assets")));
+    match beside {
+        Some(dir) if dir.is_dir() => dir,
+        _ => Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"),
+    }
+}
+
+/// A file that changed on disk and that the renderer knows how to reload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Asset {
+    Shader(ShaderKind, PathBuf),
+    Mesh(String, PathBuf),
+}
+
+impl Asset {
+    /// Classifies a path by its extension, ignoring files we do not load.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let stem = path.file_stem()?.to_str()?;
+
+        match path.extension()?.to_str()? {
+            "glsl" => match stem {
+                "vertex" =>
+                    Some(Asset::Shader(ShaderKind::Vertex, path.into())),
+                "fragment" =>
+                    Some(Asset::Shader(ShaderKind::Fragment, path.into())),
+                _ => None,
+            },
+            "path" => Some(Asset::Mesh(stem.to_string(), path.into())),
+            _ => None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Asset::Shader(_, path) | Asset::Mesh(_, path) => path,
+        }
+    }
+}
+
+/// Watches the asset directory and reports files that were written to.
+pub struct Assets {
+    // The watcher stops sending events as soon as it is dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+}
+
+impl Assets {
+    pub fn watch(root: PathBuf) -> notify::Result<Self> {
+        let (tx, events) = channel();
+
+        // Editors tend to save in several steps (truncate, write, rename), so
+        // let notify coalesce them before we try to reload anything.
+        let mut watcher = notify::watcher(tx, Duration::from_millis(100))?;
+        watcher.watch(&root, RecursiveMode::Recursive)?;
+
+        Ok(Assets {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Drains the pending file events without blocking.
+    pub fn changed(&self) -> Vec<Asset> {
+        let mut changed = Vec::new();
+
+        for event in self.events.try_iter() {
+            let path = match event {
+                DebouncedEvent::Create(path)
+                | DebouncedEvent::Write(path)
+                | DebouncedEvent::Rename(_, path) => path,
+                _ => continue,
+            };
+
+            match Asset::from_path(&path) {
+                Some(asset) => {
+                    if !changed.contains(&asset) {
+                        changed.push(asset);
+                    }
+                }
+                // Only the main pipeline's shaders are rebuilt. The rest
+                // are compiled into the game when it is built.
+                None if path.extension().is_some_and(|ext| ext == "glsl") => {
+                    println!("Not reloading {:?}, it needs a rebuild", path);
+                }
+                None => (),
+            }
+        }
+
+        changed
+    }
+}
+
+/// Compiles a GLSL file to SPIR-V, the same way `vulkano_shaders::shader!`
+/// does at build time.
+pub fn compile_shader(
+    path: &Path,
+    kind: ShaderKind,
+) -> Result<Vec<u32>, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+
+    let mut compiler = Compiler::new()
+        .ok_or("failed to create GLSL compiler")?;
+    let mut options = CompileOptions::new()
+        .ok_or("failed to initialize compile options")?;
+    options.set_target_env(TargetEnv::Vulkan, (1 << 22) | (1 << 12));
+
+    let artifact = compiler
+        .compile_into_spirv(
+            &source,
+            kind,
+            &path.to_string_lossy(),
+            "main",
+            Some(&options),
+        )
+        .map_err(|e| e.to_string())?;
+
+    Ok(artifact.as_binary().to_vec())
+}
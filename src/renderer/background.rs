@@ -0,0 +1,113 @@
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+        PipelineLayoutAbstract,
+    },
+    device::{Device, DeviceOwned},
+    format::Format,
+    framebuffer::{RenderPassAbstract, Subpass},
+    image::StorageImage,
+    pipeline::{
+        vertex::{BufferlessDefinition, BufferlessVertices},
+        GraphicsPipeline,
+    },
+    sampler::Sampler,
+};
+
+use std::sync::Arc;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "assets/shaders/background_vertex.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "assets/shaders/background_fragment.glsl"
+    }
+}
+
+type BackgroundPipeline = Arc<GraphicsPipeline<
+    BufferlessDefinition,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+    Arc<dyn RenderPassAbstract + Send + Sync>>
+>;
+
+fn mk_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> BackgroundPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+
+    Arc::new(
+        GraphicsPipeline::start()
+        .vertex_input(BufferlessDefinition)
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(fs.main_entry_point(), ())
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device).unwrap(),
+    )
+}
+
+/// Draws a texture stretched over the whole window, behind everything else.
+pub struct Background {
+    pipeline: BackgroundPipeline,
+    set: Arc<dyn DescriptorSet + Send + Sync>,
+}
+
+impl Background {
+    pub fn new(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+        image: Arc<StorageImage<Format>>,
+    ) -> Self {
+        let pipeline = mk_pipeline(device.clone(), render_pass);
+
+        let sampler = Sampler::simple_repeat_linear_no_mipmap(device);
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(
+                pipeline.descriptor_set_layout(0).unwrap().clone()
+            )
+            .add_sampled_image(image, sampler).unwrap()
+            .build().unwrap()
+        );
+
+        Background { pipeline, set }
+    }
+
+    /// Rebuilds the pipeline for a new render pass, e.g. when the sample
+    /// count changes.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        let device = self.pipeline.device().clone();
+        self.pipeline = mk_pipeline(device, render_pass);
+    }
+
+    /// Records the draw. Must be called inside the render pass, before the
+    /// rest of the scene.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+    ) {
+        builder
+            .draw(
+                self.pipeline.clone(),
+                dynamic_state,
+                BufferlessVertices { vertices: 3, instances: 1 },
+                self.set.clone(),
+                (),
+            ).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+/// The camera as the shaders see it, pushed as constants with every draw of
+/// something in the world. Layout matches the `View` block in the shaders.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct View {
+    pub pos: [f32; 2],
+    pub zoom: f32,
+    /// Degrees, using the same convention as instance angles.
+    pub angle: f32,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View { pos: [0.0, 0.0], zoom: 1.0, angle: 0.0 }
+    }
+}
+
+// Shake at full trauma, in world units and degrees.
+const MAX_SHAKE_OFFSET: f32 = 0.05;
+const MAX_SHAKE_ANGLE: f32 = 5.0;
+// Trauma lost per frame, so a full shake lasts about a second.
+const TRAUMA_DECAY: f32 = 1.0 / 60.0;
+// How fast the shake wobbles, in radians of noise per frame.
+const SHAKE_SPEED: f32 = 0.9;
+
+/// A 2D camera with screen shake. Shake is driven by trauma, which is added
+/// on impacts and decays every frame. The shake grows with the square of
+/// the trauma, so small hits barely move the view.
+#[derive(Debug, Clone)]
+pub struct Camera {
+    pub pos: [f32; 2],
+    pub zoom: f32,
+    pub angle: f32,
+    trauma: f32,
+    frame: f32,
+}
+
+impl Camera {
+    pub fn new() -> Self {
+        Camera {
+            pos: [0.0, 0.0],
+            zoom: 1.0,
+            angle: 0.0,
+            trauma: 0.0,
+            frame: 0.0,
+        }
+    }
+
+    /// Adds trauma, clamped to 1.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).min(1.0);
+    }
+
+    /// Advances the shake by one frame.
+    pub fn update(&mut self) {
+        self.trauma = (self.trauma - TRAUMA_DECAY).max(0.0);
+        self.frame += 1.0;
+    }
+
+    /// The view to draw this frame with, shake included.
+    pub fn view(&self) -> View {
+        let shake = self.trauma * self.trauma;
+        let t = self.frame * SHAKE_SPEED;
+
+        View {
+            pos: [
+                self.pos[0] + MAX_SHAKE_OFFSET * shake * noise(t, 0.0),
+                self.pos[1] + MAX_SHAKE_OFFSET * shake * noise(t, 1.0),
+            ],
+            zoom: self.zoom,
+            angle: self.angle + MAX_SHAKE_ANGLE * shake * noise(t, 2.0),
+        }
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera::new()
+    }
+}
+
+// Smooth noise in -1..1. Different seeds give unrelated curves, so each axis
+// of the shake moves independently.
+fn noise(t: f32, seed: f32) -> f32 {
+    let s = seed * 17.31;
+    ((t + s).sin() + (t * 2.13 + s * 1.7).sin() * 0.5) / 1.5
+}
@@ -0,0 +1,233 @@
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer as CpuBuf},
+    command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder},
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+        PipelineLayoutAbstract,
+    },
+    device::{Device, Queue},
+    format::Format,
+    image::{Dimensions, StorageImage},
+    instance::QueueFamily,
+    pipeline::{ComputePipeline, ComputePipelineAbstract},
+    sync,
+    sync::GpuFuture,
+};
+
+use std::sync::Arc;
+
+mod fractal_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "assets/shaders/fractal.glsl"
+    }
+}
+
+mod mult_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "assets/shaders/mult.glsl"
+    }
+}
+
+/// Size of the fractal texture. It is stretched over the window when drawn.
+pub const FRACTAL_SIZE: [u32; 2] = [1024, 1024];
+
+// Seconds between renders of the fractal. It changes too slowly to redraw
+// every frame.
+const FRACTAL_STEP: f32 = 0.1;
+
+// Must match the `local_size_*` declared in the shaders.
+const FRACTAL_GROUP: [u32; 2] = [8, 8];
+const MULT_GROUP: usize = 64;
+
+type MyComputePipeline = Arc<dyn ComputePipelineAbstract + Send + Sync>;
+
+/// Runs compute shaders on a compute-capable queue, which is the graphics
+/// queue itself when the device has no separate compute family.
+pub struct Compute {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub fractal: Arc<StorageImage<Format>>,
+    fractal_pipeline: MyComputePipeline,
+    fractal_set: Arc<dyn DescriptorSet + Send + Sync>,
+    // When the fractal was last rendered, if it has been.
+    fractal_time: Option<f32>,
+    mult_pipeline: MyComputePipeline,
+}
+
+impl Compute {
+    /// `families` lists every queue family that will use the results, so
+    /// that storage images can be shared between them.
+    pub fn new<'a, I>(device: Arc<Device>, queue: Arc<Queue>, families: I) ->
+        Self
+        where I: IntoIterator<Item = QueueFamily<'a>>
+    {
+        let fractal_cs = fractal_cs::Shader::load(device.clone()).unwrap();
+        let fractal_pipeline = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &fractal_cs.main_entry_point(),
+                &(),
+            ).unwrap()
+        );
+
+        let mult_cs = mult_cs::Shader::load(device.clone()).unwrap();
+        let mult_pipeline = Arc::new(
+            ComputePipeline::new(
+                device.clone(),
+                &mult_cs.main_entry_point(),
+                &(),
+            ).unwrap()
+        );
+
+        // The fractal is written by the compute shader and then sampled by
+        // the background pipeline.
+        let fractal = StorageImage::new(
+            device.clone(),
+            Dimensions::Dim2d {
+                width: FRACTAL_SIZE[0],
+                height: FRACTAL_SIZE[1],
+            },
+            Format::R8G8B8A8Unorm,
+            families,
+        ).unwrap();
+
+        let fractal_set = Arc::new(
+            PersistentDescriptorSet::start(
+                fractal_pipeline.descriptor_set_layout(0).unwrap().clone()
+            )
+            .add_image(fractal.clone()).unwrap()
+            .build().unwrap()
+        );
+
+        Compute {
+            device,
+            queue,
+            fractal,
+            fractal_pipeline,
+            fractal_set,
+            fractal_time: None,
+            mult_pipeline,
+        }
+    }
+
+    /// Records the commands rendering the fractal at `time` seconds into
+    /// `self.fractal`, or `None` if the image is recent enough already. The
+    /// caller executes them before sampling the image.
+    pub fn fractal_commands(&mut self, time: f32) -> Option<AutoCommandBuffer> {
+        if let Some(last) = self.fractal_time {
+            if time - last < FRACTAL_STEP {
+                return None;
+            }
+        }
+        self.fractal_time = Some(time);
+
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.queue.family(),
+        ).unwrap();
+
+        builder
+            .dispatch(
+                [
+                    FRACTAL_SIZE[0] / FRACTAL_GROUP[0],
+                    FRACTAL_SIZE[1] / FRACTAL_GROUP[1],
+                    1,
+                ],
+                self.fractal_pipeline.clone(),
+                self.fractal_set.clone(),
+                fractal_cs::ty::PushConstants { time },
+            ).unwrap();
+
+        Some(builder.build().unwrap())
+    }
+
+    /// Multiplies every element of `data` on the GPU and blocks until the
+    /// results have been read back.
+    pub fn multiply(&self, data: &[u32]) -> Vec<u32> {
+        // Vulkan has no empty buffers or dispatches.
+        if data.is_empty() {
+            return Vec::new();
+        }
+
+        // The shader does not bounds check, so pad the buffer to a whole
+        // number of work groups.
+        let groups = mult_groups(data.len());
+        let mut padded = data.to_vec();
+        padded.resize(groups * MULT_GROUP, 0);
+
+        let buf = CpuBuf::from_iter(
+            self.device.clone(),
+            BufferUsage {
+                storage_buffer: true,
+                ..BufferUsage::none()
+            },
+            false,
+            padded.into_iter(),
+        ).unwrap();
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(
+                self.mult_pipeline.descriptor_set_layout(0).unwrap().clone()
+            )
+            .add_buffer(buf.clone()).unwrap()
+            .build().unwrap()
+        );
+
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.queue.family(),
+        ).unwrap();
+
+        builder
+            .dispatch(
+                [groups as u32, 1, 1],
+                self.mult_pipeline.clone(),
+                set,
+                (),
+            ).unwrap();
+
+        let command_buffer = builder.build().unwrap();
+
+        sync::now(self.device.clone())
+            .then_execute(self.queue.clone(), command_buffer).unwrap()
+            .then_signal_fence_and_flush().unwrap()
+            .wait(None).unwrap();
+
+        let content = buf.read().unwrap();
+        content[..data.len()].to_vec()
+    }
+}
+
+// Work groups needed to multiply `len` elements.
+fn mult_groups(len: usize) -> usize {
+    len.div_ceil(MULT_GROUP)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Renderer;
+
+    #[test]
+    fn mult_groups_cover_every_element() {
+        assert_eq!(mult_groups(0), 0);
+        assert_eq!(mult_groups(1), 1);
+        assert_eq!(mult_groups(MULT_GROUP), 1);
+        assert_eq!(mult_groups(MULT_GROUP + 1), 2);
+        assert_eq!(mult_groups(100), 2);
+    }
+
+    // Needs a Vulkan device, so it only runs with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn multiply_reads_back_the_results() {
+        let renderer = Renderer::headless([64, 64]);
+        // Not a whole number of work groups, so the padding is cut off.
+        let data: Vec<u32> = (0..100).collect();
+        let expected: Vec<u32> = data.iter().map(|x| x * 12).collect();
+        assert_eq!(renderer.compute.multiply(&data), expected);
+    }
+}
@@ -0,0 +1,276 @@
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer as CpuBuf},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::PipelineLayoutAbstract,
+    device::Device,
+    framebuffer::{RenderPassAbstract, Subpass},
+    pipeline::{vertex::SingleBufferDefinition, GraphicsPipeline},
+};
+
+use std::{
+    cell::RefCell,
+    f32::consts::PI,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use super::View;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "assets/shaders/debug_vertex.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "assets/shaders/debug_fragment.glsl"
+    }
+}
+
+const CIRCLE_SEGMENTS: usize = 32;
+const ARROW_HEAD: f32 = 0.015;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct DebugVert {
+    anchor: [f32; 2],
+    offset: [f32; 2],
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(DebugVert, anchor, offset, color);
+
+// Shapes are queued per thread so that game code can draw from anywhere
+// without a reference to the renderer. Only the render thread's queue is
+// drawn, other threads hand theirs over with `take` and `extend`.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static QUEUE: RefCell<Vec<DebugVert>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Whether debug shapes are being collected, on every thread. While
+/// disabled every drawing function returns immediately, so the calls can be
+/// left in.
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Turns collecting shapes on or off. Turning it off only clears this
+/// thread's queue, the others are cleared when next taken.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled {
+        QUEUE.with(|q| q.borrow_mut().clear());
+    }
+}
+
+pub fn toggle() {
+    set_enabled(!enabled());
+}
+
+/// Takes the shapes queued on this thread, to be drawn by the render
+/// thread.
+pub fn take() -> Vec<DebugVert> {
+    let verts = QUEUE.with(|q| std::mem::take(&mut *q.borrow_mut()));
+    if enabled() { verts } else { Vec::new() }
+}
+
+/// Queues shapes taken from another thread.
+pub fn extend(verts: &[DebugVert]) {
+    if enabled() {
+        QUEUE.with(|q| q.borrow_mut().extend_from_slice(verts));
+    }
+}
+
+fn push(anchor: [f32; 2], offsets: &[[f32; 2]], color: [f32; 4]) {
+    QUEUE.with(|q| {
+        q.borrow_mut().extend(offsets.iter().map(|&offset| DebugVert {
+            anchor,
+            offset,
+            color,
+        }));
+    });
+}
+
+/// A line between two points in world space.
+pub fn line(from: [f32; 2], to: [f32; 2], color: [f32; 4]) {
+    if !enabled() {
+        return;
+    }
+
+    push(from, &[[0.0, 0.0]], color);
+    push(to, &[[0.0, 0.0]], color);
+}
+
+/// An axis aligned rectangle between two corners in world space.
+pub fn rect(min: [f32; 2], max: [f32; 2], color: [f32; 4]) {
+    if !enabled() {
+        return;
+    }
+
+    let corners = [min, [max[0], min[1]], max, [min[0], max[1]]];
+    for i in 0..4 {
+        line(corners[i], corners[(i + 1) % 4], color);
+    }
+}
+
+/// A circle around `center`. The radius is in the same units as the scale
+/// of an instance, so it matches the meshes' bounds on screen.
+pub fn circle(center: [f32; 2], radius: f32, color: [f32; 4]) {
+    if !enabled() {
+        return;
+    }
+
+    let point = |i: usize| {
+        let a = i as f32 / CIRCLE_SEGMENTS as f32 * 2.0 * PI;
+        [a.cos() * radius, a.sin() * radius]
+    };
+
+    for i in 0..CIRCLE_SEGMENTS {
+        push(center, &[point(i), point(i + 1)], color);
+    }
+}
+
+/// A line from `from` to `to` with a head at `to`, e.g. for velocities.
+pub fn arrow(from: [f32; 2], to: [f32; 2], color: [f32; 4]) {
+    if !enabled() {
+        return;
+    }
+
+    line(from, to, color);
+
+    let dir = [to[0] - from[0], to[1] - from[1]];
+    let len = (dir[0] * dir[0] + dir[1] * dir[1]).sqrt();
+    if len == 0.0 {
+        return;
+    }
+
+    // The head is offset from `to`, so it is not squashed by the aspect
+    // correction.
+    let back = [-dir[0] / len * ARROW_HEAD, -dir[1] / len * ARROW_HEAD];
+    let side = [-back[1] / 2.0, back[0] / 2.0];
+    push(to, &[
+        [0.0, 0.0], [back[0] + side[0], back[1] + side[1]],
+        [0.0, 0.0], [back[0] - side[0], back[1] - side[1]],
+    ], color);
+}
+
+type DebugPipeline = Arc<GraphicsPipeline<
+    SingleBufferDefinition<DebugVert>,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+    Arc<dyn RenderPassAbstract + Send + Sync>>
+>;
+
+fn mk_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> DebugPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+
+    Arc::new(
+        GraphicsPipeline::start()
+        .vertex_input_single_buffer()
+        .vertex_shader(vs.main_entry_point(), ())
+        .line_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(fs.main_entry_point(), ())
+        .blend_alpha_blending()
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device).unwrap(),
+    )
+}
+
+/// Draws the shapes queued on the render thread as a line list.
+pub struct DebugDraw {
+    device: Arc<Device>,
+    pipeline: DebugPipeline,
+}
+
+impl DebugDraw {
+    pub fn new(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let pipeline = mk_pipeline(device.clone(), render_pass);
+
+        DebugDraw { device, pipeline }
+    }
+
+    /// Rebuilds the pipeline for a new render pass.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        self.pipeline = mk_pipeline(self.device.clone(), render_pass);
+    }
+
+    /// Draws and clears everything queued since the last frame. Records
+    /// nothing when debug drawing is disabled.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        view: View,
+    ) {
+        let verts = QUEUE.with(|q| std::mem::take(&mut *q.borrow_mut()));
+        self.draw_verts(builder, dynamic_state, verts, view);
+    }
+
+    /// Draws lines given in clip space, e.g. for overlays. Unlike the
+    /// queued shapes these are drawn even when debug drawing is disabled.
+    pub fn draw_screen(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        lines: &[([f32; 2], [f32; 2], [f32; 4])],
+    ) {
+        let verts = lines
+            .iter()
+            .flat_map(|&(from, to, color)| {
+                let vert = move |anchor| DebugVert {
+                    anchor,
+                    offset: [0.0, 0.0],
+                    color,
+                };
+                vec![vert(from), vert(to)]
+            })
+            .collect();
+
+        // The default view maps world space straight to clip space.
+        self.draw_verts(builder, dynamic_state, verts, View::default());
+    }
+
+    fn draw_verts(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        verts: Vec<DebugVert>,
+        view: View,
+    ) {
+        if verts.is_empty() {
+            return;
+        }
+
+        let verts = CpuBuf::from_iter(
+            self.device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            verts.into_iter(),
+        ).unwrap();
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                dynamic_state,
+                verts,
+                (),
+                view,
+            ).unwrap();
+    }
+}
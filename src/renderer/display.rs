@@ -0,0 +1,303 @@
+// How the window is shown: in a window, borderless across a monitor or with
+// the monitor switched to a video mode of its own. Kept between runs in the
+// platform's config directory, along with where the window was.
+
+use serde::{Deserialize, Serialize};
+use vulkano::swapchain::FullscreenExclusive;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    monitor::{MonitorHandle, VideoMode},
+    window::{Fullscreen, Window},
+};
+
+use crate::files;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DisplayMode {
+    Windowed,
+    /// A window without decorations covering the monitor. Switching to and
+    /// from it is quick, and other windows can go over it.
+    Borderless,
+    /// The monitor switched to one of its video modes, which the driver may
+    /// hand over to the game entirely.
+    Exclusive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Display {
+    pub mode: DisplayMode,
+    /// The last fullscreen mode, which toggling fullscreen goes back to.
+    pub fullscreen: DisplayMode,
+    /// Which of `monitors` to go fullscreen on. One that has gone away falls
+    /// back to the monitor the window is on.
+    pub monitor: usize,
+    /// The size of the video mode for exclusive fullscreen, at its highest
+    /// refresh rate. `None`, or a size the monitor lacks, is its largest.
+    pub resolution: Option<[u32; 2]>,
+    /// The window's inner size and outer position when windowed, in physical
+    /// pixels. Set as it's moved and resized.
+    pub size: [u32; 2],
+    pub position: Option<[i32; 2]>,
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Display {
+            mode: DisplayMode::Windowed,
+            fullscreen: DisplayMode::Borderless,
+            monitor: 0,
+            resolution: None,
+            size: [1280, 720],
+            position: None,
+        }
+    }
+}
+
+/// The monitors the window can go fullscreen on, by name.
+pub fn monitors(window: &Window) -> Vec<String> {
+    window
+        .available_monitors()
+        .enumerate()
+        .map(|(i, monitor)| {
+            monitor
+                .name()
+                .unwrap_or_else(|| format!("Monitor {}", i + 1))
+        })
+        .collect()
+}
+
+/// The sizes of `monitor`'s video modes, largest first.
+pub fn resolutions(window: &Window, monitor: usize) -> Vec<[u32; 2]> {
+    let mut sizes: Vec<[u32; 2]> =
+        match window.available_monitors().nth(monitor) {
+            Some(monitor) => monitor
+                .video_modes()
+                .map(|mode| mode.size().into())
+                .collect(),
+            None => Vec::new(),
+        };
+    sizes.sort_by_key(|&[w, h]| std::cmp::Reverse((w * h, w)));
+    sizes.dedup();
+    sizes
+}
+
+impl Display {
+    /// Where the display settings are kept, if the platform has a config
+    /// directory.
+    pub fn path() -> Option<PathBuf> {
+        Some(dirs::config_dir()?.join("vulkano-test").join("display.json"))
+    }
+
+    /// The settings saved at `path`, or the defaults if there aren't any or
+    /// they can't be read.
+    pub fn load_or_default(path: &Path) -> Self {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(_) => return Display::default(),
+        };
+        match serde_json::from_str(&json) {
+            Ok(display) => display,
+            Err(e) => {
+                println!(
+                    "Failed to load display settings from {:?}: {}",
+                    path, e,
+                );
+                Display::default()
+            }
+        }
+    }
+
+    /// Writes the settings to `path`, never leaving them half written.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json =
+            serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        files::write_atomically(path, json.as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    /// Switches between windowed and the last fullscreen mode.
+    pub fn toggle_fullscreen(&mut self) {
+        self.mode = match self.mode {
+            DisplayMode::Windowed => self.fullscreen,
+            _ => DisplayMode::Windowed,
+        };
+    }
+
+    /// Whether going from `self` to `other` changes the screen, rather than
+    /// just where the window was.
+    pub fn same_screen(&self, other: &Display) -> bool {
+        self.mode == other.mode
+            && (self.mode == DisplayMode::Windowed
+                || (self.monitor == other.monitor
+                    && self.resolution == other.resolution))
+    }
+
+    /// Remembers the window's size and position, if it's in a window and
+    /// not minimized.
+    pub fn track(&mut self, window: &Window) {
+        let size: [u32; 2] = window.inner_size().into();
+        if self.mode != DisplayMode::Windowed
+            || window.fullscreen().is_some()
+            || size[0] == 0
+        {
+            return;
+        }
+        self.size = size;
+        if let Ok(position) = window.outer_position() {
+            self.position = Some(position.into());
+        }
+    }
+
+    /// Puts a new window where it was last time.
+    pub fn place(&self, window: &Window) {
+        window.set_inner_size(PhysicalSize::new(self.size[0], self.size[1]));
+        if let Some([x, y]) = self.position {
+            window.set_outer_position(PhysicalPosition::new(x, y));
+        }
+    }
+
+    /// What to pass to `Window::set_fullscreen`.
+    pub fn fullscreen(&self, window: &Window) -> Option<Fullscreen> {
+        let monitor = window
+            .available_monitors()
+            .nth(self.monitor)
+            .unwrap_or_else(|| window.current_monitor());
+
+        match self.mode {
+            DisplayMode::Windowed => None,
+            DisplayMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+            DisplayMode::Exclusive => match self.video_mode(&monitor) {
+                Some(mode) => Some(Fullscreen::Exclusive(mode)),
+                None => Some(Fullscreen::Borderless(monitor)),
+            },
+        }
+    }
+
+    /// How the swapchain should treat exclusive fullscreen, for drivers
+    /// with `VK_EXT_full_screen_exclusive`.
+    pub fn exclusive(&self) -> FullscreenExclusive {
+        match self.mode {
+            DisplayMode::Exclusive => FullscreenExclusive::Allowed,
+            _ => FullscreenExclusive::Disallowed,
+        }
+    }
+
+    fn video_mode(&self, monitor: &MonitorHandle) -> Option<VideoMode> {
+        let size = |mode: &VideoMode| -> [u32; 2] { mode.size().into() };
+        let modes: Vec<VideoMode> = monitor.video_modes().collect();
+        let wanted: Vec<&VideoMode> = modes
+            .iter()
+            .filter(|mode| Some(size(mode)) == self.resolution)
+            .collect();
+        let candidates = if wanted.is_empty() {
+            modes.iter().collect()
+        } else {
+            wanted
+        };
+
+        candidates
+            .into_iter()
+            .max_by_key(|mode| {
+                let [w, h] = size(mode);
+                (w * h, mode.refresh_rate(), mode.bit_depth())
+            })
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fresh directory for each test, as they run in parallel.
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = format!("display-test-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("display.json")
+    }
+
+    #[test]
+    fn fullscreen_toggles_back_to_the_last_mode() {
+        let mut display = Display::default();
+        display.toggle_fullscreen();
+        assert_eq!(display.mode, DisplayMode::Borderless);
+        display.toggle_fullscreen();
+        assert_eq!(display.mode, DisplayMode::Windowed);
+
+        display.fullscreen = DisplayMode::Exclusive;
+        display.toggle_fullscreen();
+        assert_eq!(display.mode, DisplayMode::Exclusive);
+        display.toggle_fullscreen();
+        assert_eq!(display.mode, DisplayMode::Windowed);
+    }
+
+    #[test]
+    fn only_screen_changes_are_different_screens() {
+        let windowed = Display::default();
+        let moved = Display {
+            size: [640, 480],
+            position: Some([10, 20]),
+            monitor: 1,
+            ..windowed
+        };
+        assert!(windowed.same_screen(&moved));
+
+        let borderless = Display { mode: DisplayMode::Borderless, ..windowed };
+        assert!(!windowed.same_screen(&borderless));
+        let shrunk = Display { size: [1, 1], ..borderless };
+        assert!(borderless.same_screen(&shrunk));
+        let elsewhere = Display { monitor: 1, ..borderless };
+        assert!(!borderless.same_screen(&elsewhere));
+
+        let exclusive = Display { mode: DisplayMode::Exclusive, ..windowed };
+        let resized = Display { resolution: Some([800, 600]), ..exclusive };
+        assert!(!exclusive.same_screen(&resized));
+    }
+
+    #[test]
+    fn settings_survive_saving() {
+        let path = temp_path("save");
+        assert_eq!(Display::load_or_default(&path), Display::default());
+
+        let display = Display {
+            mode: DisplayMode::Exclusive,
+            monitor: 2,
+            resolution: Some([1920, 1080]),
+            position: Some([-5, 40]),
+            ..Display::default()
+        };
+        display.save(&path).unwrap();
+        display.save(&path).unwrap();
+        assert_eq!(Display::load_or_default(&path), display);
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn missing_settings_take_their_defaults() {
+        let path = temp_path("partial");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        fs::write(&path, r#"{ "mode": "borderless", "monitor": 1 }"#)
+            .unwrap();
+        let partial = Display {
+            mode: DisplayMode::Borderless,
+            monitor: 1,
+            ..Display::default()
+        };
+        assert_eq!(Display::load_or_default(&path), partial);
+
+        fs::write(&path, r#"{ "mode": "sideways" }"#).unwrap();
+        assert_eq!(Display::load_or_default(&path), Display::default());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use vulkano::{
+    buffer::{
+        BufferAccess,
+        BufferSlice,
+        BufferUsage,
+        CpuAccessibleBuffer as CpuBuf,
+        TypedBufferAccess,
+    },
+    device::Device,
+    sync::{FenceSignalFuture, GpuFuture},
+};
+
+use std::sync::Arc;
+
+use super::InstVert;
+
+/// The most frames that `Settings::frames_in_flight` can be set to.
+pub const MAX_FRAMES_IN_FLIGHT: u32 = 3;
+
+// Instance buffers start with room for this many vertices.
+const MIN_CAPACITY: usize = 1024;
+
+pub type FrameFence = Arc<FenceSignalFuture<Box<dyn GpuFuture>>>;
+
+/// The vertices drawn from an instance buffer this frame.
+pub type InstSlice = BufferSlice<[InstVert], Arc<CpuBuf<[InstVert]>>>;
+
+// What one frame in flight owns. The GPU may read it until the fence is
+// signaled.
+struct Frame {
+    inst: Arc<CpuBuf<[InstVert]>>,
+    fence: Option<FrameFence>,
+}
+
+/// Per frame resources, reused round-robin. Before a slot is reused the
+/// frame that last used it is waited for, which also caps how many frames
+/// the CPU can get ahead of the GPU.
+pub struct Frames {
+    device: Arc<Device>,
+    frames: Vec<Frame>,
+    current: usize,
+}
+
+fn mk_inst_buf(
+    device: Arc<Device>,
+    capacity: usize,
+) -> Arc<CpuBuf<[InstVert]>> {
+    CpuBuf::from_iter(
+        device,
+        BufferUsage::vertex_buffer(),
+        false,
+        (0..capacity).map(|_| InstVert::default()),
+    ).unwrap()
+}
+
+impl Frames {
+    pub fn new(device: Arc<Device>, count: u32) -> Self {
+        let frames = (0..count)
+            .map(|_| Frame {
+                inst: mk_inst_buf(device.clone(), MIN_CAPACITY),
+                fence: None,
+            })
+            .collect();
+
+        Frames { device, frames, current: 0 }
+    }
+
+    /// Waits for every frame in flight, then changes how many there can be.
+    pub fn resize(&mut self, count: u32) {
+        self.wait_all();
+        *self = Frames::new(self.device.clone(), count);
+    }
+
+    pub fn wait_all(&mut self) {
+        for frame in self.frames.iter_mut() {
+            if let Some(fence) = frame.fence.take() {
+                fence.wait(None).unwrap();
+            }
+        }
+    }
+
+    /// Moves on to the next slot, blocking until the GPU is done with the
+    /// frame that used it last.
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.frames.len();
+
+        if let Some(fence) = self.frames[self.current].fence.take() {
+            fence.wait(None).unwrap();
+        }
+    }
+
+    /// Copies `verts` into this frame's instance buffer, growing it if they
+    /// do not fit.
+    pub fn inst_buf(&mut self, verts: &[InstVert]) -> InstSlice {
+        let frame = &mut self.frames[self.current];
+
+        if verts.len() > frame.inst.len() {
+            let capacity = verts.len().next_power_of_two();
+            frame.inst = mk_inst_buf(self.device.clone(), capacity);
+        }
+
+        frame.inst.write().unwrap()[..verts.len()].copy_from_slice(verts);
+
+        frame.inst.clone()
+            .into_buffer_slice()
+            .slice(0..verts.len())
+            .unwrap()
+    }
+
+    /// Sets the fence signaled when this frame is done.
+    pub fn set_fence(&mut self, fence: FrameFence) {
+        self.frames[self.current].fence = Some(fence);
+    }
+}
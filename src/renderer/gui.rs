@@ -0,0 +1,443 @@
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer as CpuBuf},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+        PipelineLayoutAbstract,
+    },
+    device::{Device, Queue},
+    format::Format,
+    framebuffer::{RenderPassAbstract, Subpass},
+    image::{
+        Dimensions,
+        ImageLayout,
+        ImageUsage,
+        ImmutableImage,
+        MipmapsCount,
+    },
+    pipeline::{
+        blend::{AttachmentBlend, BlendFactor},
+        viewport::Scissor,
+        vertex::SingleBufferDefinition,
+        GraphicsPipeline,
+    },
+    sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode},
+};
+
+use winit::event::{
+    ElementState,
+    MouseButton,
+    MouseScrollDelta,
+    VirtualKeyCode,
+    WindowEvent,
+};
+
+use egui::{ClippedMesh, CtxRef, Event, Key, Modifiers, PointerButton, Pos2,
+    RawInput, Rect};
+
+use std::sync::Arc;
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "assets/shaders/gui_vertex.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "assets/shaders/gui_fragment.glsl"
+    }
+}
+
+// Points scrolled per line for mouse wheels that report lines.
+const SCROLL_LINE: f32 = 50.0;
+
+// `pos` is in clip space, the rest is as egui outputs it.
+#[derive(Default, Debug, Clone, Copy)]
+struct GuiVert {
+    pos: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(GuiVert, pos, uv, color);
+
+type GuiPipeline = Arc<GraphicsPipeline<
+    SingleBufferDefinition<GuiVert>,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+    Arc<dyn RenderPassAbstract + Send + Sync>>
+>;
+
+fn mk_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> GuiPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+
+    // egui outputs premultiplied alpha.
+    let blend = AttachmentBlend {
+        color_source: BlendFactor::One,
+        alpha_source: BlendFactor::One,
+        ..AttachmentBlend::alpha_blending()
+    };
+
+    Arc::new(
+        GraphicsPipeline::start()
+        .vertex_input_single_buffer()
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_list()
+        // Every mesh is clipped to its own rectangle.
+        .viewports_scissors_dynamic(1)
+        .fragment_shader(fs.main_entry_point(), ())
+        .blend_collective(blend)
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device).unwrap(),
+    )
+}
+
+/// An egui context drawn on top of the frame. Window events are fed to it
+/// with `handle_event` and widgets are added between `Renderer::gui_frame`
+/// and `end_frame`.
+pub struct Gui {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: GuiPipeline,
+    sampler: Arc<Sampler>,
+    ctx: CtxRef,
+    input: RawInput,
+    pointer: Pos2,
+    modifiers: Modifiers,
+    scale: f32,
+    // The font texture and the egui version it was uploaded from.
+    font: Option<(u64, Arc<dyn DescriptorSet + Send + Sync>)>,
+    meshes: Vec<ClippedMesh>,
+    pub visible: bool,
+}
+
+impl Gui {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let pipeline = mk_pipeline(device.clone(), render_pass);
+
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        ).unwrap();
+
+        Gui {
+            device,
+            queue,
+            pipeline,
+            sampler,
+            ctx: CtxRef::default(),
+            input: RawInput::default(),
+            pointer: Pos2::default(),
+            modifiers: Modifiers::default(),
+            scale: 1.0,
+            font: None,
+            meshes: Vec::new(),
+            visible: false,
+        }
+    }
+
+    /// Rebuilds the pipeline for a new render pass.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        self.pipeline = mk_pipeline(self.device.clone(), render_pass);
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+        self.meshes.clear();
+    }
+
+    /// Passes a window event to egui. Returns true when egui uses it, in
+    /// which case the game should ignore it.
+    pub fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer = Pos2::new(
+                    position.x as f32 / self.scale,
+                    position.y as f32 / self.scale,
+                );
+                self.input.events.push(Event::PointerMoved(self.pointer));
+                self.ctx.wants_pointer_input()
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.input.events.push(Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => PointerButton::Primary,
+                    MouseButton::Right => PointerButton::Secondary,
+                    MouseButton::Middle => PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+
+                self.input.events.push(Event::PointerButton {
+                    pos: self.pointer,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                self.ctx.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.input.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(x, y) =>
+                        egui::vec2(*x, *y) * SCROLL_LINE,
+                    MouseScrollDelta::PixelDelta(pos) =>
+                        egui::vec2(pos.x as f32, pos.y as f32) / self.scale,
+                };
+                self.ctx.wants_pointer_input()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = Modifiers {
+                    alt: state.alt(),
+                    ctrl: state.ctrl(),
+                    shift: state.shift(),
+                    mac_cmd: cfg!(target_os = "macos") && state.logo(),
+                    command: if cfg!(target_os = "macos") {
+                        state.logo()
+                    } else {
+                        state.ctrl()
+                    },
+                };
+                self.input.modifiers = self.modifiers;
+                false
+            }
+            WindowEvent::ReceivedCharacter(c) => {
+                if !c.is_control() {
+                    self.input.events.push(Event::Text(c.to_string()));
+                }
+                self.ctx.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput { input, .. } => {
+                let key = match input.virtual_keycode.and_then(translate_key) {
+                    Some(key) => key,
+                    None => return self.ctx.wants_keyboard_input(),
+                };
+
+                self.input.events.push(Event::Key {
+                    key,
+                    pressed: input.state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                self.ctx.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Starts a frame for a screen of `size` pixels with `scale` pixels per
+    /// point. Widgets are added to the returned context.
+    pub fn begin_frame(&mut self, size: [f32; 2], scale: f32) -> CtxRef {
+        self.scale = scale;
+
+        let mut input = std::mem::take(&mut self.input);
+        input.screen_rect = Some(Rect::from_min_size(
+            Pos2::ZERO,
+            egui::vec2(size[0], size[1]) / scale,
+        ));
+        input.pixels_per_point = Some(scale);
+        input.modifiers = self.modifiers;
+
+        self.ctx.begin_frame(input);
+        self.ctx.clone()
+    }
+
+    /// Finishes the frame started by `begin_frame` and keeps its meshes to
+    /// be drawn with the next frame.
+    pub fn end_frame(&mut self) {
+        let (_output, shapes) = self.ctx.end_frame();
+        self.meshes = self.ctx.tessellate(shapes);
+    }
+
+    /// Uploads the font texture when egui changed it. Must be recorded
+    /// outside of a render pass.
+    pub fn update(&mut self, builder: &mut AutoCommandBufferBuilder) {
+        if self.meshes.is_empty() {
+            return;
+        }
+
+        let texture = self.ctx.texture();
+        if let Some((version, _)) = &self.font {
+            if *version == texture.version {
+                return;
+            }
+        }
+
+        let (image, init) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            Dimensions::Dim2d {
+                width: texture.width as u32,
+                height: texture.height as u32,
+            },
+            Format::R8G8B8A8Srgb,
+            MipmapsCount::One,
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(self.queue.family()),
+        ).unwrap();
+
+        let pixels = texture
+            .srgba_pixels(1.0)
+            .flat_map(|c| c.to_array())
+            .collect::<Vec<_>>();
+        let pixels = CpuBuf::from_iter(
+            self.device.clone(),
+            BufferUsage::transfer_source(),
+            false,
+            pixels.into_iter(),
+        ).unwrap();
+
+        builder.copy_buffer_to_image(pixels, init).unwrap();
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(
+                self.pipeline.descriptor_set_layout(0).unwrap().clone()
+            )
+            .add_sampled_image(image, self.sampler.clone()).unwrap()
+            .build().unwrap()
+        );
+
+        self.font = Some((texture.version, set));
+    }
+
+    /// Records a draw per mesh of the last frame. Must be called inside the
+    /// render pass, after everything the GUI should cover.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+    ) {
+        let set = match &self.font {
+            Some((_, set)) if self.visible => set,
+            _ => return,
+        };
+
+        let viewport = &dynamic_state.viewports.as_ref().unwrap()[0];
+        let size = viewport.dimensions;
+        let screen_size = [size[0] / self.scale, size[1] / self.scale];
+
+        for ClippedMesh(clip, mesh) in self.meshes.iter() {
+            if mesh.indices.is_empty() {
+                continue;
+            }
+
+            // The clip rectangle is in points, scissors are in pixels.
+            let min = [
+                (clip.min.x * self.scale).clamp(0.0, size[0]),
+                (clip.min.y * self.scale).clamp(0.0, size[1]),
+            ];
+            let max = [
+                (clip.max.x * self.scale).clamp(min[0], size[0]),
+                (clip.max.y * self.scale).clamp(min[1], size[1]),
+            ];
+            if max[0] - min[0] < 1.0 || max[1] - min[1] < 1.0 {
+                continue;
+            }
+
+            let mut dynamic_state = dynamic_state.clone();
+            dynamic_state.scissors = Some(vec![Scissor {
+                origin: [min[0] as i32, min[1] as i32],
+                dimensions: [
+                    (max[0] - min[0]).round() as u32,
+                    (max[1] - min[1]).round() as u32,
+                ],
+            }]);
+
+            let verts = CpuBuf::from_iter(
+                self.device.clone(),
+                BufferUsage::vertex_buffer(),
+                false,
+                mesh.vertices.iter().map(|v| {
+                    let [r, g, b, a] = v.color.to_array();
+                    GuiVert {
+                        pos: [
+                            v.pos.x / screen_size[0] * 2.0 - 1.0,
+                            v.pos.y / screen_size[1] * 2.0 - 1.0,
+                        ],
+                        uv: [v.uv.x, v.uv.y],
+                        color: [
+                            r as f32 / 255.0,
+                            g as f32 / 255.0,
+                            b as f32 / 255.0,
+                            a as f32 / 255.0,
+                        ],
+                    }
+                }),
+            ).unwrap();
+
+            let indices = CpuBuf::from_iter(
+                self.device.clone(),
+                BufferUsage::index_buffer(),
+                false,
+                mesh.indices.iter().cloned(),
+            ).unwrap();
+
+            builder
+                .draw_indexed(
+                    self.pipeline.clone(),
+                    &dynamic_state,
+                    verts,
+                    indices,
+                    set.clone(),
+                    (),
+                ).unwrap();
+        }
+    }
+}
+
+fn translate_key(key: VirtualKeyCode) -> Option<Key> {
+    Some(match key {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Z => Key::Z,
+        _ => return None,
+    })
+}
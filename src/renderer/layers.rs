@@ -0,0 +1,101 @@
+use vulkano::{
+    command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder},
+    device::Device,
+    framebuffer::{RenderPassAbstract, Subpass},
+    instance::QueueFamily,
+};
+
+use rayon::prelude::*;
+
+use std::sync::Arc;
+
+/// Records the draws of one layer into a secondary command buffer.
+pub type Layer<'a> = dyn Fn(&mut AutoCommandBufferBuilder) + Sync + 'a;
+
+/// Starts a secondary command buffer for the render pass, to be executed
+/// once.
+pub fn secondary(
+    device: Arc<Device>,
+    family: QueueFamily<'_>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> AutoCommandBufferBuilder {
+    AutoCommandBufferBuilder::secondary_graphics_one_time_submit(
+        device,
+        family,
+        Subpass::from(render_pass, 0).unwrap(),
+    ).unwrap()
+}
+
+// Starts a secondary command buffer for the render pass, which may be
+// executed by several primary command buffers at once.
+fn simultaneous(
+    device: Arc<Device>,
+    family: QueueFamily<'_>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> AutoCommandBufferBuilder {
+    AutoCommandBufferBuilder::secondary_graphics_simultaneous_use(
+        device,
+        family,
+        Subpass::from(render_pass, 0).unwrap(),
+    ).unwrap()
+}
+
+/// Records each layer into its own secondary command buffer, in the same
+/// order. With `parallel` the layers are recorded on rayon's thread pool,
+/// whose threads live on between frames and keep their command pools.
+pub fn record(
+    device: &Arc<Device>,
+    family: QueueFamily<'_>,
+    render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+    layers: &[&Layer<'_>],
+    parallel: bool,
+) -> Vec<AutoCommandBuffer> {
+    let record = |layer: &&Layer<'_>| {
+        let mut builder =
+            secondary(device.clone(), family, render_pass.clone());
+        layer(&mut builder);
+        builder.build().unwrap()
+    };
+
+    if parallel {
+        layers.par_iter().map(record).collect()
+    } else {
+        layers.iter().map(record).collect()
+    }
+}
+
+/// A layer whose draws rarely change, such as the background. It is
+/// recorded once and replayed every frame until invalidated, which has to
+/// happen whenever its inputs, the render pass or the framebuffers change.
+pub struct CachedLayer {
+    commands: Option<Arc<AutoCommandBuffer>>,
+}
+
+impl CachedLayer {
+    pub fn new() -> Self {
+        CachedLayer { commands: None }
+    }
+
+    pub fn invalidate(&mut self) {
+        self.commands = None;
+    }
+
+    /// The recorded commands, recorded with `layer` first if there are none.
+    pub fn commands(
+        &mut self,
+        device: &Arc<Device>,
+        family: QueueFamily<'_>,
+        render_pass: &Arc<dyn RenderPassAbstract + Send + Sync>,
+        layer: &Layer<'_>,
+    ) -> Arc<AutoCommandBuffer> {
+        self.commands
+            .get_or_insert_with(|| {
+                // Frames in flight may run the same commands at once.
+                let mut builder =
+                    simultaneous(device.clone(), family, render_pass.clone());
+                layer(&mut builder);
+                Arc::new(builder.build().unwrap())
+            })
+            .clone()
+    }
+}
@@ -1,7 +1,7 @@
 extern crate lyon;
-use lyon::math::{point, Point};
+use lyon::math::Point;
 use lyon::path::Path;
-use lyon::path::builder::*;
+use lyon::svg::path_utils::build_path;
 use lyon::tessellation::*;
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -9,45 +9,58 @@ pub struct Vertex {
     pub pos: [f32; 2],
 }
 
-pub fn ship_mesh() -> Vec<Vertex> {
-    let mut builder = Path::builder();
-    builder.move_to(point(0.0, -1.0));
-    builder.line_to(point(-1.0, 1.0));
-    builder.line_to(point(0.0, 0.0));
-    builder.line_to(point(1.0, 1.0));
+/// Index of a mesh in `MESHES`, and of its instance group.
+pub type MeshId = usize;
 
-    let path = builder.build();
-    let mut geometry: VertexBuffers<Vertex, u16> = VertexBuffers::new();
-    let mut tessellator = FillTessellator::new();
-
-    {
-        // Compute the tessellation.
-        tessellator.tessellate_path(
-            &path,
-            &FillOptions::default(),
-            &mut BuffersBuilder::new(&mut geometry, |pos: Point, _: FillAttributes| {
-                Vertex {
-                    pos: pos.to_array(),
-                }
-            }),
-        ).unwrap();
-    }
+/// Meshes known to the renderer, in the order their instance groups are
+/// passed to `Renderer::redraw`. The path data is baked into the binary so the
+/// game still starts without an assets directory, and the names match the
+/// files in `assets/meshes` that are watched for hot reloading.
+pub const MESHES: [(&str, &str); 3] = [
+    ("ship", include_str!("../../assets/meshes/ship.path")),
+    ("asteroid", include_str!("../../assets/meshes/asteroid.path")),
+    ("bullet", include_str!("../../assets/meshes/bullet.path")),
+];
 
-    geometry.indices
+pub fn builtin_meshes() -> Vec<Vec<Vertex>> {
+    MESHES
         .iter()
-        .map(|i| geometry.vertices[usize::from(*i)])
-        .collect::<Vec<Vertex>>()
+        .map(|(name, data)| {
+            tessellate(data)
+                .unwrap_or_else(|e| panic!("Invalid mesh {}: {}", name, e))
+        })
+        .collect()
 }
 
-pub fn asteroid_mesh() -> Vec<Vertex> {
-    let mut builder = Path::builder();
-    builder.move_to(point(0.0, 0.0));
-    builder.line_to(point(1.0, 0.0));
-    builder.quadratic_bezier_to(point(2.0, 0.0), point(2.0, 1.0));
-    builder.cubic_bezier_to(point(2.0, 2.0), point(0.0, 2.0), point(0.0, 0.0));
-    builder.close();
+/// Index of the mesh called `name` in `MESHES`.
+pub fn mesh_index(name: &str) -> Option<MeshId> {
+    MESHES.iter().position(|(mesh, _)| *mesh == name)
+}
 
-    let path = builder.build();// Build a simple path.
+/// Radius of the smallest circle around the origin that contains the mesh.
+/// Instances rotate about the origin, so this bounds them at any angle.
+pub fn bounding_radius(mesh: &[Vertex]) -> f32 {
+    mesh.iter()
+        .map(|v| (v.pos[0] * v.pos[0] + v.pos[1] * v.pos[1]).sqrt())
+        .fold(0.0, f32::max)
+}
+
+/// Area covered by the mesh's triangles, in mesh units.
+pub fn area(mesh: &[Vertex]) -> f32 {
+    mesh.chunks_exact(3)
+        .map(|t| {
+            let [ax, ay] = t[0].pos;
+            let [bx, by] = t[1].pos;
+            let [cx, cy] = t[2].pos;
+            ((bx - ax) * (cy - ay) - (cx - ax) * (by - ay)).abs() / 2.0
+        })
+        .sum()
+}
+
+/// Turns SVG path data (the `d` attribute of a `<path>`) into a triangle list.
+pub fn tessellate(svg: &str) -> Result<Vec<Vertex>, String> {
+    let path = build_path(Path::builder().with_svg(), svg)
+        .map_err(|e| format!("{:?}", e))?;
 
     let mut geometry: VertexBuffers<Vertex, u16> = VertexBuffers::new();
     let mut tessellator = FillTessellator::new();
@@ -62,12 +75,15 @@ pub fn asteroid_mesh() -> Vec<Vertex> {
                     pos: pos.to_array(),
                 }
             }),
-        ).unwrap();
+        ).map_err(|e| format!("{:?}", e))?;
     }
 
-    geometry.indices
+    if geometry.indices.is_empty() {
+        return Err("path produced no triangles".to_string());
+    }
+
+    Ok(geometry.indices
         .iter()
         .map(|i| geometry.vertices[usize::from(*i)])
-        .collect::<Vec<Vertex>>()
+        .collect::<Vec<Vertex>>())
 }
-
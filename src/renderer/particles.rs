@@ -0,0 +1,461 @@
+use vulkano::{
+    buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer as CpuBuf},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+        PipelineLayoutAbstract,
+    },
+    device::{Device, Queue},
+    framebuffer::{RenderPassAbstract, Subpass},
+    pipeline::{
+        vertex::OneVertexOneInstanceDefinition,
+        ComputePipeline,
+        ComputePipelineAbstract,
+        GraphicsPipeline,
+    },
+};
+
+use rand::Rng;
+
+use std::sync::Arc;
+
+use super::View;
+
+mod sim_cs {
+    vulkano_shaders::shader! {
+        ty: "compute",
+        path: "assets/shaders/particles.glsl"
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "assets/shaders/particle_vertex.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "assets/shaders/particle_fragment.glsl"
+    }
+}
+
+/// Maximum number of live particles. When full, the oldest particles are
+/// overwritten. Must be a multiple of the simulation's work group size.
+pub const CAPACITY: usize = 4096;
+const SIM_GROUP: usize = 64;
+
+// Must match `DRAG` in particles.glsl.
+const DRAG: f32 = 0.97;
+
+/// Layout matches the `Particle` struct in particles.glsl (std430). Velocity
+/// is in units per frame and life is counted in frames, like the rest of the
+/// simulation.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+pub struct Particle {
+    pub pos: [f32; 2],
+    pub vel: [f32; 2],
+    pub life: f32,
+    pub max_life: f32,
+}
+
+vulkano::impl_vertex!(Particle, pos, vel, life, max_life);
+
+// Push constants of particles.glsl.
+#[repr(C)]
+struct World {
+    half_size: f32,
+}
+
+/// Advances particles by one frame, wrapping them around a world that spans
+/// `-half_size..half_size`. This is the CPU version of particles.glsl, used
+/// when the graphics queue cannot run compute shaders.
+pub fn step(particles: &mut [Particle], half_size: f32) {
+    for p in particles.iter_mut().filter(|p| p.life > 0.0) {
+        p.pos[0] += p.vel[0];
+        p.pos[1] += p.vel[1];
+        p.vel[0] *= DRAG;
+        p.vel[1] *= DRAG;
+        p.life -= 1.0;
+
+        for i in 0..2 {
+            if p.pos[i] > half_size {
+                p.pos[i] -= 2.0 * half_size;
+            } else if p.pos[i] < -half_size {
+                p.pos[i] += 2.0 * half_size;
+            }
+        }
+    }
+}
+
+/// Spawns particles from a point in a cone, e.g. attached to a ship's engine.
+#[derive(Debug, Clone)]
+pub struct Emitter {
+    /// Particles per frame. Fractional rates carry over to the next frame.
+    pub rate: f32,
+    /// Half angle of the cone, in degrees.
+    pub spread: f32,
+    pub speed: [f32; 2],
+    pub life: [f32; 2],
+    pending: f32,
+}
+
+impl Emitter {
+    pub fn new(rate: f32, spread: f32, speed: [f32; 2], life: [f32; 2]) ->
+        Self
+    {
+        Emitter { rate, spread, speed, life, pending: 0.0 }
+    }
+
+    /// Engine exhaust, emitted continuously while thrusting.
+    pub fn exhaust() -> Self {
+        Emitter::new(2.0, 15.0, [0.004, 0.008], [15.0, 30.0])
+    }
+
+    /// Debris flying out in every direction.
+    pub fn explosion() -> Self {
+        Emitter::new(0.0, 180.0, [0.002, 0.012], [30.0, 60.0])
+    }
+
+    /// Emits this frame's particles. `angle` is the direction of the cone in
+    /// degrees, using the same convention as the ship, and `base_vel` is added
+    /// to every particle so they inherit the motion of their entity.
+    pub fn emit<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        pos: [f32; 2],
+        angle: f32,
+        base_vel: [f32; 2],
+        out: &mut Vec<Particle>,
+    ) {
+        self.pending += self.rate;
+        let count = self.pending.floor();
+        self.pending -= count;
+
+        self.burst(rng, count as usize, pos, angle, base_vel, out);
+    }
+
+    /// Emits `count` particles at once, e.g. when something explodes.
+    pub fn burst<R: Rng>(
+        &self,
+        rng: &mut R,
+        count: usize,
+        pos: [f32; 2],
+        angle: f32,
+        base_vel: [f32; 2],
+        out: &mut Vec<Particle>,
+    ) {
+        for _ in 0..count {
+            let dir = (angle + rng.gen_range(-self.spread, self.spread))
+                .to_radians();
+            let speed = rng.gen_range(self.speed[0], self.speed[1]);
+            let life = rng.gen_range(self.life[0], self.life[1]);
+
+            out.push(Particle {
+                pos,
+                vel: [
+                    base_vel[0] + dir.sin() * speed,
+                    base_vel[1] + dir.cos() * speed,
+                ],
+                life,
+                max_life: life,
+            });
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+struct Corner {
+    corner: [f32; 2],
+}
+
+vulkano::impl_vertex!(Corner, corner);
+
+type ParticlePipeline = Arc<GraphicsPipeline<
+    OneVertexOneInstanceDefinition<Corner, Particle>,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+    Arc<dyn RenderPassAbstract + Send + Sync>>
+>;
+
+fn mk_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> ParticlePipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+
+    Arc::new(
+        GraphicsPipeline::start()
+        .vertex_input(OneVertexOneInstanceDefinition::<Corner, Particle>::new())
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(fs.main_entry_point(), ())
+        // Particles fade out as they die. Being translucent they are
+        // hidden by what is in front but do not hide anything themselves.
+        .blend_alpha_blending()
+        .depth_stencil(super::depth_stencil(&render_pass, false))
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device).unwrap(),
+    )
+}
+
+/// Where the particles live and how they are stepped.
+enum Sim {
+    /// A ring buffer that is updated in place by particles.glsl.
+    Gpu {
+        pipeline: Arc<dyn ComputePipelineAbstract + Send + Sync>,
+        set: Arc<dyn DescriptorSet + Send + Sync>,
+        buf: Arc<CpuBuf<[Particle]>>,
+        head: usize,
+    },
+    /// Stepped with `step` and uploaded every frame, like `mk_inst_buf`.
+    Cpu(Vec<Particle>),
+}
+
+pub struct Particles {
+    device: Arc<Device>,
+    pipeline: ParticlePipeline,
+    quad: Arc<CpuBuf<[Corner]>>,
+    sim: Sim,
+    spawned: Vec<Particle>,
+}
+
+impl Particles {
+    pub fn new(
+        device: Arc<Device>,
+        queue: &Queue,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let pipeline = mk_pipeline(device.clone(), render_pass);
+
+        let quad = CpuBuf::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            [
+                [-1.0, -1.0], [1.0, -1.0], [1.0, 1.0],
+                [-1.0, -1.0], [1.0, 1.0], [-1.0, 1.0],
+            ].iter().map(|&corner| Corner { corner }),
+        ).unwrap();
+
+        let sim = if queue.family().supports_compute() {
+            let cs = sim_cs::Shader::load(device.clone()).unwrap();
+            let pipeline = Arc::new(
+                ComputePipeline::new(
+                    device.clone(),
+                    &cs.main_entry_point(),
+                    &(),
+                ).unwrap()
+            );
+
+            // Starts out full of dead particles.
+            let buf = CpuBuf::from_iter(
+                device.clone(),
+                BufferUsage {
+                    storage_buffer: true,
+                    vertex_buffer: true,
+                    transfer_destination: true,
+                    ..BufferUsage::none()
+                },
+                false,
+                (0..CAPACITY).map(|_| Particle::default()),
+            ).unwrap();
+
+            let set = Arc::new(
+                PersistentDescriptorSet::start(
+                    pipeline.descriptor_set_layout(0).unwrap().clone()
+                )
+                .add_buffer(buf.clone()).unwrap()
+                .build().unwrap()
+            );
+
+            Sim::Gpu { pipeline, set, buf, head: 0 }
+        } else {
+            Sim::Cpu(Vec::new())
+        };
+
+        Particles {
+            device,
+            pipeline,
+            quad,
+            sim,
+            spawned: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the pipeline for a new render pass. Live particles are kept.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        self.pipeline = mk_pipeline(self.device.clone(), render_pass);
+    }
+
+    /// Queues particles to be added on the next frame.
+    pub fn spawn<I: IntoIterator<Item = Particle>>(&mut self, particles: I) {
+        self.spawned.extend(particles);
+    }
+
+    /// Forgets the particles queued, for a frame that isn't drawn.
+    pub fn clear(&mut self) {
+        self.spawned.clear();
+    }
+
+    /// Adds the queued particles and steps the simulation by one frame. Must
+    /// be recorded outside of a render pass.
+    pub fn update(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder,
+        half_size: f32,
+    ) {
+        let spawned = std::mem::take(&mut self.spawned);
+
+        match &mut self.sim {
+            Sim::Gpu { pipeline, set, buf, head } => {
+                // Anything beyond the capacity would overwrite itself.
+                let skipped = spawned.len().saturating_sub(CAPACITY);
+                let spawned = &spawned[skipped..];
+
+                if !spawned.is_empty() {
+                    let src = CpuBuf::from_iter(
+                        self.device.clone(),
+                        BufferUsage::transfer_source(),
+                        false,
+                        spawned.iter().cloned(),
+                    ).unwrap();
+
+                    // Copy into the ring, in two parts if it wraps around.
+                    let first = spawned.len().min(CAPACITY - *head);
+                    copy(builder, &src, 0..first, buf, *head);
+                    if first < spawned.len() {
+                        copy(builder, &src, first..spawned.len(), buf, 0);
+                    }
+
+                    *head = (*head + spawned.len()) % CAPACITY;
+                }
+
+                builder
+                    .dispatch(
+                        [(CAPACITY / SIM_GROUP) as u32, 1, 1],
+                        pipeline.clone(),
+                        set.clone(),
+                        World { half_size },
+                    ).unwrap();
+            }
+            Sim::Cpu(particles) => {
+                particles.retain(|p| p.life > 0.0);
+                particles.extend(spawned);
+
+                let excess = particles.len().saturating_sub(CAPACITY);
+                particles.drain(..excess);
+
+                step(particles, half_size);
+            }
+        }
+    }
+
+    /// Records the draw. Must be called inside the render pass.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        view: View,
+    ) {
+        let instances = match &self.sim {
+            Sim::Gpu { buf, .. } => buf.clone(),
+            Sim::Cpu(particles) if particles.is_empty() => return,
+            Sim::Cpu(particles) => CpuBuf::from_iter(
+                self.device.clone(),
+                BufferUsage::vertex_buffer(),
+                false,
+                particles.iter().cloned(),
+            ).unwrap(),
+        };
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                dynamic_state,
+                (self.quad.clone(), instances),
+                (),
+                view,
+            ).unwrap();
+    }
+}
+
+fn copy(
+    builder: &mut AutoCommandBufferBuilder,
+    src: &Arc<CpuBuf<[Particle]>>,
+    range: std::ops::Range<usize>,
+    dst: &Arc<CpuBuf<[Particle]>>,
+    offset: usize,
+) {
+    let len = range.len();
+
+    builder
+        .copy_buffer(
+            src.clone().into_buffer_slice().slice(range).unwrap(),
+            dst.clone()
+                .into_buffer_slice()
+                .slice(offset..offset + len)
+                .unwrap(),
+        ).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn particle(pos: [f32; 2], vel: [f32; 2], life: f32) -> Particle {
+        Particle { pos, vel, life, max_life: 10.0 }
+    }
+
+    #[test]
+    fn particles_move_and_slow_down() {
+        let mut particles = [particle([0.1, -0.2], [0.01, 0.02], 10.0)];
+        step(&mut particles, 1.0);
+
+        let p = particles[0];
+        assert!((p.pos[0] - 0.11).abs() < 1e-6);
+        assert!((p.pos[1] + 0.18).abs() < 1e-6);
+        assert!((p.vel[0] - 0.01 * DRAG).abs() < 1e-6);
+        assert!((p.vel[1] - 0.02 * DRAG).abs() < 1e-6);
+    }
+
+    #[test]
+    fn particles_age_and_stay_dead() {
+        let mut particles = [
+            particle([0.0, 0.0], [0.01, 0.0], 2.0),
+            particle([0.5, 0.5], [0.01, 0.0], 0.0),
+        ];
+        step(&mut particles, 1.0);
+        assert_eq!(particles[0].life, 1.0);
+        step(&mut particles, 1.0);
+        assert_eq!(particles[0].life, 0.0);
+
+        let moved = particles[0].pos;
+        step(&mut particles, 1.0);
+        assert_eq!(particles[0].pos, moved);
+        assert_eq!(particles[1], particle([0.5, 0.5], [0.01, 0.0], 0.0));
+    }
+
+    #[test]
+    fn particles_wrap_around_the_world() {
+        let mut particles = [
+            particle([0.49, -0.49], [0.02, -0.02], 5.0),
+            particle([-0.49, 0.49], [-0.02, 0.02], 5.0),
+        ];
+        step(&mut particles, 0.5);
+
+        let expected = [[-0.49, 0.49], [0.49, -0.49]];
+        for (p, expected) in particles.iter().zip(expected.iter()) {
+            assert!((p.pos[0] - expected[0]).abs() < 1e-6, "{:?}", p.pos);
+            assert!((p.pos[1] - expected[1]).abs() < 1e-6, "{:?}", p.pos);
+        }
+    }
+}
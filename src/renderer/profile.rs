@@ -0,0 +1,466 @@
+use vulkano::{
+    command_buffer::{
+        pool::standard::StandardCommandPoolBuilder,
+        sys::UnsafeCommandBufferBuilder,
+    },
+    device::{DeviceOwned, Queue},
+    query::{QueryType, UnsafeQueryPool},
+    sync::PipelineStages,
+    VulkanObject,
+};
+
+use super::unsynced::Unsynced;
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Frames kept for the graph.
+const HISTORY: usize = 120;
+// Frame time at the top of the graph, in milliseconds.
+const GRAPH_MS: f32 = 1000.0 / 30.0;
+const BUDGET_MS: f32 = 1000.0 / 60.0;
+
+// Where the graph is drawn, in clip space.
+const GRAPH_MIN: [f32; 2] = [-0.95, 0.6];
+const GRAPH_MAX: [f32; 2] = [-0.35, 0.95];
+pub(super) const TEXT_SIZE: f32 = 0.02;
+
+const CPU_COLOR: [f32; 4] = [0.3, 1.0, 0.3, 1.0];
+const GPU_COLOR: [f32; 4] = [1.0, 0.6, 0.1, 1.0];
+const FRAME_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+
+// Thread ids in the trace, so CPU and GPU work get a row each.
+const CPU_TID: u32 = 1;
+const GPU_TID: u32 = 2;
+
+// A finished span of work, relative to the recorder's epoch.
+struct Span {
+    name: &'static str,
+    tid: u32,
+    start: Duration,
+    duration: Duration,
+}
+
+struct Recorder {
+    epoch: Instant,
+    last_frame: Option<Instant>,
+    // Milliseconds per frame, oldest first.
+    frames: VecDeque<f32>,
+    gpu: VecDeque<f32>,
+    // Scopes finished this frame and in the last complete one.
+    scopes: Vec<(&'static str, f32)>,
+    last_scopes: Vec<(&'static str, f32)>,
+    // Spans collected while a trace is being recorded.
+    trace: Option<Vec<Span>>,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        Recorder {
+            epoch: Instant::now(),
+            last_frame: None,
+            frames: VecDeque::with_capacity(HISTORY),
+            gpu: VecDeque::with_capacity(HISTORY),
+            scopes: Vec::new(),
+            last_scopes: Vec::new(),
+            trace: None,
+        }
+    }
+
+    fn span(
+        &mut self,
+        name: &'static str,
+        tid: u32,
+        start: Instant,
+        duration: Duration,
+    ) {
+        let epoch = self.epoch;
+        if let Some(trace) = &mut self.trace {
+            trace.push(Span {
+                name,
+                tid,
+                start: start.saturating_duration_since(epoch),
+                duration,
+            });
+        }
+    }
+}
+
+fn push_history(history: &mut VecDeque<f32>, ms: f32) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+    history.push_back(ms);
+}
+
+fn ms(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+// Like debug shapes, timings are recorded per thread and only the render
+// thread's are shown.
+thread_local! {
+    static VISIBLE: Cell<bool> = const { Cell::new(false) };
+    static RECORDER: RefCell<Recorder> = RefCell::new(Recorder::new());
+}
+
+/// Whether the frame time graph is drawn. Timings are recorded either way.
+pub fn visible() -> bool {
+    VISIBLE.with(Cell::get)
+}
+
+pub fn toggle() {
+    VISIBLE.with(|v| v.set(!v.get()));
+}
+
+/// Times the rest of the enclosing block, or until the returned guard is
+/// dropped.
+pub fn scope(name: &'static str) -> Scope {
+    Scope { name, start: Instant::now() }
+}
+
+pub struct Scope {
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        let duration = self.start.elapsed();
+        RECORDER.with(|r| {
+            let mut r = r.borrow_mut();
+            r.scopes.push((self.name, ms(duration)));
+            r.span(self.name, CPU_TID, self.start, duration);
+        });
+    }
+}
+
+/// Marks the start of a frame. The time since the last call is the frame
+/// time shown in the graph.
+pub fn frame() {
+    let now = Instant::now();
+    RECORDER.with(|r| {
+        let mut r = r.borrow_mut();
+        if let Some(last) = r.last_frame {
+            push_history(&mut r.frames, ms(now - last));
+            r.span("frame", CPU_TID, last, now - last);
+        }
+        r.last_frame = Some(now);
+        r.last_scopes = std::mem::take(&mut r.scopes);
+    });
+}
+
+// Records GPU time spent on a frame. The GPU clock is not related to
+// `Instant`, so in traces the span starts when the frame was submitted.
+fn gpu_frame(submitted: Instant, duration: Duration) {
+    RECORDER.with(|r| {
+        let mut r = r.borrow_mut();
+        push_history(&mut r.gpu, ms(duration));
+        r.span("gpu", GPU_TID, submitted, duration);
+    });
+}
+
+/// Whether a trace is being recorded.
+pub fn tracing() -> bool {
+    RECORDER.with(|r| r.borrow().trace.is_some())
+}
+
+/// Starts recording every span for a trace, dropping any unfinished one.
+pub fn start_trace() {
+    RECORDER.with(|r| r.borrow_mut().trace = Some(Vec::new()));
+}
+
+/// Stops recording and returns the trace in Chrome's trace event format,
+/// which `chrome://tracing` and Perfetto can open. Returns `None` if no
+/// trace was being recorded.
+pub fn finish_trace() -> Option<String> {
+    let spans = RECORDER.with(|r| r.borrow_mut().trace.take())?;
+
+    let mut json = String::from("{\"traceEvents\":[\n");
+    for (i, span) in spans.iter().enumerate() {
+        if i > 0 {
+            json.push_str(",\n");
+        }
+        // Names are identifiers from the code, so need no escaping.
+        write!(
+            json,
+            concat!(
+                "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":1,",
+                "\"tid\":{},\"ts\":{},\"dur\":{}}}"
+            ),
+            span.name,
+            span.tid,
+            span.start.as_micros(),
+            span.duration.as_micros(),
+        ).unwrap();
+    }
+    json.push_str("\n]}\n");
+
+    Some(json)
+}
+
+/// A line of the graph, in clip space.
+pub(super) type Line = ([f32; 2], [f32; 2], [f32; 4]);
+
+/// Text next to the graph: top left corner, color and the text itself.
+pub(super) type Label = ([f32; 2], [f32; 4], String);
+
+/// The graph of the last frames' CPU and GPU times, and labels for it.
+pub(super) fn graph() -> (Vec<Line>, Vec<Label>) {
+    RECORDER.with(|r| {
+        let r = r.borrow();
+
+        let [x0, y0] = GRAPH_MIN;
+        let [x1, y1] = GRAPH_MAX;
+        let x = |i: usize| x0 + (x1 - x0) * i as f32 / (HISTORY - 1) as f32;
+        // y points down, so the bottom of the graph is the larger value.
+        let y = |ms: f32| y1 - (y1 - y0) * (ms / GRAPH_MS).min(1.0);
+
+        let mut lines = vec![
+            ([x0, y0], [x0, y1], FRAME_COLOR),
+            ([x0, y1], [x1, y1], FRAME_COLOR),
+            ([x0, y(BUDGET_MS)], [x1, y(BUDGET_MS)], FRAME_COLOR),
+        ];
+        for (history, color) in [(&r.frames, CPU_COLOR), (&r.gpu, GPU_COLOR)] {
+            // Newest on the right.
+            let offset = HISTORY - history.len();
+            let points: Vec<_> = history
+                .iter()
+                .enumerate()
+                .map(|(i, &ms)| [x(offset + i), y(ms)])
+                .collect();
+            lines.extend(points.windows(2).map(|p| (p[0], p[1], color)));
+        }
+
+        let frame = r.frames.back().cloned().unwrap_or(0.0);
+        let mut labels = vec![
+            (
+                [x0, y0 - TEXT_SIZE * 2.0],
+                CPU_COLOR,
+                format!("FRAME {:.1} MS", frame),
+            ),
+            (
+                [x0 + (x1 - x0) / 2.0, y0 - TEXT_SIZE * 2.0],
+                GPU_COLOR,
+                match r.gpu.back() {
+                    Some(ms) => format!("GPU {:.2} MS", ms),
+                    None => "GPU -".to_string(),
+                },
+            ),
+        ];
+        // Scopes are stacked above the totals, last finished on top.
+        for (i, (name, ms)) in r.last_scopes.iter().enumerate() {
+            labels.push((
+                [x0, y0 - TEXT_SIZE * (4.0 + 2.0 * i as f32)],
+                FRAME_COLOR,
+                format!("{} {:.2} MS", name, ms),
+            ));
+        }
+        if r.trace.is_some() {
+            labels.push((
+                [x1, y0 - TEXT_SIZE * 2.0],
+                [1.0, 0.2, 0.2, 1.0],
+                "REC".to_string(),
+            ));
+        }
+
+        (lines, labels)
+    })
+}
+
+// Frames whose timestamps can be waiting to be read back.
+const RING: u32 = 4;
+
+/// Measures how long the GPU spends on each frame with timestamp queries.
+/// The timestamps are written by small command buffers submitted right
+/// before and after a frame's, and read back `RING` frames later, when they
+/// are certainly done.
+pub(super) struct GpuTimer {
+    queue: Arc<Queue>,
+    pool: Arc<UnsafeQueryPool>,
+    // Nanoseconds per tick, and the bits of a timestamp that are valid.
+    period: f64,
+    mask: u64,
+    slot: u32,
+    // When each slot's frame was submitted, if it has not been read yet.
+    pending: [Option<Instant>; RING as usize],
+}
+
+impl GpuTimer {
+    /// Returns `None` if the queue does not support timestamps.
+    pub fn new(queue: Arc<Queue>) -> Option<Self> {
+        let bits = queue.family().timestamp_valid_bits()?;
+        if bits == 0 {
+            return None;
+        }
+
+        let device = queue.device().clone();
+        let limits = device.physical_device().limits();
+        let period = limits.timestamp_period() as f64;
+
+        // A begin and end query per slot. `queries_range` refuses ranges that
+        // end at the last query, so there is one spare.
+        let pool =
+            UnsafeQueryPool::new(device, QueryType::Timestamp, RING * 2 + 1)
+                .ok()?;
+
+        Some(GpuTimer {
+            queue,
+            pool: Arc::new(pool),
+            period,
+            mask: if bits >= 64 { !0 } else { (1 << bits) - 1 },
+            slot: 0,
+            pending: [None; RING as usize],
+        })
+    }
+
+    /// Reads back the oldest frame's time, and returns command buffers to
+    /// run right before and after this frame's.
+    pub fn frame(&mut self) -> (Unsynced, Unsynced) {
+        self.slot = (self.slot + 1) % RING;
+        let first = self.slot * 2;
+
+        if let Some(submitted) = self.pending[self.slot as usize].take() {
+            if let Some(duration) = self.read(first) {
+                gpu_frame(submitted, duration);
+            }
+        }
+        self.pending[self.slot as usize] = Some(Instant::now());
+
+        let begin = self.timestamps(|builder, pool| unsafe {
+            builder.reset_query_pool(pool.queries_range(first, 2).unwrap());
+            builder.write_timestamp(
+                pool.query(first).unwrap(),
+                PipelineStages {
+                    top_of_pipe: true,
+                    ..PipelineStages::none()
+                },
+            );
+        });
+        let end = self.timestamps(|builder, pool| unsafe {
+            builder.write_timestamp(
+                pool.query(first + 1).unwrap(),
+                PipelineStages {
+                    bottom_of_pipe: true,
+                    ..PipelineStages::none()
+                },
+            );
+        });
+
+        (begin, end)
+    }
+
+    // The time between a slot's timestamps, if they are available.
+    fn read(&self, first: u32) -> Option<Duration> {
+        let device = self.queue.device();
+        let mut ticks = [0u64; 2];
+
+        // Without the wait flag this returns NOT_READY instead of blocking
+        // when the frame is somehow still running.
+        let result = unsafe {
+            device.pointers().GetQueryPoolResults(
+                device.internal_object(),
+                self.pool.internal_object(),
+                first,
+                2,
+                std::mem::size_of_val(&ticks),
+                ticks.as_mut_ptr() as *mut _,
+                std::mem::size_of::<u64>() as u64,
+                vk_sys::QUERY_RESULT_64_BIT,
+            )
+        };
+        if result != vk_sys::SUCCESS {
+            return None;
+        }
+
+        let (begin, end) = (ticks[0] & self.mask, ticks[1] & self.mask);
+        let elapsed = end.wrapping_sub(begin) & self.mask;
+        Some(Duration::from_nanos((elapsed as f64 * self.period) as u64))
+    }
+
+    // Timestamps only use the query pool, which is kept alive with them.
+    fn timestamps<F>(&self, record: F) -> Unsynced
+    where
+        F: FnOnce(
+            &mut UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>,
+            &UnsafeQueryPool,
+        ),
+    {
+        let pool = self.pool.clone();
+        unsafe {
+            Unsynced::record(&self.queue, pool, |builder| {
+                record(builder, &self.pool)
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Timings are kept per thread, and each test runs on a thread of its own.
+    fn labels() -> Vec<String> {
+        graph().1.into_iter().map(|(_, _, text)| text).collect()
+    }
+
+    #[test]
+    fn history_keeps_the_newest_frames() {
+        let mut history = VecDeque::new();
+        for i in 0..HISTORY + 5 {
+            push_history(&mut history, i as f32);
+        }
+        assert_eq!(history.len(), HISTORY);
+        assert_eq!(history.front(), Some(&5.0));
+        assert_eq!(history.back(), Some(&(HISTORY as f32 + 4.0)));
+    }
+
+    #[test]
+    fn scopes_are_shown_for_the_last_frame() {
+        frame();
+        {
+            let _scope = scope("work");
+        }
+        assert!(!labels().iter().any(|label| label.starts_with("work ")));
+
+        frame();
+        assert!(labels().iter().any(|label| label.starts_with("work ")));
+        assert!(labels().contains(&"GPU -".to_string()));
+
+        gpu_frame(Instant::now(), Duration::from_micros(2500));
+        assert!(labels().contains(&"GPU 2.50 MS".to_string()));
+
+        frame();
+        assert!(!labels().iter().any(|label| label.starts_with("work ")));
+    }
+
+    #[test]
+    fn traces_are_chrome_trace_events() {
+        assert!(finish_trace().is_none());
+
+        start_trace();
+        assert!(tracing());
+        frame();
+        {
+            let _scope = scope("work");
+        }
+        frame();
+
+        let json = finish_trace().unwrap();
+        assert!(!tracing());
+        let trace: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let names: Vec<&str> = events
+            .iter()
+            .map(|e| e["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"work"));
+        assert!(names.contains(&"frame"));
+        assert!(events.iter().all(|e| e["ph"] == "X" && e["tid"] == CPU_TID));
+    }
+}
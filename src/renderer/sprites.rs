@@ -0,0 +1,358 @@
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer as CpuBuf},
+    command_buffer::{
+        AutoCommandBuffer,
+        AutoCommandBufferBuilder,
+        DynamicState,
+    },
+    descriptor::{
+        descriptor_set::PersistentDescriptorSet,
+        DescriptorSet,
+        PipelineLayoutAbstract,
+    },
+    device::{Device, Queue},
+    format::Format,
+    framebuffer::{RenderPassAbstract, Subpass},
+    image::{
+        Dimensions,
+        ImageLayout,
+        ImageUsage,
+        ImmutableImage,
+        MipmapsCount,
+    },
+    pipeline::{vertex::OneVertexOneInstanceDefinition, GraphicsPipeline},
+    sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode},
+};
+
+use image::{imageops::FilterType, RgbaImage};
+
+use std::{path::Path, sync::Arc};
+
+use super::{InstanceData, View};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "assets/shaders/sprite_vertex.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "assets/shaders/sprite_fragment.glsl"
+    }
+}
+
+/// Handle to a texture loaded with `Renderer::load_texture`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureId(usize);
+
+/// Draws an instance as a textured quad instead of a mesh. `uv` is the
+/// rectangle of the texture to show, as (min u, min v, max u, max v).
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Sprite {
+    pub texture: TextureId,
+    pub uv: [f32; 4],
+}
+
+/// A texture holding one or more sprites.
+#[derive(Debug, Clone, Copy)]
+pub struct Atlas {
+    pub texture: TextureId,
+    pub size: [u32; 2],
+}
+
+impl Atlas {
+    /// The sprite at `rect` (x, y, width, height), in pixels.
+    pub fn sprite(&self, rect: [u32; 4]) -> Sprite {
+        let [w, h] = [self.size[0] as f32, self.size[1] as f32];
+
+        Sprite {
+            texture: self.texture,
+            uv: [
+                rect[0] as f32 / w,
+                rect[1] as f32 / h,
+                (rect[0] + rect[2]) as f32 / w,
+                (rect[1] + rect[3]) as f32 / h,
+            ],
+        }
+    }
+
+    /// The whole texture as a single sprite.
+    pub fn full(&self) -> Sprite {
+        self.sprite([0, 0, self.size[0], self.size[1]])
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+struct Corner {
+    corner: [f32; 2],
+}
+
+vulkano::impl_vertex!(Corner, corner);
+
+#[derive(Default, Debug, Clone)]
+struct SpriteVert {
+    pos_offset: [f32; 2],
+    angle: f32,
+    scale: f32,
+    uv_rect: [f32; 4],
+    depth: f32,
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(
+    SpriteVert,
+    pos_offset,
+    angle,
+    scale,
+    uv_rect,
+    depth,
+    color
+);
+
+type SpritePipeline = Arc<GraphicsPipeline<
+    OneVertexOneInstanceDefinition<Corner, SpriteVert>,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+    Arc<dyn RenderPassAbstract + Send + Sync>>
+>;
+
+fn mk_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> SpritePipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+
+    Arc::new(
+        GraphicsPipeline::start()
+        .vertex_input(
+            OneVertexOneInstanceDefinition::<Corner, SpriteVert>::new()
+        )
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(fs.main_entry_point(), ())
+        .blend_alpha_blending()
+        // Translucent, so sorted by layer rather than writing depth.
+        .depth_stencil(super::depth_stencil(&render_pass, false))
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device).unwrap(),
+    )
+}
+
+pub struct Sprites {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: SpritePipeline,
+    sampler: Arc<Sampler>,
+    quad: Arc<CpuBuf<[Corner]>>,
+    textures: Vec<Arc<dyn DescriptorSet + Send + Sync>>,
+}
+
+impl Sprites {
+    pub fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let pipeline = mk_pipeline(device.clone(), render_pass);
+
+        // Clamp so sprites at the border of an atlas do not bleed into the
+        // opposite side.
+        let sampler = Sampler::new(
+            device.clone(),
+            Filter::Linear,
+            Filter::Linear,
+            MipmapMode::Linear,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            1000.0,
+        ).unwrap();
+
+        let quad = CpuBuf::from_iter(
+            device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            [
+                [-1.0, -1.0], [1.0, -1.0], [1.0, 1.0],
+                [-1.0, -1.0], [1.0, 1.0], [-1.0, 1.0],
+            ].iter().map(|&corner| Corner { corner }),
+        ).unwrap();
+
+        Sprites {
+            device,
+            queue,
+            pipeline,
+            sampler,
+            quad,
+            textures: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the pipeline for a new render pass. Loaded textures stay
+    /// valid, their descriptor sets only depend on the pipeline's layout.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        self.pipeline = mk_pipeline(self.device.clone(), render_pass);
+    }
+
+    /// Decodes an image file and records its upload, mipmaps included. The
+    /// returned command buffer must be executed before the texture is drawn.
+    pub fn load(&mut self, path: &Path) ->
+        Result<(Atlas, AutoCommandBuffer), String>
+    {
+        let image = image::open(path)
+            .map_err(|e| e.to_string())?
+            .into_rgba8();
+        let size = [image.width(), image.height()];
+
+        let (texture, upload) = self.upload(&image)?;
+
+        let set = Arc::new(
+            PersistentDescriptorSet::start(
+                self.pipeline.descriptor_set_layout(0).unwrap().clone()
+            )
+            .add_sampled_image(texture, self.sampler.clone()).unwrap()
+            .build().unwrap()
+        );
+
+        self.textures.push(set);
+        let atlas = Atlas {
+            texture: TextureId(self.textures.len() - 1),
+            size,
+        };
+
+        Ok((atlas, upload))
+    }
+
+    // `ImmutableImage::from_iter` only fills the first mip level and vulkano
+    // cannot blit between levels of the same image, so the chain is
+    // downsampled on the CPU and every level is copied separately.
+    fn upload(&self, image: &RgbaImage) ->
+        Result<(Arc<ImmutableImage<Format>>, AutoCommandBuffer), String>
+    {
+        let dimensions = Dimensions::Dim2d {
+            width: image.width(),
+            height: image.height(),
+        };
+
+        let (texture, init) = ImmutableImage::uninitialized(
+            self.device.clone(),
+            dimensions,
+            Format::R8G8B8A8Srgb,
+            MipmapsCount::Log2,
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            ImageLayout::ShaderReadOnlyOptimal,
+            Some(self.queue.family()),
+        ).map_err(|e| e.to_string())?;
+        let init = Arc::new(init);
+
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(
+            self.device.clone(),
+            self.queue.family(),
+        ).map_err(|e| e.to_string())?;
+
+        for level in 0..texture.mipmap_levels() {
+            let width = (image.width() >> level).max(1);
+            let height = (image.height() >> level).max(1);
+
+            let pixels = if level == 0 {
+                image.clone()
+            } else {
+                image::imageops::resize(
+                    image,
+                    width,
+                    height,
+                    FilterType::Triangle,
+                )
+            };
+
+            let source = CpuBuf::from_iter(
+                self.device.clone(),
+                BufferUsage::transfer_source(),
+                false,
+                pixels.into_raw().into_iter(),
+            ).map_err(|e| e.to_string())?;
+
+            builder
+                .copy_buffer_to_image_dimensions(
+                    source,
+                    init.clone(),
+                    [0, 0, 0],
+                    [width, height, 1],
+                    0,
+                    1,
+                    level,
+                ).map_err(|e| e.to_string())?;
+        }
+
+        let upload = builder.build().map_err(|e| e.to_string())?;
+
+        Ok((texture, upload))
+    }
+
+    /// Records draws for every instance that has a sprite. Sprites are
+    /// translucent, so they are drawn back to front by layer, keeping the
+    /// given order within a layer. Consecutive sprites sharing a texture
+    /// are drawn together. Must be called inside the render pass.
+    pub fn draw(
+        &self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+        view: View,
+        data: &[Vec<InstanceData>],
+    ) {
+        let mut sprites = data
+            .iter()
+            .flatten()
+            .filter_map(|inst| {
+                let sprite = inst.sprite?;
+                let vert = SpriteVert {
+                    pos_offset: inst.pos_offset,
+                    angle: inst.angle,
+                    scale: inst.scale,
+                    uv_rect: sprite.uv,
+                    depth: super::layer_depth(inst.layer),
+                    color: inst.color,
+                };
+
+                Some((inst.layer, sprite.texture, vert))
+            })
+            .collect::<Vec<_>>();
+
+        // `sort_by_key` is stable.
+        sprites.sort_by_key(|&(layer, _, _)| layer);
+
+        for batch in sprites.chunk_by(|a, b| a.1 == b.1) {
+            let TextureId(index) = batch[0].1;
+
+            let instances = CpuBuf::from_iter(
+                self.device.clone(),
+                BufferUsage::vertex_buffer(),
+                false,
+                batch.iter().map(|(_, _, vert)| vert.clone()),
+            ).unwrap();
+
+            builder
+                .draw(
+                    self.pipeline.clone(),
+                    dynamic_state,
+                    (self.quad.clone(), instances),
+                    self.textures[index].clone(),
+                    view,
+                ).unwrap();
+        }
+    }
+}
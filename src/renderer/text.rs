@@ -0,0 +1,394 @@
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer as CpuBuf},
+    command_buffer::{AutoCommandBufferBuilder, DynamicState},
+    descriptor::PipelineLayoutAbstract,
+    device::Device,
+    framebuffer::{RenderPassAbstract, Subpass},
+    pipeline::{vertex::SingleBufferDefinition, GraphicsPipeline},
+};
+
+use lyon::math::Point;
+use lyon::path::Path;
+use lyon::svg::path_utils::build_path;
+use lyon::tessellation::*;
+
+use std::{collections::HashMap, sync::Arc};
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "assets/shaders/text_vertex.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "assets/shaders/text_fragment.glsl"
+    }
+}
+
+// Glyphs are strokes on a 4x6 grid with y pointing down, in the style of the
+// original arcade vector font. Lowercase letters are drawn as uppercase and
+// anything missing falls back to '?'.
+const GLYPHS: &[(char, &str)] = &[
+    ('A', "M 0 6 L 0 2 L 2 0 L 4 2 L 4 6 M 0 4 L 4 4"),
+    ('B', concat!(
+        "M 0 6 L 0 0 L 3 0 L 4 1 L 4 2 L 3 3 L 0 3 ",
+        "M 3 3 L 4 4 L 4 5 L 3 6 Z"
+    )),
+    ('C', "M 4 0 L 0 0 L 0 6 L 4 6"),
+    ('D', "M 0 0 L 2 0 L 4 2 L 4 4 L 2 6 L 0 6 Z"),
+    ('E', "M 4 0 L 0 0 L 0 6 L 4 6 M 0 3 L 3 3"),
+    ('F', "M 4 0 L 0 0 L 0 6 M 0 3 L 3 3"),
+    ('G', "M 4 1 L 4 0 L 0 0 L 0 6 L 4 6 L 4 3 L 2 3"),
+    ('H', "M 0 0 L 0 6 M 4 0 L 4 6 M 0 3 L 4 3"),
+    ('I', "M 0 0 L 4 0 M 2 0 L 2 6 M 0 6 L 4 6"),
+    ('J', "M 4 0 L 4 6 L 2 6 L 0 4"),
+    ('K', "M 0 0 L 0 6 M 4 0 L 0 3 L 4 6"),
+    ('L', "M 0 0 L 0 6 L 4 6"),
+    ('M', "M 0 6 L 0 0 L 2 2 L 4 0 L 4 6"),
+    ('N', "M 0 6 L 0 0 L 4 6 L 4 0"),
+    ('O', "M 0 0 L 4 0 L 4 6 L 0 6 Z"),
+    ('P', "M 0 6 L 0 0 L 4 0 L 4 3 L 0 3"),
+    ('Q', "M 0 0 L 4 0 L 4 4 L 2 6 L 0 6 Z M 2 4 L 4 6"),
+    ('R', "M 0 6 L 0 0 L 4 0 L 4 3 L 0 3 M 1 3 L 4 6"),
+    ('S', "M 4 0 L 0 0 L 0 3 L 4 3 L 4 6 L 0 6"),
+    ('T', "M 0 0 L 4 0 M 2 0 L 2 6"),
+    ('U', "M 0 0 L 0 6 L 4 6 L 4 0"),
+    ('V', "M 0 0 L 2 6 L 4 0"),
+    ('W', "M 0 0 L 0 6 L 2 4 L 4 6 L 4 0"),
+    ('X', "M 0 0 L 4 6 M 4 0 L 0 6"),
+    ('Y', "M 0 0 L 2 2 L 4 0 M 2 2 L 2 6"),
+    ('Z', "M 0 0 L 4 0 L 0 6 L 4 6"),
+    ('0', "M 0 0 L 4 0 L 4 6 L 0 6 Z M 4 0 L 0 6"),
+    ('1', "M 1 1 L 2 0 L 2 6"),
+    ('2', "M 0 0 L 4 0 L 4 3 L 0 3 L 0 6 L 4 6"),
+    ('3', "M 0 0 L 4 0 L 4 6 L 0 6 M 0 3 L 4 3"),
+    ('4', "M 0 0 L 0 3 L 4 3 M 4 0 L 4 6"),
+    ('5', "M 4 0 L 0 0 L 0 3 L 4 3 L 4 6 L 0 6"),
+    ('6', "M 0 0 L 0 6 L 4 6 L 4 3 L 0 3"),
+    ('7', "M 0 0 L 4 0 L 4 6"),
+    ('8', "M 0 0 L 4 0 L 4 6 L 0 6 Z M 0 3 L 4 3"),
+    ('9', "M 4 3 L 0 3 L 0 0 L 4 0 L 4 6"),
+    ('.', "M 2 5.5 L 2 6"),
+    (',', "M 2 5 L 1 7"),
+    (':', "M 2 1.5 L 2 2 M 2 4.5 L 2 5"),
+    ('-', "M 1 3 L 3 3"),
+    ('+', "M 1 3 L 3 3 M 2 2 L 2 4"),
+    ('=', "M 1 2 L 3 2 M 1 4 L 3 4"),
+    ('/', "M 0 6 L 4 0"),
+    ('%', "M 0 6 L 4 0 M 0 0 L 1 0 L 1 1 L 0 1 Z M 3 5 L 4 5 L 4 6 L 3 6 Z"),
+    ('(', "M 3 0 L 2 1 L 2 5 L 3 6"),
+    (')', "M 1 0 L 2 1 L 2 5 L 1 6"),
+    ('\'', "M 2 0 L 2 1.5"),
+    ('_', "M 0 6 L 4 6"),
+    ('!', "M 2 0 L 2 4 M 2 5.5 L 2 6"),
+    ('?', "M 0 0 L 4 0 L 4 3 L 2 3 L 2 4 M 2 5.5 L 2 6"),
+];
+
+const GLYPH_HEIGHT: f32 = 6.0;
+const ADVANCE: f32 = 6.0;
+const LINE_HEIGHT: f32 = 10.0;
+const STROKE_WIDTH: f32 = 0.6;
+
+/// Horizontal alignment of each line relative to the position passed to
+/// `Renderer::draw_text`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct TextVert {
+    origin: [f32; 2],
+    offset: [f32; 2],
+    color: [f32; 4],
+}
+
+vulkano::impl_vertex!(TextVert, origin, offset, color);
+
+/// The tessellated glyphs. Layout happens entirely on the CPU, so it can be
+/// checked without a GPU.
+pub struct Font {
+    glyphs: HashMap<char, Vec<[f32; 2]>>,
+}
+
+impl Font {
+    pub fn new() -> Self {
+        let glyphs = GLYPHS
+            .iter()
+            .map(|(c, svg)| (*c, stroke(svg)))
+            .collect();
+
+        Font { glyphs }
+    }
+
+    fn glyph(&self, c: char) -> Option<&Vec<[f32; 2]>> {
+        if c == ' ' {
+            return None;
+        }
+
+        self.glyphs
+            .get(&c.to_ascii_uppercase())
+            .or_else(|| self.glyphs.get(&'?'))
+    }
+
+    fn line_width(line: &str, scale: f32) -> f32 {
+        let chars = line.chars().count() as f32;
+        ((chars * ADVANCE - (ADVANCE - 4.0)) * scale).max(0.0)
+    }
+
+    /// Size of `text` drawn `size` high, before the aspect correction.
+    pub fn measure(&self, size: f32, text: &str) -> [f32; 2] {
+        let scale = size / GLYPH_HEIGHT;
+        let width = text
+            .lines()
+            .map(|line| Font::line_width(line, scale))
+            .fold(0.0, f32::max);
+        let lines = text.lines().count().max(1) as f32;
+
+        [width, ((lines - 1.0) * LINE_HEIGHT + GLYPH_HEIGHT) * scale]
+    }
+
+    /// Appends the triangles for `text` to `out`. `pos` is the top of the
+    /// first line, with `align` deciding which horizontal edge it is on.
+    pub fn layout(
+        &self,
+        pos: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        align: Align,
+        text: &str,
+        out: &mut Vec<TextVert>,
+    ) {
+        let scale = size / GLYPH_HEIGHT;
+
+        for (row, line) in text.lines().enumerate() {
+            let width = Font::line_width(line, scale);
+            let start = match align {
+                Align::Left => 0.0,
+                Align::Center => -width / 2.0,
+                Align::Right => -width,
+            };
+            let y = row as f32 * LINE_HEIGHT * scale;
+
+            for (col, c) in line.chars().enumerate() {
+                let x = start + col as f32 * ADVANCE * scale;
+
+                for vert in self.glyph(c).into_iter().flatten() {
+                    out.push(TextVert {
+                        origin: pos,
+                        offset: [x + vert[0] * scale, y + vert[1] * scale],
+                        color,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn stroke(svg: &str) -> Vec<[f32; 2]> {
+    let path = build_path(Path::builder().with_svg(), svg).unwrap();
+
+    let mut geometry: VertexBuffers<[f32; 2], u16> = VertexBuffers::new();
+    let mut tessellator = StrokeTessellator::new();
+
+    tessellator.tessellate_path(
+        &path,
+        &StrokeOptions::default()
+            .with_line_width(STROKE_WIDTH)
+            .with_line_cap(LineCap::Round)
+            .with_line_join(LineJoin::Round)
+            .with_tolerance(0.05),
+        &mut BuffersBuilder::new(
+            &mut geometry,
+            |pos: Point, _: StrokeAttributes| pos.to_array(),
+        ),
+    ).unwrap();
+
+    geometry.indices
+        .iter()
+        .map(|i| geometry.vertices[usize::from(*i)])
+        .collect()
+}
+
+type TextPipeline = Arc<GraphicsPipeline<
+    SingleBufferDefinition<TextVert>,
+    Box<dyn PipelineLayoutAbstract + Send + Sync>,
+    Arc<dyn RenderPassAbstract + Send + Sync>>
+>;
+
+fn mk_pipeline(
+    device: Arc<Device>,
+    render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+) -> TextPipeline {
+    let vs = vs::Shader::load(device.clone()).unwrap();
+    let fs = fs::Shader::load(device.clone()).unwrap();
+
+    Arc::new(
+        GraphicsPipeline::start()
+        .vertex_input_single_buffer()
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_list()
+        .viewports_dynamic_scissors_irrelevant(1)
+        .fragment_shader(fs.main_entry_point(), ())
+        .blend_alpha_blending()
+        .render_pass(Subpass::from(render_pass, 0).unwrap())
+        .build(device).unwrap(),
+    )
+}
+
+/// Collects the text drawn during a frame and draws all of it at once.
+pub struct Text {
+    device: Arc<Device>,
+    pipeline: TextPipeline,
+    pub font: Font,
+    queued: Vec<TextVert>,
+}
+
+impl Text {
+    pub fn new(
+        device: Arc<Device>,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) -> Self {
+        let pipeline = mk_pipeline(device.clone(), render_pass);
+
+        Text {
+            device,
+            pipeline,
+            font: Font::new(),
+            queued: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the pipeline for a new render pass.
+    pub fn set_render_pass(
+        &mut self,
+        render_pass: Arc<dyn RenderPassAbstract + Send + Sync>,
+    ) {
+        self.pipeline = mk_pipeline(self.device.clone(), render_pass);
+    }
+
+    pub fn queue(
+        &mut self,
+        pos: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        align: Align,
+        text: &str,
+    ) {
+        self.font.layout(pos, size, color, align, text, &mut self.queued);
+    }
+
+    /// Forgets everything queued, for a frame that isn't drawn.
+    pub fn clear(&mut self) {
+        self.queued.clear();
+    }
+
+    /// Draws and clears everything queued since the last frame. Must be
+    /// called inside the render pass, after the rest of the scene.
+    pub fn draw(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder,
+        dynamic_state: &DynamicState,
+    ) {
+        if self.queued.is_empty() {
+            return;
+        }
+
+        let verts = CpuBuf::from_iter(
+            self.device.clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            self.queued.drain(..),
+        ).unwrap();
+
+        builder
+            .draw(
+                self.pipeline.clone(),
+                dynamic_state,
+                verts,
+                (),
+                (),
+            ).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::Renderer;
+
+    const WHITE: [f32; 4] = [1.0; 4];
+
+    fn layout(align: Align, text: &str) -> Vec<[f32; 2]> {
+        let mut out = Vec::new();
+        let font = Font::new();
+        font.layout([0.5, 0.5], GLYPH_HEIGHT, WHITE, align, text, &mut out);
+        assert!(out.iter().all(|vert| vert.origin == [0.5, 0.5]));
+        out.iter().map(|vert| vert.offset).collect()
+    }
+
+    // Whether `moved` is `verts` moved by `by`.
+    fn shifted(moved: &[[f32; 2]], verts: &[[f32; 2]], by: [f32; 2]) -> bool {
+        moved.len() == verts.len()
+            && moved.iter().zip(verts.iter()).all(|(m, v)| {
+                (m[0] - v[0] - by[0]).abs() < 1e-4
+                    && (m[1] - v[1] - by[1]).abs() < 1e-4
+            })
+    }
+
+    #[test]
+    fn text_is_measured_by_its_longest_line() {
+        let font = Font::new();
+        assert_eq!(font.measure(GLYPH_HEIGHT, "AB"), [10.0, 6.0]);
+        assert_eq!(font.measure(GLYPH_HEIGHT * 2.0, "AB"), [20.0, 12.0]);
+        assert_eq!(font.measure(GLYPH_HEIGHT, "A\nABC"), [16.0, 16.0]);
+        assert_eq!(font.measure(GLYPH_HEIGHT, ""), [0.0, 6.0]);
+    }
+
+    #[test]
+    fn lines_are_aligned_to_the_position() {
+        let left = layout(Align::Left, "AB");
+        assert!(shifted(&layout(Align::Center, "AB"), &left, [-5.0, 0.0]));
+        assert!(shifted(&layout(Align::Right, "AB"), &left, [-10.0, 0.0]));
+
+        // Each line is aligned by its own width.
+        let lines = layout(Align::Right, "AB\nA");
+        let a = layout(Align::Left, "A");
+        assert!(shifted(&lines[left.len()..], &a, [-4.0, LINE_HEIGHT]));
+    }
+
+    #[test]
+    fn missing_glyphs_fall_back() {
+        assert_eq!(layout(Align::Left, "abc"), layout(Align::Left, "ABC"));
+        assert_eq!(layout(Align::Left, "\u{2603}"), layout(Align::Left, "?"));
+
+        // Spaces draw nothing but still take up room.
+        let a = layout(Align::Left, "A");
+        let spaced = layout(Align::Left, "A  A");
+        assert_eq!(spaced.len(), a.len() * 2);
+        assert!(shifted(&spaced[a.len()..], &a, [3.0 * ADVANCE, 0.0]));
+    }
+
+    // Needs a Vulkan device, so it only runs with `cargo test -- --ignored`.
+    #[test]
+    #[ignore]
+    fn text_shows_up_in_headless_snapshots() {
+        let mut renderer = Renderer::headless([64, 64]);
+        renderer.settings.clear_color = [0.0; 3];
+        renderer.redraw(Vec::new());
+        let blank = renderer.snapshot().unwrap();
+        assert_eq!(blank.dimensions(), (64, 64));
+        assert!(blank.pixels().all(|pixel| pixel[0] == 0));
+
+        let pos = [0.0, -0.5];
+        renderer.draw_text_aligned(pos, 0.5, WHITE, Align::Center, "HI");
+        renderer.redraw(Vec::new());
+        let text = renderer.snapshot().unwrap();
+        assert!(text.pixels().any(|pixel| pixel[0] > 128));
+    }
+}
@@ -0,0 +1,155 @@
+use vulkano::{
+    buffer::BufferAccess,
+    command_buffer::{
+        pool::standard::{StandardCommandPoolAlloc, StandardCommandPoolBuilder},
+        sys::{
+            Flags,
+            Kind,
+            UnsafeCommandBuffer,
+            UnsafeCommandBufferBuilder,
+            UnsafeCommandBufferBuilderPipelineBarrier,
+        },
+        CommandBuffer,
+        CommandBufferExecError,
+    },
+    device::{Device, DeviceOwned, Queue},
+    image::{ImageAccess, ImageLayout},
+    sync::{AccessCheckError, AccessFlagBits, GpuFuture, PipelineStages},
+};
+
+use std::sync::Arc;
+
+/// A command buffer recorded with vulkano's unsafe builder, for commands the
+/// safe one doesn't have. It uses no buffers or images, so it can be
+/// submitted without any synchronization.
+pub(super) struct Unsynced {
+    inner: UnsafeCommandBuffer<StandardCommandPoolAlloc>,
+    // Whatever the commands refer to, kept alive until they have run.
+    owner: Arc<dyn DeviceOwned + Send + Sync>,
+}
+
+impl Unsynced {
+    /// Records a primary command buffer for `queue`'s family.
+    ///
+    /// # Safety
+    ///
+    /// The commands must not use buffers or images, and anything else they
+    /// use must be kept alive by `owner`.
+    pub unsafe fn record<F>(
+        queue: &Queue,
+        owner: Arc<dyn DeviceOwned + Send + Sync>,
+        record: F,
+    ) -> Self
+    where
+        F: FnOnce(&mut UnsafeCommandBufferBuilder<StandardCommandPoolBuilder>),
+    {
+        let command_pool =
+            Device::standard_command_pool(queue.device(), queue.family());
+        let mut builder = UnsafeCommandBufferBuilder::new(
+            &command_pool,
+            Kind::primary(),
+            Flags::OneTimeSubmit,
+        ).unwrap();
+        record(&mut builder);
+
+        Unsynced { inner: builder.build().unwrap(), owner }
+    }
+
+    /// A barrier between the stages a frame uses buffers and images in, for
+    /// everything submitted to `queue` before it and everything after:
+    /// earlier shader and transfer writes finish and are visible before
+    /// later commands read or write. It stands in for the barriers vulkano
+    /// leaves out around secondary command buffers.
+    pub fn barrier(queue: &Arc<Queue>) -> Self {
+        // Instances are read as vertices, the fractal and textures in
+        // fragment shaders, and particles are written by a compute shader
+        // and by copies.
+        let stages = PipelineStages {
+            vertex_input: true,
+            vertex_shader: true,
+            fragment_shader: true,
+            compute_shader: true,
+            transfer: true,
+            ..PipelineStages::none()
+        };
+        let writes = AccessFlagBits {
+            shader_write: true,
+            transfer_write: true,
+            ..AccessFlagBits::none()
+        };
+        let access = AccessFlagBits {
+            vertex_attribute_read: true,
+            uniform_read: true,
+            shader_read: true,
+            transfer_read: true,
+            ..writes
+        };
+
+        // SAFETY: a memory barrier refers to no buffer or image, and the
+        // queue keeps the device alive.
+        unsafe {
+            Unsynced::record(queue, queue.clone(), |builder| {
+                let mut barrier =
+                    UnsafeCommandBufferBuilderPipelineBarrier::new();
+                barrier.add_memory_barrier(
+                    stages,
+                    writes,
+                    stages,
+                    access,
+                    false,
+                );
+                builder.pipeline_barrier(&barrier);
+            })
+        }
+    }
+}
+
+// SAFETY: the device is the one the commands were recorded for.
+unsafe impl DeviceOwned for Unsynced {
+    fn device(&self) -> &Arc<Device> {
+        self.owner.device()
+    }
+}
+
+// SAFETY: `record` only takes commands that use no buffers or images, which
+// are all vulkano locks and tracks. What they do use, a query pool, is only
+// touched by the one command buffer written for each query slot. So there
+// is nothing to lock on submit, and nothing for a check to report: any
+// resource asked about is unknown to this command buffer, and vulkano looks
+// for it in the rest of the submission instead.
+unsafe impl CommandBuffer for Unsynced {
+    type PoolAlloc = StandardCommandPoolAlloc;
+
+    fn inner(&self) -> &UnsafeCommandBuffer<StandardCommandPoolAlloc> {
+        &self.inner
+    }
+
+    fn lock_submit(
+        &self,
+        _future: &dyn GpuFuture,
+        _queue: &Queue,
+    ) -> Result<(), CommandBufferExecError> {
+        Ok(())
+    }
+
+    unsafe fn unlock(&self) {}
+
+    fn check_buffer_access(
+        &self,
+        _buffer: &dyn BufferAccess,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+
+    fn check_image_access(
+        &self,
+        _image: &dyn ImageAccess,
+        _layout: ImageLayout,
+        _exclusive: bool,
+        _queue: &Queue,
+    ) -> Result<Option<(PipelineStages, AccessFlagBits)>, AccessCheckError> {
+        Err(AccessCheckError::Unknown)
+    }
+}
@@ -0,0 +1,56 @@
+use std::f32::consts::SQRT_2;
+
+use super::InstanceData;
+
+// Must match `stretch` in the vertex shaders. Offsets from an instance's
+// position are stretched vertically by this much.
+const STRETCH_Y: f32 = 1920.0 / 1080.0;
+
+/// Adds a copy of every instance that crosses an edge of the world on the
+/// opposite side, and one in the opposite corner when it crosses two edges,
+/// so entities slide smoothly off one side and onto the other. `radii` are
+/// the bounding radii of each group's mesh and the world spans
+/// `-world..world` on both axes.
+pub fn add_ghosts(data: &mut [Vec<InstanceData>], radii: &[f32], world: f32) {
+    for (group, radius) in data.iter_mut().zip(radii.iter()) {
+        let mut ghosts = Vec::new();
+
+        for inst in group.iter() {
+            // Sprites are drawn on a quad instead of the mesh.
+            let radius = match inst.sprite {
+                Some(_) => SQRT_2,
+                None => *radius,
+            } * inst.scale;
+
+            let [x, y] = inst.pos_offset;
+            let dx = shift(x, radius, world);
+            let dy = shift(y, radius * STRETCH_Y, world);
+
+            let offsets = [(dx, 0.0), (0.0, dy), (dx, dy)];
+            for &(dx, dy) in offsets.iter() {
+                if dx == 0.0 && dy == 0.0 {
+                    continue;
+                }
+
+                ghosts.push(InstanceData {
+                    pos_offset: [x + dx, y + dy],
+                    ..inst.clone()
+                });
+            }
+        }
+
+        group.extend(ghosts);
+    }
+}
+
+// How far to move a ghost of something at `pos` with extent `radius` along
+// one axis, or 0 if it does not cross an edge.
+fn shift(pos: f32, radius: f32, world: f32) -> f32 {
+    if pos + radius > world {
+        -2.0 * world
+    } else if pos - radius < -world {
+        2.0 * world
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,757 @@
+// The screens the game moves between, as a stack of scenes. The top scene
+// takes menu input and updates the game if it should, and it is drawn over
+// the scenes below it that show through.
+
+use crate::{
+    game::{self, Mode, Setup, State, PLAYER_COLORS},
+    renderer::{Align, Renderer},
+    scores::{Entry, HighScores, INITIALS},
+};
+
+use std::path::PathBuf;
+
+/// Moving around menus, from whatever keys are bound to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+}
+
+/// Where scenes draw, so they can be drawn without a window.
+pub trait Canvas {
+    /// Draws `text` with `pos` on the edge of each line given by `align`,
+    /// as `Renderer::draw_text_aligned` does.
+    fn text(
+        &mut self,
+        pos: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        align: Align,
+        text: &str,
+    );
+}
+
+impl Canvas for Renderer {
+    fn text(
+        &mut self,
+        pos: [f32; 2],
+        size: f32,
+        color: [f32; 4],
+        align: Align,
+        text: &str,
+    ) {
+        self.draw_text_aligned(pos, size, color, align, text);
+    }
+}
+
+const TEXT_SIZE: f32 = 0.04;
+const LINE: f32 = 0.07;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.8];
+// Menu items that aren't selected.
+const DIM_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.35];
+const TITLE_SIZE: f32 = 0.12;
+
+// Space between letters of initials being entered.
+const LETTER_SPACING: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TitleItem {
+    Start,
+    HighScores,
+    Settings,
+    Quit,
+}
+
+const TITLE_MENU: [TitleItem; 4] = [
+    TitleItem::Start,
+    TitleItem::HighScores,
+    TitleItem::Settings,
+    TitleItem::Quit,
+];
+
+impl TitleItem {
+    fn label(self) -> &'static str {
+        match self {
+            TitleItem::Start => "START",
+            TitleItem::HighScores => "HIGH SCORES",
+            TitleItem::Settings => "SETTINGS",
+            TitleItem::Quit => "QUIT",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PauseItem {
+    Resume,
+    Settings,
+    QuitToTitle,
+}
+
+const PAUSE_MENU: [PauseItem; 3] =
+    [PauseItem::Resume, PauseItem::Settings, PauseItem::QuitToTitle];
+
+impl PauseItem {
+    fn label(self) -> &'static str {
+        match self {
+            PauseItem::Resume => "RESUME",
+            PauseItem::Settings => "SETTINGS",
+            PauseItem::QuitToTitle => "QUIT TO TITLE",
+        }
+    }
+}
+
+// Settings are rows of a name and a value, and a way back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingsItem {
+    Players,
+    Mode,
+    Back,
+}
+
+const SETTINGS_MENU: [SettingsItem; 3] =
+    [SettingsItem::Players, SettingsItem::Mode, SettingsItem::Back];
+
+impl SettingsItem {
+    fn label(self, setup: &Setup) -> String {
+        match self {
+            SettingsItem::Players => format!("PLAYERS {}", setup.players),
+            SettingsItem::Mode => match setup.mode {
+                Mode::Coop => "MODE CO-OP".to_string(),
+                Mode::Versus => "MODE VERSUS".to_string(),
+            },
+            SettingsItem::Back => "BACK".to_string(),
+        }
+    }
+}
+
+/// A screen the game can be on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Scene {
+    /// The title screen and its menu, over a game that hasn't started.
+    Title { selected: usize },
+    /// The game running, with the scores.
+    Playing,
+    /// The game on hold under a menu.
+    Paused { selected: usize },
+    /// The end of a game, which carries on behind it.
+    GameOver,
+    /// How the next game is played.
+    Settings { selected: usize },
+    /// A player with a high score entering their initials, a letter at a
+    /// time. Any other players with high scores follow, as the player and
+    /// their score.
+    Initials {
+        queue: Vec<(usize, u32)>,
+        letters: [u8; INITIALS],
+        cursor: usize,
+    },
+    /// The high score table, with the newest entry highlighted.
+    HighScores { highlight: Option<usize> },
+}
+
+// What a scene does to the stack.
+enum Transition {
+    Stay,
+    Push(Scene),
+    Pop,
+    // Replaces the whole stack.
+    Reset(Scene),
+    Quit,
+}
+
+// Initials entry for the first of `queue`, or the table once no one is
+// left to enter theirs.
+fn initials(queue: Vec<(usize, u32)>, highlight: Option<usize>) -> Transition {
+    if queue.is_empty() {
+        Transition::Reset(Scene::HighScores { highlight })
+    } else {
+        let letters = [b'A'; INITIALS];
+        Transition::Reset(Scene::Initials { queue, letters, cursor: 0 })
+    }
+}
+
+// Draws `text` centered on `pos`.
+fn centered(
+    canvas: &mut impl Canvas,
+    pos: [f32; 2],
+    size: f32,
+    color: [f32; 4],
+    text: &str,
+) {
+    canvas.text(pos, size, color, Align::Center, text);
+}
+
+// Moves a menu selection up or down, wrapping around.
+fn step(selected: &mut usize, len: usize, nav: Nav) {
+    match nav {
+        Nav::Up => *selected = (*selected + len - 1) % len,
+        Nav::Down => *selected = (*selected + 1) % len,
+        _ => (),
+    }
+}
+
+fn menu<S: AsRef<str>>(
+    canvas: &mut impl Canvas,
+    top: f32,
+    items: impl Iterator<Item = S>,
+    selected: usize,
+) {
+    for (i, item) in items.enumerate() {
+        let color = if i == selected { TEXT_COLOR } else { DIM_COLOR };
+        let pos = [0.0, top + i as f32 * LINE];
+        centered(canvas, pos, TEXT_SIZE, color, item.as_ref());
+    }
+}
+
+impl Scene {
+    fn input(
+        &mut self,
+        nav: Nav,
+        st: &mut State,
+        setup: &mut Setup,
+        scores: &mut HighScores,
+    ) -> Transition {
+        match self {
+            Scene::Title { selected } => match nav {
+                Nav::Select => match TITLE_MENU[*selected] {
+                    TitleItem::Start => {
+                        game::start(st, setup.clone());
+                        Transition::Reset(Scene::Playing)
+                    }
+                    TitleItem::HighScores => {
+                        Transition::Push(Scene::HighScores { highlight: None })
+                    }
+                    TitleItem::Settings => {
+                        Transition::Push(Scene::Settings { selected: 0 })
+                    }
+                    TitleItem::Quit => Transition::Quit,
+                },
+                Nav::Back => Transition::Quit,
+                _ => {
+                    step(selected, TITLE_MENU.len(), nav);
+                    Transition::Stay
+                }
+            },
+            Scene::Playing => match nav {
+                Nav::Back => Transition::Push(Scene::Paused { selected: 0 }),
+                _ => Transition::Stay,
+            },
+            Scene::Paused { selected } => match nav {
+                Nav::Select => match PAUSE_MENU[*selected] {
+                    PauseItem::Resume => Transition::Pop,
+                    PauseItem::Settings => {
+                        Transition::Push(Scene::Settings { selected: 0 })
+                    }
+                    PauseItem::QuitToTitle => {
+                        game::start(st, setup.clone());
+                        Transition::Reset(Scene::Title { selected: 0 })
+                    }
+                },
+                Nav::Back => Transition::Pop,
+                _ => {
+                    step(selected, PAUSE_MENU.len(), nav);
+                    Transition::Stay
+                }
+            },
+            Scene::GameOver => match nav {
+                Nav::Select | Nav::Back => {
+                    let queue = st.players
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, player)| scores.qualifies(player.score))
+                        .map(|(i, player)| (i, player.score))
+                        .collect();
+                    game::start(st, setup.clone());
+                    initials(queue, None)
+                }
+                _ => Transition::Stay,
+            },
+            Scene::Settings { selected } => {
+                match (nav, SETTINGS_MENU[*selected]) {
+                    (Nav::Back, _) | (Nav::Select, SettingsItem::Back) => {
+                        Transition::Pop
+                    }
+                    (Nav::Left, SettingsItem::Players) => {
+                        setup.players = (setup.players - 1).max(1);
+                        Transition::Stay
+                    }
+                    (Nav::Right, SettingsItem::Players) => {
+                        let most = PLAYER_COLORS.len();
+                        setup.players = (setup.players + 1).min(most);
+                        Transition::Stay
+                    }
+                    (Nav::Left, SettingsItem::Mode)
+                    | (Nav::Right, SettingsItem::Mode)
+                    | (Nav::Select, SettingsItem::Mode) => {
+                        setup.mode = match setup.mode {
+                            Mode::Coop => Mode::Versus,
+                            Mode::Versus => Mode::Coop,
+                        };
+                        Transition::Stay
+                    }
+                    _ => {
+                        step(selected, SETTINGS_MENU.len(), nav);
+                        Transition::Stay
+                    }
+                }
+            }
+            Scene::Initials { queue, letters, cursor } => match nav {
+                Nav::Up | Nav::Down => {
+                    let letter = &mut letters[*cursor];
+                    let offset = if nav == Nav::Up { 1 } else { 25 };
+                    *letter = b'A' + (*letter - b'A' + offset) % 26;
+                    Transition::Stay
+                }
+                Nav::Left => {
+                    *cursor = cursor.saturating_sub(1);
+                    Transition::Stay
+                }
+                Nav::Right => {
+                    *cursor = (*cursor + 1).min(INITIALS - 1);
+                    Transition::Stay
+                }
+                Nav::Select if *cursor < INITIALS - 1 => {
+                    *cursor += 1;
+                    Transition::Stay
+                }
+                // Entered, or skipped by backing out.
+                Nav::Select | Nav::Back => {
+                    let (_, score) = queue.remove(0);
+                    let rank = if nav == Nav::Select {
+                        let initials =
+                            String::from_utf8_lossy(letters).into_owned();
+                        scores.insert(Entry { initials, score })
+                    } else {
+                        None
+                    };
+                    initials(std::mem::take(queue), rank)
+                }
+            },
+            Scene::HighScores { .. } => match nav {
+                Nav::Select | Nav::Back => {
+                    Transition::Reset(Scene::Title { selected: 0 })
+                }
+                _ => Transition::Stay,
+            },
+        }
+    }
+
+    // Only a game being played moves. Menus over it hold it still.
+    fn update(&mut self, st: &mut State) -> Transition {
+        match self {
+            Scene::Playing => {
+                game::update(st);
+                if game::game_over(st).is_some() {
+                    Transition::Push(Scene::GameOver)
+                } else {
+                    Transition::Stay
+                }
+            }
+            Scene::GameOver => {
+                game::update(st);
+                Transition::Stay
+            }
+            _ => Transition::Stay,
+        }
+    }
+
+    // Whether the scene below is drawn too.
+    fn overlay(&self) -> bool {
+        matches!(self, Scene::Paused { .. } | Scene::GameOver)
+    }
+
+    fn render(
+        &self,
+        st: &State,
+        setup: &Setup,
+        scores: &HighScores,
+        canvas: &mut impl Canvas,
+    ) {
+        let heading = TEXT_SIZE * 2.0;
+        match self {
+            Scene::Title { selected } => {
+                let pos = [0.0, -0.5];
+                centered(canvas, pos, TITLE_SIZE, TEXT_COLOR, "ASTEROIDS");
+                let items = TITLE_MENU.iter().map(|item| item.label());
+                menu(canvas, 0.1, items, *selected);
+            }
+            Scene::Playing => {
+                for (i, player) in st.players.iter().enumerate() {
+                    let [r, g, b, _] = player.color;
+                    canvas.text(
+                        [-0.95, -0.95 + i as f32 * LINE],
+                        TEXT_SIZE,
+                        [r, g, b, TEXT_COLOR[3]],
+                        Align::Left,
+                        &format!(
+                            "P{} {:>6} LIVES {}",
+                            i + 1,
+                            player.score,
+                            player.lives,
+                        ),
+                    );
+                }
+                if let Some(message) = game::game_over(st) {
+                    let pos = [0.0, -TEXT_SIZE];
+                    centered(canvas, pos, heading, TEXT_COLOR, &message);
+                }
+            }
+            Scene::Paused { selected } => {
+                let pos = [0.0, -0.3];
+                centered(canvas, pos, heading, TEXT_COLOR, "PAUSED");
+                let items = PAUSE_MENU.iter().map(|item| item.label());
+                menu(canvas, 0.0, items, *selected);
+            }
+            Scene::GameOver => {
+                let pos = [0.0, TEXT_SIZE * 2.0];
+                centered(canvas, pos, TEXT_SIZE, DIM_COLOR, "PRESS ENTER");
+            }
+            Scene::Settings { selected } => {
+                let pos = [0.0, -0.3];
+                centered(canvas, pos, heading, TEXT_COLOR, "SETTINGS");
+                let items = SETTINGS_MENU.iter().map(|item| item.label(setup));
+                menu(canvas, 0.0, items, *selected);
+            }
+            Scene::Initials { queue, letters, cursor } => {
+                let (player, score) = queue[0];
+                let [r, g, b, _] = PLAYER_COLORS[player];
+                let color = [r, g, b, TEXT_COLOR[3]];
+                let title = "NEW HIGH SCORE";
+                centered(canvas, [0.0, -0.4], heading, TEXT_COLOR, title);
+                let line = format!("P{} {}", player + 1, score);
+                centered(canvas, [0.0, -0.2], TEXT_SIZE, color, &line);
+
+                let middle = (INITIALS - 1) as f32 / 2.0;
+                for (i, &letter) in letters.iter().enumerate() {
+                    let x = (i as f32 - middle) * LETTER_SPACING;
+                    let color =
+                        if i == *cursor { TEXT_COLOR } else { DIM_COLOR };
+                    let letter = (letter as char).to_string();
+                    centered(canvas, [x, 0.0], heading, color, &letter);
+                }
+                let pos = [0.0, 0.3];
+                let hint = "UP/DOWN AND ENTER";
+                centered(canvas, pos, TEXT_SIZE, DIM_COLOR, hint);
+            }
+            Scene::HighScores { highlight } => {
+                let pos = [0.0, -0.6];
+                centered(canvas, pos, heading, TEXT_COLOR, "HIGH SCORES");
+                for (i, entry) in scores.entries().iter().enumerate() {
+                    let color = if Some(i) == *highlight {
+                        TEXT_COLOR
+                    } else {
+                        DIM_COLOR
+                    };
+                    let line = format!(
+                        "{:>2}. {} {:>6}",
+                        i + 1,
+                        entry.initials,
+                        entry.score,
+                    );
+                    let pos = [0.0, -0.4 + i as f32 * LINE];
+                    centered(canvas, pos, TEXT_SIZE, color, &line);
+                }
+            }
+        }
+    }
+}
+
+/// The stack of scenes, the setup the next game starts with and the high
+/// scores.
+#[derive(Debug, Clone)]
+pub struct Scenes {
+    stack: Vec<Scene>,
+    pub setup: Setup,
+    pub scores: HighScores,
+    // Where the high scores are saved whenever they change, if anywhere.
+    scores_path: Option<PathBuf>,
+}
+
+impl Scenes {
+    /// Starts on the title screen, with the default high scores.
+    pub fn new(setup: Setup) -> Self {
+        Scenes {
+            stack: vec![Scene::Title { selected: 0 }],
+            setup,
+            scores: HighScores::defaults(),
+            scores_path: None,
+        }
+    }
+
+    /// Starts in a game that is already running.
+    pub fn playing(setup: Setup) -> Self {
+        Scenes { stack: vec![Scene::Playing], ..Scenes::new(setup) }
+    }
+
+    /// Uses the high scores at `path`, and saves them there.
+    pub fn with_scores(self, path: PathBuf) -> Self {
+        let scores = HighScores::load_or_default(&path);
+        Scenes { scores, scores_path: Some(path), ..self }
+    }
+
+    pub fn top(&self) -> Option<&Scene> {
+        self.stack.last()
+    }
+
+    /// Whether the player quit, leaving no scenes.
+    pub fn done(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Whether input goes to menus rather than to the ships.
+    pub fn in_menu(&self) -> bool {
+        self.top().is_some_and(|top| *top != Scene::Playing)
+    }
+
+    /// Starts a game with `setup` and plays it, whatever was showing.
+    pub fn new_game(&mut self, st: &mut State, setup: Setup) {
+        game::start(st, setup.clone());
+        self.setup = setup;
+        self.stack = vec![Scene::Playing];
+    }
+
+    pub fn input(&mut self, nav: Nav, st: &mut State) {
+        let top = match self.stack.last_mut() {
+            Some(top) => top,
+            None => return,
+        };
+        let before = self.scores.clone();
+        let transition = top.input(nav, st, &mut self.setup, &mut self.scores);
+        self.apply(transition);
+
+        if let (Some(path), true) = (&self.scores_path, self.scores != before) {
+            if let Err(e) = self.scores.save(path) {
+                println!(
+                    "Failed to save high scores to {:?}: {}",
+                    path, e,
+                );
+            }
+        }
+    }
+
+    /// Runs a tick of whatever is showing.
+    pub fn update(&mut self, st: &mut State) {
+        if let Some(top) = self.stack.last_mut() {
+            let transition = top.update(st);
+            self.apply(transition);
+        }
+    }
+
+    /// Draws the top scene over those below it that show through.
+    pub fn render(&self, st: &State, canvas: &mut impl Canvas) {
+        let shown = self.stack
+            .iter()
+            .rposition(|scene| !scene.overlay())
+            .unwrap_or(0);
+        for scene in self.stack[shown..].iter() {
+            scene.render(st, &self.setup, &self.scores, canvas);
+        }
+    }
+
+    fn apply(&mut self, transition: Transition) {
+        match transition {
+            Transition::Stay => (),
+            Transition::Push(scene) => self.stack.push(scene),
+            Transition::Pop => {
+                self.stack.pop();
+            }
+            Transition::Reset(scene) => self.stack = vec![scene],
+            Transition::Quit => self.stack.clear(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::net::Game;
+
+    // Keeps what was drawn as text.
+    struct Texts(Vec<String>);
+
+    impl Canvas for Texts {
+        fn text(
+            &mut self,
+            _: [f32; 2],
+            _: f32,
+            _: [f32; 4],
+            _: Align,
+            text: &str,
+        ) {
+            self.0.push(text.to_string());
+        }
+    }
+
+    fn drawn(scenes: &Scenes, st: &State) -> Vec<String> {
+        let mut texts = Texts(Vec::new());
+        scenes.render(st, &mut texts);
+        texts.0
+    }
+
+    fn new_game() -> (Scenes, State) {
+        let setup = Setup { players: 1, mode: Mode::Coop };
+        (Scenes::new(setup.clone()), game::new_state(None, setup))
+    }
+
+    #[test]
+    fn the_title_starts_a_game() {
+        let (mut scenes, mut st) = new_game();
+        assert!(drawn(&scenes, &st).contains(&"ASTEROIDS".to_string()));
+
+        let before = st.checksum();
+        scenes.update(&mut st);
+        assert_eq!(st.checksum(), before);
+
+        let navs = [Nav::Down, Nav::Up, Nav::Up, Nav::Down, Nav::Select];
+        for nav in navs.iter() {
+            scenes.input(*nav, &mut st);
+        }
+        assert_eq!(scenes.top(), Some(&Scene::Playing));
+        assert!(!scenes.in_menu());
+        scenes.update(&mut st);
+        assert_ne!(st.checksum(), before);
+    }
+
+    #[test]
+    fn pausing_holds_the_game_but_draws_it() {
+        let (mut scenes, mut st) = new_game();
+        scenes.new_game(&mut st, Setup { players: 2, mode: Mode::Coop });
+        scenes.update(&mut st);
+
+        scenes.input(Nav::Back, &mut st);
+        assert_eq!(scenes.top(), Some(&Scene::Paused { selected: 0 }));
+        let before = st.checksum();
+        for _ in 0..10 {
+            scenes.update(&mut st);
+        }
+        assert_eq!(st.checksum(), before);
+
+        let texts = drawn(&scenes, &st);
+        assert!(texts.contains(&"PAUSED".to_string()));
+        assert!(texts.iter().any(|text| text.starts_with("P2")));
+
+        scenes.input(Nav::Select, &mut st);
+        assert_eq!(scenes.top(), Some(&Scene::Playing));
+        scenes.update(&mut st);
+        assert_ne!(st.checksum(), before);
+    }
+
+    #[test]
+    fn settings_change_the_next_game() {
+        let (mut scenes, mut st) = new_game();
+        let navs = [
+            Nav::Down,
+            Nav::Down,
+            Nav::Select,
+            Nav::Right,
+            Nav::Right,
+            Nav::Down,
+            Nav::Left,
+        ];
+        for nav in navs.iter() {
+            scenes.input(*nav, &mut st);
+        }
+        assert_eq!(scenes.setup, Setup { players: 3, mode: Mode::Versus });
+        assert!(drawn(&scenes, &st).contains(&"PLAYERS 3".to_string()));
+
+        scenes.input(Nav::Back, &mut st);
+        assert_eq!(scenes.top(), Some(&Scene::Title { selected: 2 }));
+        for nav in [Nav::Up, Nav::Up, Nav::Select].iter() {
+            scenes.input(*nav, &mut st);
+        }
+        assert_eq!(st.players.len(), 3);
+        assert_eq!(st.mode, Mode::Versus);
+    }
+
+    #[test]
+    fn games_end_back_at_the_title() {
+        let (mut scenes, mut st) = new_game();
+        scenes.input(Nav::Select, &mut st);
+        for player in st.players.iter_mut() {
+            player.lives = 0;
+            player.ship = None;
+        }
+
+        scenes.update(&mut st);
+        assert_eq!(scenes.top(), Some(&Scene::GameOver));
+        let texts = drawn(&scenes, &st);
+        assert!(texts.contains(&"GAME OVER".to_string()));
+        assert!(texts.contains(&"PRESS ENTER".to_string()));
+
+        // Nothing scored, so straight to the table.
+        scenes.input(Nav::Select, &mut st);
+        assert_eq!(scenes.top(), Some(&Scene::HighScores { highlight: None }));
+        assert!(st.players.iter().all(|player| player.ship.is_some()));
+        assert_eq!(scenes.scores, HighScores::defaults());
+
+        scenes.input(Nav::Back, &mut st);
+        assert_eq!(scenes.top(), Some(&Scene::Title { selected: 0 }));
+        scenes.input(Nav::Back, &mut st);
+        assert!(scenes.done());
+    }
+
+    #[test]
+    fn high_scores_take_initials() {
+        let (mut scenes, mut st) = new_game();
+        scenes.new_game(&mut st, Setup { players: 2, mode: Mode::Coop });
+        st.players[0].score = 100;
+        st.players[1].score = 9000;
+        for player in st.players.iter_mut() {
+            player.lives = 0;
+            player.ship = None;
+        }
+        scenes.update(&mut st);
+        scenes.input(Nav::Select, &mut st);
+
+        // Only the second player made it, and spells out "BZA".
+        let navs = [Nav::Up, Nav::Select, Nav::Down, Nav::Right];
+        for nav in navs.iter() {
+            scenes.input(*nav, &mut st);
+        }
+        let texts = drawn(&scenes, &st);
+        assert!(texts.contains(&"NEW HIGH SCORE".to_string()));
+        assert!(texts.contains(&"P2 9000".to_string()));
+
+        scenes.input(Nav::Select, &mut st);
+        let table = Scene::HighScores { highlight: Some(0) };
+        assert_eq!(scenes.top(), Some(&table));
+        let entry = Entry { initials: "BZA".to_string(), score: 9000 };
+        assert_eq!(scenes.scores.entries()[0], entry);
+        assert!(drawn(&scenes, &st).iter().any(|text| text.contains("BZA")));
+    }
+
+    #[test]
+    fn high_scores_are_saved_once_entered() {
+        let dir = format!("scene-test-scores-{}", std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("scores.json");
+
+        let (scenes, mut st) = new_game();
+        let mut scenes = scenes.with_scores(path.clone());
+        scenes.new_game(&mut st, Setup { players: 1, mode: Mode::Coop });
+        st.players[0].score = 9000;
+        st.players[0].lives = 0;
+        st.players[0].ship = None;
+        scenes.update(&mut st);
+        scenes.input(Nav::Select, &mut st);
+
+        // Nothing changes until the initials are in.
+        for nav in [Nav::Up, Nav::Right, Nav::Right].iter() {
+            scenes.input(*nav, &mut st);
+        }
+        assert!(!path.exists());
+
+        scenes.input(Nav::Select, &mut st);
+        let saved = HighScores::load(&path).unwrap();
+        assert_eq!(saved, scenes.scores);
+        let entry = Entry { initials: "BAA".to_string(), score: 9000 };
+        assert_eq!(saved.entries()[0], entry);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
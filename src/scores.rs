@@ -0,0 +1,244 @@
+// The high score table, kept between runs in the platform's data directory.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{codec::fnv, files};
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Entries kept in the table.
+pub const TABLE_SIZE: usize = 10;
+
+/// Letters of initials.
+pub const INITIALS: usize = 3;
+
+// Bumped when the file format changes. Older files are thrown away.
+const VERSION: u32 = 1;
+
+// The table a new player starts with, and falls back to when the file is
+// damaged.
+const DEFAULTS: [(&str, u32); TABLE_SIZE] = [
+    ("ACE", 5000),
+    ("ZAP", 4500),
+    ("ROK", 4000),
+    ("VEC", 3500),
+    ("ION", 3000),
+    ("ORB", 2500),
+    ("HEX", 2000),
+    ("SUN", 1500),
+    ("DOT", 1000),
+    ("BIT", 500),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub initials: String,
+    pub score: u32,
+}
+
+/// The best scores, highest first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HighScores {
+    entries: Vec<Entry>,
+}
+
+// What's on disk. The checksum catches files that still parse but were
+// truncated, edited or half written by something else.
+#[derive(Serialize, Deserialize)]
+struct File {
+    version: u32,
+    checksum: u64,
+    entries: Vec<Entry>,
+}
+
+fn checksum(entries: &[Entry]) -> u64 {
+    let mut bytes = Vec::new();
+    for entry in entries {
+        bytes.extend(entry.initials.as_bytes());
+        bytes.extend(&entry.score.to_le_bytes());
+    }
+    fnv(&bytes)
+}
+
+fn valid_initials(initials: &str) -> bool {
+    initials.len() == INITIALS
+        && initials.bytes().all(|c| c.is_ascii_uppercase())
+}
+
+impl HighScores {
+    pub fn defaults() -> Self {
+        let entries = DEFAULTS
+            .iter()
+            .map(|&(initials, score)| Entry {
+                initials: initials.to_string(),
+                score,
+            })
+            .collect();
+        HighScores { entries }
+    }
+
+    /// Where the table is kept, if the platform has a data directory.
+    pub fn path() -> Option<PathBuf> {
+        Some(dirs::data_dir()?.join("vulkano-test").join("scores.json"))
+    }
+
+    /// Reads the table from `path`. A missing file is an empty table, but a
+    /// damaged one is an error.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(HighScores { entries: Vec::new() });
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        let file: File =
+            serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+        if file.version != VERSION {
+            return Err(format!("unknown version {}", file.version));
+        }
+        if file.checksum != checksum(&file.entries) {
+            return Err("checksum mismatch".to_string());
+        }
+        let sorted = file.entries
+            .windows(2)
+            .all(|pair| pair[0].score >= pair[1].score);
+        let initials = file.entries
+            .iter()
+            .all(|entry| valid_initials(&entry.initials));
+        if file.entries.len() > TABLE_SIZE || !sorted || !initials {
+            return Err("invalid entries".to_string());
+        }
+
+        Ok(HighScores { entries: file.entries })
+    }
+
+    /// Like `load`, but falls back to the default table when there isn't a
+    /// file yet or it can't be read.
+    pub fn load_or_default(path: &Path) -> Self {
+        match HighScores::load(path) {
+            Ok(scores) if !scores.entries.is_empty() => scores,
+            Ok(_) => HighScores::defaults(),
+            Err(e) => {
+                println!("Failed to load high scores from {:?}: {}", path, e);
+                HighScores::defaults()
+            }
+        }
+    }
+
+    /// Writes the table to `path`, never leaving half of one.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let file = File {
+            version: VERSION,
+            checksum: checksum(&self.entries),
+            entries: self.entries.clone(),
+        };
+        let json =
+            serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+        files::write_atomically(path, json.as_bytes())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Whether `score` would make it into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < TABLE_SIZE
+                || self.entries.last().is_some_and(|last| score > last.score))
+    }
+
+    /// Adds an entry, below any with the same score, and returns its rank
+    /// if it made it into the table.
+    pub fn insert(&mut self, entry: Entry) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        let rank = self.entries
+            .iter()
+            .position(|other| other.score < entry.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(initials: &str, score: u32) -> Entry {
+        Entry { initials: initials.to_string(), score }
+    }
+
+    // A fresh directory for each test, as they run in parallel.
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = format!("scores-test-{}-{}", name, std::process::id());
+        let dir = std::env::temp_dir().join(dir);
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("scores.json")
+    }
+
+    #[test]
+    fn entries_are_ranked() {
+        let mut scores = HighScores::defaults();
+        assert!(!scores.qualifies(500));
+        assert_eq!(scores.insert(entry("LOW", 100)), None);
+        assert_eq!(scores.insert(entry("TOP", 9000)), Some(0));
+        assert_eq!(scores.insert(entry("TIE", 4000)), Some(4));
+
+        let entries = scores.entries();
+        assert_eq!(entries.len(), TABLE_SIZE);
+        assert_eq!(entries[3].initials, "ROK");
+        assert_eq!(entries[4].initials, "TIE");
+        assert_eq!(entries[TABLE_SIZE - 1].score, 1500);
+    }
+
+    #[test]
+    fn tables_survive_saving() {
+        let path = temp_path("save");
+        let empty = HighScores { entries: Vec::new() };
+        assert_eq!(HighScores::load(&path), Ok(empty));
+        assert_eq!(HighScores::load_or_default(&path), HighScores::defaults());
+
+        let mut scores = HighScores::defaults();
+        scores.insert(entry("NEW", 4200));
+        scores.save(&path).unwrap();
+        scores.save(&path).unwrap();
+        assert_eq!(HighScores::load(&path), Ok(scores));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn damaged_tables_fall_back_to_the_defaults() {
+        let path = temp_path("damaged");
+        let mut scores = HighScores::defaults();
+        scores.insert(entry("NEW", 4200));
+        scores.save(&path).unwrap();
+        let json = fs::read_to_string(&path).unwrap();
+
+        let damaged = [
+            json[..json.len() / 2].to_string(),
+            json.replace("4200", "4300"),
+            json.replace("NEW", "new"),
+            "{}".to_string(),
+        ];
+        for json in damaged.iter() {
+            fs::write(&path, json).unwrap();
+            assert!(HighScores::load(&path).is_err());
+            let loaded = HighScores::load_or_default(&path);
+            assert_eq!(loaded, HighScores::defaults());
+        }
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
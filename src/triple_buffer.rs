@@ -0,0 +1,143 @@
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+// The shared slot holds an index and whether it was written since the
+// reader last took it.
+const INDEX: usize = 0b11;
+const FRESH: usize = 0b100;
+
+struct Shared<T> {
+    slots: [UnsafeCell<T>; 3],
+    middle: AtomicUsize,
+}
+
+// Each slot is only ever accessed by whoever holds its index, and indices
+// change hands through the atomic swap.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// Publishes values to a `Reader` on another thread without either side
+/// ever waiting for the other. The writer fills a back slot and swaps it
+/// with the shared middle one, the reader swaps the middle slot with its
+/// front one whenever there is something newer.
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    back: usize,
+}
+
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    front: usize,
+}
+
+/// Creates a triple buffer, with every slot starting as `initial`.
+pub fn triple_buffer<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        slots: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        middle: AtomicUsize::new(1),
+    });
+
+    let writer = Writer { shared: shared.clone(), back: 0 };
+    let reader = Reader { shared, front: 2 };
+
+    (writer, reader)
+}
+
+impl<T> Writer<T> {
+    /// Lets `write` fill the back slot, then makes it the latest value. The
+    /// slot holds whatever was published a few values ago, so `clone_from`
+    /// can reuse its allocations.
+    pub fn publish<F: FnOnce(&mut T)>(&mut self, write: F) {
+        write(unsafe { &mut *self.shared.slots[self.back].get() });
+
+        let old = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = old & INDEX;
+    }
+}
+
+impl<T> Reader<T> {
+    /// The latest published value. Values published between two reads are
+    /// skipped.
+    pub fn read(&mut self) -> &T {
+        if self.shared.middle.load(Ordering::Relaxed) & FRESH != 0 {
+            let old = self.shared.middle.swap(self.front, Ordering::AcqRel);
+            self.front = old & INDEX;
+        }
+
+        unsafe { &*self.shared.slots[self.front].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::thread;
+
+    #[test]
+    fn reads_what_was_published() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        assert_eq!(*reader.read(), 0);
+
+        writer.publish(|value| *value = 1);
+        assert_eq!(*reader.read(), 1);
+    }
+
+    #[test]
+    fn skips_values_published_between_reads() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        for i in 1..=5 {
+            writer.publish(|value| *value = i);
+        }
+        assert_eq!(*reader.read(), 5);
+    }
+
+    #[test]
+    fn reads_the_same_value_until_a_new_one() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        writer.publish(|value| *value = 1);
+        assert_eq!(*reader.read(), 1);
+        assert_eq!(*reader.read(), 1);
+
+        writer.publish(|value| *value = 2);
+        assert_eq!(*reader.read(), 2);
+        assert_eq!(*reader.read(), 2);
+    }
+
+    #[test]
+    fn never_reads_torn_or_older_values() {
+        const VALUES: u64 = 100_000;
+
+        // Every slot holds a value in each of its elements, so a slot the
+        // writer is still filling would show up as a mix.
+        let (mut writer, mut reader) = triple_buffer([0u64; 16]);
+        let writing = thread::spawn(move || {
+            for i in 1..=VALUES {
+                writer.publish(|value| *value = [i; 16]);
+            }
+        });
+
+        let mut last = 0;
+        while last < VALUES {
+            let value = *reader.read();
+            let whole = value.iter().all(|&v| v == value[0]);
+            assert!(whole, "torn read {:?}", value);
+            assert!(
+                value[0] >= last,
+                "went back from {} to {}",
+                last,
+                value[0],
+            );
+            last = value[0];
+        }
+        writing.join().unwrap();
+    }
+}
@@ -0,0 +1,49 @@
+use vulkano_test::{
+    game::{self, Action, Mode, Setup},
+    net::{
+        self,
+        sim::{Conditions, SimLink},
+        Session,
+    },
+};
+
+// Turns, thrusts and fires in a pattern that differs between players and
+// changes often, so guesses are often wrong.
+fn buttons(player: usize, tick: u32) -> u8 {
+    let phase = tick / (5 + player as u32 * 3);
+    let mut buttons = Action::Fire.bit();
+    if phase % 3 < 2 {
+        buttons |= Action::Thrust.bit();
+    }
+    match phase % 4 {
+        0 => buttons | Action::Left.bit(),
+        2 => buttons | Action::Right.bit(),
+        _ => buttons,
+    }
+}
+
+#[test]
+fn peers_stay_in_sync() {
+    let link = SimLink::new(Conditions { latency: 5, jitter: 3, loss: 0.2 }, 3);
+    let (a, b) = link.ends();
+    let mut ends = [a, b];
+    let setup = Setup { players: net::PLAYERS, mode: Mode::Versus };
+    let mut sessions = [
+        Session::new(game::new_state(None, setup.clone()), 0, net::INPUT_DELAY),
+        Session::new(game::new_state(None, setup), 1, net::INPUT_DELAY),
+    ];
+
+    for tick in 0..900 {
+        let pairs = sessions.iter_mut().zip(ends.iter_mut());
+        for (player, (session, end)) in pairs.enumerate() {
+            if let Err(desync) = session.tick(buttons(player, tick), end) {
+                panic!("player {} desynced at frame {}", player, desync.frame);
+            }
+        }
+        link.tick();
+    }
+
+    // Something happened that could have gone differently.
+    let st = sessions[0].game_mut();
+    assert!(st.players.iter().any(|player| player.score > 0));
+}
@@ -0,0 +1,155 @@
+use vulkano_test::{
+    game::{self, Action, Mode, Setup, State},
+    net::{delta, Client, Game, Refused, Replicated, Server, Udp},
+};
+
+use std::{net::UdpSocket, thread, time::Duration};
+
+fn serve(players: usize) -> Server<State> {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let st = game::new_state(None, Setup { players, mode: Mode::Coop });
+    Server::new(st, socket, players).unwrap()
+}
+
+fn connect(server: &Server<State>) -> (Client<State>, Udp) {
+    let transport = Udp::join(server.local_addr().unwrap()).unwrap();
+    // Nothing like the server's game, so everything shown must have come
+    // from snapshots.
+    let setup = Setup { players: 1, mode: Mode::Versus };
+    let template = game::new_state(None, setup);
+    (Client::new(template), transport)
+}
+
+// Ticks the server and then each client, giving datagrams a moment to
+// cross localhost in between.
+fn run(
+    server: &mut Server<State>,
+    clients: &mut [(Client<State>, Udp)],
+    ticks: u32,
+    buttons: impl Fn(usize, u32) -> u8,
+) {
+    for tick in 0..ticks {
+        server.tick();
+        thread::sleep(Duration::from_millis(1));
+        for (i, (client, transport)) in clients.iter_mut().enumerate() {
+            client.tick(buttons(i, tick), transport).unwrap();
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn speed(st: &State, player: usize) -> f32 {
+    let ship = st.players[player].ship.unwrap();
+    let vel = st.entities.velocities.get(ship).unwrap().0;
+    vel[0].hypot(vel[1])
+}
+
+#[test]
+fn games_survive_encoding() {
+    let setup = Setup { players: 3, mode: Mode::Versus };
+    let mut st = game::new_state(None, setup);
+    for _ in 0..30 {
+        st.advance(&[Action::Fire.bit(), Action::Thrust.bit(), 0], false);
+    }
+    let mut encoded = Vec::new();
+    st.encode(&mut encoded);
+
+    let mut copy = game::new_state(None, Setup::default());
+    assert!(copy.decode(&encoded));
+    assert_eq!(copy.checksum(), st.checksum());
+    assert_eq!(copy.buttons(), st.buttons());
+    let mut again = Vec::new();
+    copy.encode(&mut again);
+    assert_eq!(again, encoded);
+
+    assert!(!copy.decode(&encoded[..encoded.len() - 1]));
+}
+
+#[test]
+fn deltas_are_smaller_than_snapshots() {
+    let mut st = game::new_state(None, Setup::default());
+    let mut before = Vec::new();
+    st.encode(&mut before);
+    st.advance(&[Action::Thrust.bit(), 0], false);
+    let mut after = Vec::new();
+    st.encode(&mut after);
+
+    let delta = delta::diff(&before, &after);
+    assert!(
+        delta.len() * 2 < after.len(),
+        "{} byte delta of {} bytes",
+        delta.len(),
+        after.len(),
+    );
+}
+
+#[test]
+fn clients_play_on_a_server() {
+    let mut server = serve(2);
+    let mut clients = [connect(&server), connect(&server)];
+    let thrust = |i: usize, _| if i == 0 { Action::Thrust.bit() } else { 0 };
+
+    run(&mut server, &mut clients, 30, thrust);
+    let mut players: Vec<usize> =
+        clients.iter().map(|(client, _)| client.player().unwrap()).collect();
+    players.sort_unstable();
+    assert_eq!(players, [0, 1]);
+    assert_eq!(server.clients(), 2);
+
+    // The thrusting ship is ahead of the server, having applied inputs the
+    // server hasn't yet.
+    let (client, _) = &clients[0];
+    let local = client.player().unwrap();
+    let (_, latest) = client.latest().unwrap();
+    let predicted = client.predicted().unwrap();
+    assert!(client.pending() > 0);
+    assert!(speed(predicted, local) > speed(latest, local));
+    assert!(speed(latest, local) > 0.0);
+
+    let mut view = game::new_state(None, Setup::default());
+    let (from, to, t) = client.interpolated().unwrap();
+    game::blend(&mut view, from, to, t, predicted, local);
+    let ship = predicted.players[local].ship.unwrap();
+    assert_eq!(
+        view.entities.transforms.get(ship).unwrap().pos,
+        predicted.entities.transforms.get(ship).unwrap().pos,
+    );
+
+    // Once the inputs stop, the server catches up with the prediction and
+    // the clients with the server.
+    run(&mut server, &mut clients, 60, |_, _| 0);
+    for _ in 0..5 {
+        thread::sleep(Duration::from_millis(2));
+        for (client, transport) in clients.iter_mut() {
+            client.tick(0, transport).unwrap();
+        }
+    }
+    for (client, _) in clients.iter() {
+        let (tick, latest) = client.latest().unwrap();
+        assert_eq!(tick, server.tick_count());
+        assert_eq!(latest.checksum(), server.game().checksum());
+        assert_eq!(latest.players.len(), 2);
+    }
+}
+
+#[test]
+fn full_servers_turn_clients_away() {
+    let mut server = serve(1);
+    let (mut first, mut first_transport) = connect(&server);
+    let (mut second, mut second_transport) = connect(&server);
+
+    let mut refused = None;
+    for _ in 0..50 {
+        server.tick();
+        thread::sleep(Duration::from_millis(1));
+        first.tick(0, &mut first_transport).unwrap();
+        if let Err(e) = second.tick(0, &mut second_transport) {
+            refused = Some(e);
+            break;
+        }
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    assert_eq!(refused, Some(Refused::Full));
+    assert_eq!(first.player(), Some(0));
+}